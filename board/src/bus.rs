@@ -0,0 +1,198 @@
+//! The sensor bus, which gets itself going again after a transfer broke off.
+//!
+//! A transfer that the other side did not acknowledge leaves the peripheral in a usable state and
+//! is worth retrying as it is. Anything else can leave it stuck half way through, so [`I2c`]
+//! disables and enables the peripheral again before it reports the failure. The pins stay
+//! selected while it is disabled, so this needs nothing the driver gave away.
+//!
+//! The v2 board has a second bus on the edge connector, see [`crate::board_i2c_external!`]. On
+//! the v1 board the edge connector is wired to the sensor bus.
+use crate::error::{Bus, Error};
+use core::cell::RefCell;
+use embedded_hal::blocking::i2c::{Read, Write, WriteRead};
+
+#[cfg(feature = "v1")]
+use microbit::{
+    hal::twi::{self as hal, Twi as Driver},
+    pac::{twi0::frequency::FREQUENCY_A, twi0::RegisterBlock, TWI0 as Internal},
+};
+
+#[cfg(feature = "v2")]
+use microbit::{
+    board::I2CExternalPins,
+    hal::twim::{self as hal, Twim as Driver},
+    pac::{twim0::frequency::FREQUENCY_A, twim0::RegisterBlock, TWIM0 as Internal, TWIM1},
+};
+
+/// The peripherals an [`I2c`] runs on.
+pub trait Instance: hal::Instance {
+    /// The registers of the peripheral, for starting over. Only an [`I2c`] that owns the
+    /// peripheral uses them.
+    fn registers() -> &'static RegisterBlock;
+}
+
+macro_rules! instances {
+    ($($peripheral:ty),*) => {
+        $(
+            impl Instance for $peripheral {
+                fn registers() -> &'static RegisterBlock {
+                    #[allow(unsafe_code)]
+                    unsafe {
+                        &*<$peripheral>::ptr()
+                    }
+                }
+            }
+        )*
+    };
+}
+
+#[cfg(feature = "v1")]
+instances!(Internal);
+
+#[cfg(feature = "v2")]
+instances!(Internal, TWIM1);
+
+/// An I2C bus, by default the one the LSM303AGR is connected to, see [`crate::board_i2c!`].
+pub struct I2c<T: Instance = Internal> {
+    driver: Driver<T>,
+    resets: u32,
+}
+
+impl<T: Instance> I2c<T> {
+    /// Sets up the bus at 100kHz.
+    pub fn new(peripheral: T, pins: hal::Pins) -> Self {
+        I2c {
+            driver: Driver::new(peripheral, pins, FREQUENCY_A::K100),
+            resets: 0,
+        }
+    }
+
+    /// How many times a failed transfer made the bus start over.
+    pub fn resets(&self) -> u32 {
+        self.resets
+    }
+
+    /// Reports `error`, after starting over unless a retry might do.
+    fn failed(&mut self, error: Bus) -> Bus {
+        if error != Bus::Nack {
+            let registers = T::registers();
+            registers.enable.write(|w| w.enable().disabled());
+            registers.enable.write(|w| w.enable().enabled());
+            self.resets = self.resets.wrapping_add(1);
+        }
+        error
+    }
+}
+
+/// Sets up the bus on the edge connector, see [`crate::board_i2c_external!`].
+#[cfg(feature = "v2")]
+pub fn i2c_external(pins: I2CExternalPins) -> I2c<TWIM1> {
+    // `microbit::Board` leaves out TWIM1, and SPIM1, SPIS1 and TWIS1 which share its registers.
+    // Its pins only come out of the board once, so this is the only bus using them.
+    #[allow(unsafe_code)]
+    let twim1 = unsafe { microbit::pac::Peripherals::steal() }.TWIM1;
+    I2c::new(twim1, pins.into())
+}
+
+/// The nRF51 does not tell a missing acknowledgement apart from other failures.
+#[cfg(feature = "v1")]
+impl From<hal::Error> for Bus {
+    fn from(_: hal::Error) -> Self {
+        Bus::Failed
+    }
+}
+
+#[cfg(feature = "v2")]
+impl From<hal::Error> for Bus {
+    fn from(error: hal::Error) -> Self {
+        match error {
+            hal::Error::AddressNack | hal::Error::DataNack => Bus::Nack,
+            _ => Bus::Failed,
+        }
+    }
+}
+
+impl<T: Instance> Write for I2c<T> {
+    type Error = Bus;
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Bus> {
+        let result = self.driver.write(address, bytes);
+        result.map_err(|error| self.failed(error.into()))
+    }
+}
+
+impl<T: Instance> Read for I2c<T> {
+    type Error = Bus;
+
+    fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), Bus> {
+        let result = self.driver.read(address, buffer);
+        result.map_err(|error| self.failed(error.into()))
+    }
+}
+
+impl<T: Instance> WriteRead for I2c<T> {
+    type Error = Bus;
+
+    fn write_read(&mut self, address: u8, bytes: &[u8], buffer: &mut [u8]) -> Result<(), Bus> {
+        let result = self.driver.write_read(address, bytes, buffer);
+        result.map_err(|error| self.failed(error.into()))
+    }
+}
+
+/// A handle on an [`I2c`] which can be copied, so that a driver can keep one while the
+/// application talks to the same bus with another.
+pub struct SharedI2c<'a, T: Instance = Internal>(&'a RefCell<I2c<T>>);
+
+impl<'a, T: Instance> SharedI2c<'a, T> {
+    pub fn new(bus: &'a RefCell<I2c<T>>) -> Self {
+        SharedI2c(bus)
+    }
+
+    pub fn resets(&self) -> u32 {
+        self.0.borrow().resets()
+    }
+}
+
+impl<T: Instance> Clone for SharedI2c<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: Instance> Copy for SharedI2c<'_, T> {}
+
+// Transfers run to the end before they return, so the bus is never borrowed twice
+
+impl<T: Instance> Write for SharedI2c<'_, T> {
+    type Error = Bus;
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Bus> {
+        self.0.borrow_mut().write(address, bytes)
+    }
+}
+
+impl<T: Instance> Read for SharedI2c<'_, T> {
+    type Error = Bus;
+
+    fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), Bus> {
+        self.0.borrow_mut().read(address, buffer)
+    }
+}
+
+impl<T: Instance> WriteRead for SharedI2c<'_, T> {
+    type Error = Bus;
+
+    fn write_read(&mut self, address: u8, bytes: &[u8], buffer: &mut [u8]) -> Result<(), Bus> {
+        self.0.borrow_mut().write_read(address, bytes, buffer)
+    }
+}
+
+/// Reads a single register of the device at `address`.
+pub(crate) fn read_register<B>(bus: &mut B, address: u8, register: u8) -> Result<u8, Error>
+where
+    B: WriteRead<Error = Bus>,
+{
+    let mut value = [0];
+    bus.write_read(address, &[register], &mut value)?;
+    Ok(value[0])
+}
@@ -0,0 +1,308 @@
+//! What goes wrong on the board, and what the applications do about it instead of panicking.
+//!
+//! Every [`Error`] has a [`Policy`]: a transfer the sensor did not acknowledge is retried, a bus
+//! that broke off a transfer has already started over (see [`crate::I2c`]) and the sensor gets
+//! its settings again, everything else is reported over the serial port or RTT. [`Counters`] keep
+//! track of all of it, and [`Counters::recover`] follows the policies for an operation.
+use core::fmt;
+use core::ops::Add;
+use protocol::message;
+
+/// How many times a transfer that was not acknowledged is tried again.
+pub const RETRIES: u32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// A transfer on the sensor bus failed.
+    Bus(Bus),
+    /// The sensor does not support what it was asked to do.
+    Sensor,
+    /// Received bytes were lost, see [`crate::Stats`].
+    Serial,
+    /// A message from the host could not be read.
+    Protocol(message::Error),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bus {
+    /// Nobody acknowledged the address or the data.
+    Nack,
+    /// The transfer broke off.
+    Failed,
+}
+
+/// What to do about an [`Error`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Policy {
+    /// Try again as it is, up to [`RETRIES`] times.
+    Retry,
+    /// Set the sensor up again and try once more.
+    Reinit,
+    /// Give up and tell the user.
+    Report,
+}
+
+impl Error {
+    pub fn policy(self) -> Policy {
+        match self {
+            Error::Bus(Bus::Nack) => Policy::Retry,
+            Error::Bus(Bus::Failed) => Policy::Reinit,
+            Error::Sensor | Error::Serial | Error::Protocol(_) => Policy::Report,
+        }
+    }
+
+    /// What to tell the user.
+    pub fn reason(self) -> &'static str {
+        match self {
+            Error::Bus(Bus::Nack) => "sensor not responding",
+            Error::Bus(Bus::Failed) => "sensor bus failed",
+            Error::Sensor => "sensor does not support that",
+            Error::Serial => "bytes lost on the serial port",
+            Error::Protocol(_) => "request not understood",
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.reason())
+    }
+}
+
+impl From<Bus> for Error {
+    fn from(error: Bus) -> Self {
+        Error::Bus(error)
+    }
+}
+
+impl<PinE> From<lsm303agr::Error<Bus, PinE>> for Error {
+    fn from(error: lsm303agr::Error<Bus, PinE>) -> Self {
+        match error {
+            lsm303agr::Error::Comm(error) => Error::Bus(error),
+            lsm303agr::Error::Pin(_) | lsm303agr::Error::InvalidInputData => Error::Sensor,
+        }
+    }
+}
+
+impl From<message::Error> for Error {
+    fn from(error: message::Error) -> Self {
+        Error::Protocol(error)
+    }
+}
+
+impl From<Error> for shell::Error<'static> {
+    fn from(error: Error) -> Self {
+        shell::Error::Failed(error.reason())
+    }
+}
+
+/// How often each kind of [`Error`] happened, and what was done about them.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Counters {
+    pub bus: u32,
+    pub sensor: u32,
+    pub serial: u32,
+    pub protocol: u32,
+    /// Operations tried again after a [`Policy::Retry`].
+    pub retries: u32,
+    /// Times the sensor was set up again after a [`Policy::Reinit`].
+    pub reinits: u32,
+}
+
+impl Counters {
+    pub fn count(&mut self, error: Error) {
+        let counter = match error {
+            Error::Bus(_) => &mut self.bus,
+            Error::Sensor => &mut self.sensor,
+            Error::Serial => &mut self.serial,
+            Error::Protocol(_) => &mut self.protocol,
+        };
+        *counter = counter.wrapping_add(1);
+    }
+
+    /// Runs `op` on `target` until it succeeds or an error has to be reported, which it returns.
+    ///
+    /// Errors to retry are, up to [`RETRIES`] times. After those, or after an error that calls for
+    /// it, `reinit` sets the target up again once and `op` gets another go. Every error is
+    /// counted along the way.
+    pub fn recover<D, T>(
+        &mut self,
+        target: &mut D,
+        mut op: impl FnMut(&mut D) -> Result<T, Error>,
+        mut reinit: impl FnMut(&mut D) -> Result<(), Error>,
+    ) -> Result<T, Error> {
+        let mut retries = 0;
+        let mut reinitialised = false;
+        loop {
+            let error = match op(target) {
+                Ok(value) => return Ok(value),
+                Err(error) => error,
+            };
+            self.count(error);
+            match error.policy() {
+                Policy::Retry if retries < RETRIES => {
+                    retries += 1;
+                    self.retries = self.retries.wrapping_add(1);
+                }
+                Policy::Retry | Policy::Reinit if !reinitialised => {
+                    reinitialised = true;
+                    self.reinits = self.reinits.wrapping_add(1);
+                    if let Err(error) = reinit(target) {
+                        self.count(error);
+                        return Err(error);
+                    }
+                }
+                _ => return Err(error),
+            }
+        }
+    }
+}
+
+/// Counters kept in different places, together.
+impl Add for Counters {
+    type Output = Counters;
+
+    fn add(self, other: Counters) -> Counters {
+        Counters {
+            bus: self.bus.wrapping_add(other.bus),
+            sensor: self.sensor.wrapping_add(other.sensor),
+            serial: self.serial.wrapping_add(other.serial),
+            protocol: self.protocol.wrapping_add(other.protocol),
+            retries: self.retries.wrapping_add(other.retries),
+            reinits: self.reinits.wrapping_add(other.reinits),
+        }
+    }
+}
+
+impl fmt::Display for Counters {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "bus {} sensor {} serial {} protocol {} retries {} reinits {}",
+            self.bus, self.sensor, self.serial, self.protocol, self.retries, self.reinits
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stands in for the sensor: fails with the errors it was given, one per call, then succeeds.
+    #[derive(Default)]
+    struct Fake {
+        errors: Vec<Error>,
+        calls: usize,
+        reinits: usize,
+        reinit_error: Option<Error>,
+    }
+
+    impl Fake {
+        fn failing(errors: &[Error]) -> Self {
+            Fake {
+                errors: errors.to_vec(),
+                ..Fake::default()
+            }
+        }
+    }
+
+    fn op(fake: &mut Fake) -> Result<u8, Error> {
+        fake.calls += 1;
+        match fake.errors.get(fake.calls - 1) {
+            Some(&error) => Err(error),
+            None => Ok(42),
+        }
+    }
+
+    fn reinit(fake: &mut Fake) -> Result<(), Error> {
+        fake.reinits += 1;
+        fake.reinit_error.map_or(Ok(()), Err)
+    }
+
+    const NACK: Error = Error::Bus(Bus::Nack);
+    const FAILED: Error = Error::Bus(Bus::Failed);
+
+    #[test]
+    fn retries_until_it_succeeds() {
+        let mut counters = Counters::default();
+        let mut fake = Fake::failing(&[NACK, NACK]);
+        assert_eq!(counters.recover(&mut fake, op, reinit), Ok(42));
+        assert_eq!((fake.calls, fake.reinits), (3, 0));
+        assert_eq!(
+            counters,
+            Counters {
+                bus: 2,
+                retries: 2,
+                ..Counters::default()
+            }
+        );
+    }
+
+    #[test]
+    fn sets_up_again_once_the_retries_are_used_up() {
+        let mut counters = Counters::default();
+        let mut fake = Fake::failing(&[NACK; RETRIES as usize + 1]);
+        assert_eq!(counters.recover(&mut fake, op, reinit), Ok(42));
+        assert_eq!((fake.calls, fake.reinits), (RETRIES as usize + 2, 1));
+        assert_eq!(
+            counters,
+            Counters {
+                bus: RETRIES + 1,
+                retries: RETRIES,
+                reinits: 1,
+                ..Counters::default()
+            }
+        );
+    }
+
+    #[test]
+    fn sets_up_again_only_once() {
+        let mut counters = Counters::default();
+        let mut fake = Fake::failing(&[FAILED, FAILED]);
+        assert_eq!(counters.recover(&mut fake, op, reinit), Err(FAILED));
+        assert_eq!((fake.calls, fake.reinits), (2, 1));
+        assert_eq!(
+            counters,
+            Counters {
+                bus: 2,
+                reinits: 1,
+                ..Counters::default()
+            }
+        );
+    }
+
+    #[test]
+    fn reports_a_failed_set_up() {
+        let mut counters = Counters::default();
+        let mut fake = Fake {
+            reinit_error: Some(Error::Sensor),
+            ..Fake::failing(&[FAILED])
+        };
+        assert_eq!(counters.recover(&mut fake, op, reinit), Err(Error::Sensor));
+        assert_eq!((fake.calls, fake.reinits), (1, 1));
+        assert_eq!(
+            counters,
+            Counters {
+                bus: 1,
+                sensor: 1,
+                reinits: 1,
+                ..Counters::default()
+            }
+        );
+    }
+
+    #[test]
+    fn reports_right_away() {
+        let mut counters = Counters::default();
+        let mut fake = Fake::failing(&[Error::Serial]);
+        assert_eq!(counters.recover(&mut fake, op, reinit), Err(Error::Serial));
+        assert_eq!((fake.calls, fake.reinits), (1, 0));
+        assert_eq!(
+            counters,
+            Counters {
+                serial: 1,
+                ..Counters::default()
+            }
+        );
+    }
+}
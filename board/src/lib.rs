@@ -0,0 +1,140 @@
+//! The parts of the micro:bit the applications in this workspace share, set up the same way on
+//! both board revisions.
+//!
+//! The v1 and v2 boards name their peripherals differently (`TWI0` vs `TWIM0`, `UART0` vs
+//! `UARTE0`) and the HAL drivers for them differ as well. Enable the `v1` or `v2` feature to match
+//! the board and use [`board_i2c!`] and [`board_serial!`] to take the peripherals out of a
+//! `microbit::Board`:
+//!
+//! ```ignore
+//! let board = microbit::Board::take().unwrap();
+//! let i2c = board::board_i2c!(board);
+//! let mut serial = board::board_serial!(board);
+//! ```
+//!
+//! Those are macros rather than functions so that they only move the fields they need out of the
+//! board, the rest stays available to the application.
+//!
+//! The applications handle what goes wrong on the board through [`error`] instead of panicking, and
+//! check the sensor at boot with [`selftest`]. [`fifo`] reads the accelerometer in bursts, which
+//! the sensor driver has no support for, and [`wakeup`] sleeps until it feels a jolt.
+//!
+//! Without a board feature only [`error`] is built, which is enough to test it on the host with
+//! `cargo test -p board`.
+#![cfg_attr(not(test), no_std)]
+#![deny(unsafe_code)]
+
+#[cfg(any(feature = "v1", feature = "v2"))]
+mod bus;
+pub mod error;
+#[cfg(any(feature = "v1", feature = "v2"))]
+pub mod fifo;
+#[cfg(any(feature = "v1", feature = "v2"))]
+pub mod selftest;
+#[cfg(any(feature = "v1", feature = "v2"))]
+mod serial;
+#[cfg(any(feature = "v1", feature = "v2"))]
+pub mod wakeup;
+
+#[cfg(feature = "v2")]
+pub use bus::i2c_external;
+#[cfg(any(feature = "v1", feature = "v2"))]
+pub use bus::{I2c, Instance, SharedI2c};
+#[cfg(any(feature = "v1", feature = "v2"))]
+pub use serial::{Buffers, Error, Serial, Stats, DEFAULT_CAPACITY};
+#[cfg(any(feature = "v1", feature = "v2"))]
+pub use wakeup::Wakeup;
+
+#[doc(hidden)]
+pub use cortex_m;
+
+/// Takes the internal I2C bus out of a `microbit::Board`, returning an [`I2c`].
+#[cfg(feature = "v1")]
+#[macro_export]
+macro_rules! board_i2c {
+    ($board:ident) => {
+        $crate::I2c::new($board.TWI0, $board.i2c.into())
+    };
+}
+
+/// Takes the internal I2C bus out of a `microbit::Board`, returning an [`I2c`].
+#[cfg(feature = "v2")]
+#[macro_export]
+macro_rules! board_i2c {
+    ($board:ident) => {
+        $crate::I2c::new($board.TWIM0, $board.i2c_internal.into())
+    };
+}
+
+/// Takes the I2C bus on the edge connector out of a `microbit::Board`, returning an
+/// `I2c<TWIM1>`. Only the v2 board has one, the v1 board wires the edge connector to the bus of
+/// [`board_i2c!`].
+#[cfg(feature = "v2")]
+#[macro_export]
+macro_rules! board_i2c_external {
+    ($board:ident) => {
+        $crate::i2c_external($board.i2c_external)
+    };
+}
+
+/// Takes GPIOTE and the accelerometer interrupt pin out of a `microbit::Board`, returning a
+/// [`Wakeup`]. The pin is driven both ways, so it floats.
+#[cfg(feature = "v1")]
+#[macro_export]
+macro_rules! board_wakeup {
+    ($board:ident) => {
+        $crate::Wakeup::new(
+            $board.GPIOTE,
+            $board.pins.p0_28.into_floating_input().degrade(),
+        )
+    };
+}
+
+/// Takes GPIOTE and the interrupt pin of the sensor bus out of a `microbit::Board`, returning a
+/// [`Wakeup`]. The accelerometer drives the pin both ways, so it floats.
+#[cfg(feature = "v2")]
+#[macro_export]
+macro_rules! board_wakeup {
+    ($board:ident) => {
+        $crate::Wakeup::new(
+            $board.GPIOTE,
+            $board.pins.p0_25.into_floating_input().degrade(),
+        )
+    };
+}
+
+/// Takes the serial port connected to the USB interface chip out of a `microbit::Board`,
+/// returning a [`Serial`] running at 115200 baud. The sizes of the receive and transmit ring
+/// buffers can be given after the board, they default to [`DEFAULT_CAPACITY`]. Can only be used
+/// once.
+#[cfg(feature = "v1")]
+#[macro_export]
+macro_rules! board_serial {
+    ($board:ident) => {
+        $crate::board_serial!($board, $crate::DEFAULT_CAPACITY, $crate::DEFAULT_CAPACITY)
+    };
+    ($board:ident, $rx:expr, $tx:expr) => {{
+        let buffers = $crate::cortex_m::singleton!(
+            : $crate::Buffers<{ $rx }, { $tx }> = $crate::Buffers::new()
+        );
+        $crate::Serial::new($board.UART0, $board.uart.into(), buffers.unwrap())
+    }};
+}
+
+/// Takes the serial port connected to the USB interface chip out of a `microbit::Board`,
+/// returning a [`Serial`] running at 115200 baud. The sizes of the receive and transmit ring
+/// buffers can be given after the board, they default to [`DEFAULT_CAPACITY`]. Can only be used
+/// once.
+#[cfg(feature = "v2")]
+#[macro_export]
+macro_rules! board_serial {
+    ($board:ident) => {
+        $crate::board_serial!($board, $crate::DEFAULT_CAPACITY, $crate::DEFAULT_CAPACITY)
+    };
+    ($board:ident, $rx:expr, $tx:expr) => {{
+        let buffers = $crate::cortex_m::singleton!(
+            : $crate::Buffers<{ $rx }, { $tx }> = $crate::Buffers::new()
+        );
+        $crate::Serial::new($board.UARTE0, $board.uart.into(), buffers.unwrap())
+    }};
+}
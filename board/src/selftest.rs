@@ -0,0 +1,349 @@
+//! The self-test of the LSM303AGR, to tell a faulty sensor from one that only needs calibrating.
+//!
+//! Both parts of the sensor can apply a known force or field to themselves. [`run`] measures with
+//! and without it following the procedure in the datasheet, and checks that the output moved by
+//! as much as the datasheet says it should, on every axis. It also checks the WHO_AM_I registers.
+//!
+//! The test talks to the registers directly, so it does not need a driver and runs before one is
+//! set up. It puts back every register it changes, so a driver that already exists is not
+//! disturbed either.
+use crate::bus::read_register;
+use crate::error::{Bus, Error};
+use core::fmt;
+use embedded_hal::blocking::i2c::{Write, WriteRead};
+
+pub const ACCELEROMETER_ADDR: u8 = 0b0011001;
+pub const MAGNETOMETER_ADDR: u8 = 0b0011110;
+
+pub const ACCELEROMETER_ID_REG: u8 = 0x0f;
+pub const MAGNETOMETER_ID_REG: u8 = 0x4f;
+
+pub const ACCELEROMETER_ID: u8 = 0x33;
+pub const MAGNETOMETER_ID: u8 = 0x40;
+
+const CTRL_REG1_A: u8 = 0x20;
+const CTRL_REG4_A: u8 = 0x23;
+const STATUS_REG_A: u8 = 0x27;
+const OUT_X_L_A: u8 = 0x28;
+const CFG_REG_A_M: u8 = 0x60;
+const CFG_REG_C_M: u8 = 0x62;
+const STATUS_REG_M: u8 = 0x67;
+const OUTX_L_REG_M: u8 = 0x68;
+
+/// Reading several accelerometer registers at once needs this bit in the first one.
+const AUTO_INCREMENT: u8 = 0x80;
+/// New data on all three axes, in both status registers.
+const ZYXDA: u8 = 1 << 3;
+
+/// In CTRL_REG1_A, 100Hz in normal mode.
+const ACCEL_ODR_100HZ: u8 = 0b0101 << 4;
+/// In CTRL_REG1_A, all three axes on.
+const XYZ_EN: u8 = 0b111;
+/// In CTRL_REG4_A, block data update and self-test 0.
+const BDU_A: u8 = 1 << 7;
+const SELF_TEST_0_A: u8 = 1 << 1;
+
+/// In CFG_REG_A_M, temperature compensation, which the datasheet says has to be on.
+const COMP_TEMP_EN: u8 = 1 << 7;
+/// In CFG_REG_A_M, 100Hz in continuous mode.
+const MAG_ODR_100HZ: u8 = 0b11 << 2;
+/// In CFG_REG_B_M, offset cancellation.
+const OFF_CANC: u8 = 1 << 1;
+/// In CFG_REG_C_M, block data update and the self-test.
+const BDU_M: u8 = 1 << 4;
+const SELF_TEST_M: u8 = 1 << 1;
+
+/// How often to look for a new sample before giving up, a few seconds at 100kHz.
+const POLLS: u32 = 10_000;
+
+/// Both parts run at 100Hz during the test, so waiting for the output to settle means skipping
+/// samples. The datasheet waits 90ms after switching the accelerometer, then drops one more sample
+/// and averages five.
+const ACCEL_STEPS: Steps = Steps {
+    settle: 10,
+    average: 5,
+};
+/// 20ms and 60ms for the magnetometer, rounded up to the 60ms, and fifty samples.
+const MAG_STEPS: Steps = Steps {
+    settle: 7,
+    average: 50,
+};
+
+struct Steps {
+    settle: u32,
+    average: i32,
+}
+
+/// The accelerometer in normal mode at ±2g has 4mg per digit, the datasheet allows a change of
+/// 17 to 360 of them.
+const ACCEL_LIMITS: (i32, i32) = (17 * 4, 360 * 4);
+/// The magnetometer has 1.5mG per digit, and may change by 15 to 500 of them.
+const MAG_LIMITS: (i32, i32) = (15 * 3 / 2, 500 * 3 / 2);
+
+/// The result of the test of one part of the sensor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Part {
+    pub name: &'static str,
+    /// What the WHO_AM_I register holds.
+    pub id: Result<u8, Error>,
+    pub expected_id: u8,
+    /// How much the self-test moved each axis, in `unit`.
+    pub change: Result<[i32; 3], Error>,
+    /// The smallest and largest change the datasheet allows, in `unit`.
+    pub limits: (i32, i32),
+    pub unit: &'static str,
+}
+
+impl Part {
+    pub fn id_ok(&self) -> bool {
+        self.id == Ok(self.expected_id)
+    }
+
+    pub fn change_ok(&self) -> bool {
+        let (min, max) = self.limits;
+        match self.change {
+            Ok(change) => change.iter().all(|axis| (min..=max).contains(&axis.abs())),
+            Err(_) => false,
+        }
+    }
+
+    pub fn passed(&self) -> bool {
+        self.id_ok() && self.change_ok()
+    }
+}
+
+impl fmt::Display for Part {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: id ", self.name)?;
+        match self.id {
+            Ok(id) => write!(f, "0x{:02x}", id)?,
+            Err(error) => write!(f, "unknown, {}", error)?,
+        }
+        write!(f, " {}, self-test ", verdict(self.id_ok()))?;
+        match self.change {
+            Ok([x, y, z]) => write!(f, "x {} y {} z {} {}", x, y, z, self.unit)?,
+            Err(error) => write!(f, "not run, {}", error)?,
+        }
+        write!(f, " {}", verdict(self.change_ok()))
+    }
+}
+
+fn verdict(ok: bool) -> &'static str {
+    if ok {
+        "pass"
+    } else {
+        "FAIL"
+    }
+}
+
+/// What [`run`] found, a line per part and one with the verdict.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Report {
+    pub accelerometer: Part,
+    pub magnetometer: Part,
+}
+
+impl Report {
+    pub fn passed(&self) -> bool {
+        self.accelerometer.passed() && self.magnetometer.passed()
+    }
+
+    /// A tick or a cross for the LED matrix, with LEDs either off (0) or on (1).
+    pub fn image(&self) -> [[u8; 5]; 5] {
+        if self.passed() {
+            [
+                [0, 0, 0, 0, 0],
+                [0, 0, 0, 0, 1],
+                [0, 0, 0, 1, 0],
+                [1, 0, 1, 0, 0],
+                [0, 1, 0, 0, 0],
+            ]
+        } else {
+            [
+                [1, 0, 0, 0, 1],
+                [0, 1, 0, 1, 0],
+                [0, 0, 1, 0, 0],
+                [0, 1, 0, 1, 0],
+                [1, 0, 0, 0, 1],
+            ]
+        }
+    }
+}
+
+/// The lines are separated by `\r\n`, with none after the last one.
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}\r\n{}\r\n", self.accelerometer, self.magnetometer)?;
+        write!(
+            f,
+            "selftest {}",
+            if self.passed() { "passed" } else { "FAILED" }
+        )
+    }
+}
+
+/// Tests both parts of the sensor on `bus`, which takes about a second.
+pub fn run<B>(bus: &mut B) -> Report
+where
+    B: Write<Error = Bus> + WriteRead<Error = Bus>,
+{
+    Report {
+        accelerometer: Part {
+            name: "accelerometer",
+            id: read_register(bus, ACCELEROMETER_ADDR, ACCELEROMETER_ID_REG),
+            expected_id: ACCELEROMETER_ID,
+            change: accel_change(bus),
+            limits: ACCEL_LIMITS,
+            unit: "mg",
+        },
+        magnetometer: Part {
+            name: "magnetometer",
+            id: read_register(bus, MAGNETOMETER_ADDR, MAGNETOMETER_ID_REG),
+            expected_id: MAGNETOMETER_ID,
+            change: mag_change(bus),
+            limits: MAG_LIMITS,
+            unit: "mG",
+        },
+    }
+}
+
+fn accel_change<B>(bus: &mut B) -> Result<[i32; 3], Error>
+where
+    B: Write<Error = Bus> + WriteRead<Error = Bus>,
+{
+    // CTRL_REG1_A to CTRL_REG4_A
+    let mut saved = [0; 4];
+    bus.write_read(
+        ACCELEROMETER_ADDR,
+        &[CTRL_REG1_A | AUTO_INCREMENT],
+        &mut saved,
+    )?;
+
+    let result = accel_test(bus);
+    let [reg1, reg2, reg3, reg4] = saved;
+    bus.write(
+        ACCELEROMETER_ADDR,
+        &[CTRL_REG1_A | AUTO_INCREMENT, reg1, reg2, reg3, reg4],
+    )?;
+    result
+}
+
+fn mag_change<B>(bus: &mut B) -> Result<[i32; 3], Error>
+where
+    B: Write<Error = Bus> + WriteRead<Error = Bus>,
+{
+    // CFG_REG_A_M to CFG_REG_C_M, the magnetometer moves on to the next register by itself
+    let mut saved = [0; 3];
+    bus.write_read(MAGNETOMETER_ADDR, &[CFG_REG_A_M], &mut saved)?;
+
+    let result = mag_test(bus);
+    let [a, b, c] = saved;
+    bus.write(MAGNETOMETER_ADDR, &[CFG_REG_A_M, a, b, c])?;
+    result
+}
+
+fn accel_test<B>(bus: &mut B) -> Result<[i32; 3], Error>
+where
+    B: Write<Error = Bus> + WriteRead<Error = Bus>,
+{
+    // 100Hz on all axes in normal mode at ±2g with block data update, without filters or
+    // interrupts
+    let normal = [
+        CTRL_REG1_A | AUTO_INCREMENT,
+        ACCEL_ODR_100HZ | XYZ_EN,
+        0x00,
+        0x00,
+        BDU_A,
+    ];
+    bus.write(ACCELEROMETER_ADDR, &normal)?;
+    let without = average(bus, accel_sample, ACCEL_STEPS)?;
+    // Self-test 0
+    bus.write(ACCELEROMETER_ADDR, &[CTRL_REG4_A, BDU_A | SELF_TEST_0_A])?;
+    let with = average(bus, accel_sample, ACCEL_STEPS)?;
+    Ok(difference(with, without))
+}
+
+fn mag_test<B>(bus: &mut B) -> Result<[i32; 3], Error>
+where
+    B: Write<Error = Bus> + WriteRead<Error = Bus>,
+{
+    // Continuous mode at 100Hz with temperature compensation, offset cancellation and block data
+    // update
+    let normal = [CFG_REG_A_M, COMP_TEMP_EN | MAG_ODR_100HZ, OFF_CANC, BDU_M];
+    bus.write(MAGNETOMETER_ADDR, &normal)?;
+    let without = average(bus, mag_sample, MAG_STEPS)?;
+    bus.write(MAGNETOMETER_ADDR, &[CFG_REG_C_M, BDU_M | SELF_TEST_M])?;
+    let with = average(bus, mag_sample, MAG_STEPS)?;
+    Ok(difference(with, without))
+}
+
+/// Skips the samples to settle, then averages the ones after them.
+fn average<B, F>(bus: &mut B, mut sample: F, steps: Steps) -> Result<[i32; 3], Error>
+where
+    F: FnMut(&mut B) -> Result<[i32; 3], Error>,
+{
+    for _ in 0..steps.settle {
+        sample(bus)?;
+    }
+    let mut sum = [0; 3];
+    for _ in 0..steps.average {
+        for (sum, axis) in sum.iter_mut().zip(sample(bus)?) {
+            *sum += axis;
+        }
+    }
+    Ok(sum.map(|sum| sum / steps.average))
+}
+
+fn difference(with: [i32; 3], without: [i32; 3]) -> [i32; 3] {
+    [
+        with[0] - without[0],
+        with[1] - without[1],
+        with[2] - without[2],
+    ]
+}
+
+/// The next accelerometer sample in mg, for normal mode at ±2g.
+fn accel_sample<B>(bus: &mut B) -> Result<[i32; 3], Error>
+where
+    B: Write<Error = Bus> + WriteRead<Error = Bus>,
+{
+    let raw = next_sample(
+        bus,
+        ACCELEROMETER_ADDR,
+        STATUS_REG_A,
+        OUT_X_L_A | AUTO_INCREMENT,
+    )?;
+    // Left justified, with 10 bits in normal mode
+    Ok(raw.map(|axis| (axis >> 6) * 4))
+}
+
+/// The next magnetometer sample in mG.
+fn mag_sample<B>(bus: &mut B) -> Result<[i32; 3], Error>
+where
+    B: Write<Error = Bus> + WriteRead<Error = Bus>,
+{
+    let raw = next_sample(bus, MAGNETOMETER_ADDR, STATUS_REG_M, OUTX_L_REG_M)?;
+    Ok(raw.map(|axis| axis * 3 / 2))
+}
+
+/// Waits for new data on all axes and reads them, as they come out of the registers.
+fn next_sample<B>(bus: &mut B, address: u8, status: u8, output: u8) -> Result<[i32; 3], Error>
+where
+    B: Write<Error = Bus> + WriteRead<Error = Bus>,
+{
+    let mut ready = false;
+    for _ in 0..POLLS {
+        if read_register(bus, address, status)? & ZYXDA != 0 {
+            ready = true;
+            break;
+        }
+    }
+    if !ready {
+        return Err(Error::Sensor);
+    }
+
+    let mut bytes = [0; 6];
+    bus.write_read(address, &[output], &mut bytes)?;
+    let axis = |i: usize| i16::from_le_bytes([bytes[i], bytes[i + 1]]) as i32;
+    Ok([axis(0), axis(2), axis(4)])
+}
@@ -0,0 +1,527 @@
+//! One interrupt driven serial port type for both boards.
+//!
+//! Received bytes are moved into a ring buffer by the UART interrupt as soon as they arrive, and
+//! bytes to send are queued in another one which the interrupt drains into the peripheral. The
+//! application only ever touches the ring buffers, so it no longer loses input while it is busy
+//! with something else and writing only blocks once the transmit buffer is full.
+//!
+//! The nRF51 has a plain UART that sends and receives a byte at a time, the nRF52 only has the DMA
+//! based UARTE. On the UARTE reception runs continuously into two DMA buffers of [`RX_CHUNK`] bytes
+//! which take turns: when one is full, ENDRX restarts reception into the other one right away
+//! through a shortcut, and the interrupt moves what it holds into the ring buffer. A buffer that is
+//! only partly filled would keep its bytes until more arrive, so when the application finds the
+//! ring buffer empty while bytes were received since (RXDRDY), the port stops reception with
+//! STOPRX. That ends the buffer with ENDRX. Bytes that arrive until RXTO says the receiver has
+//! stopped stay in its FIFO, so FLUSHRX moves them into the other buffer, which ends with one more
+//! ENDRX, and then reception starts over. The interrupt has to come within the time a buffer takes
+//! to fill, 2.8ms at 115200 baud, or the next one is written over. Transmission sends up to
+//! [`TX_CHUNK`] bytes per DMA transfer.
+//!
+//! To check reception on the board, paste a few kB of text without line breaks at the prompt of
+//! the `i2c` app and press Enter. The line editor rings the bell at every byte that does not fit,
+//! but the app must not print `error: serial` after the command, which it does when a count in
+//! [`Stats`] went up.
+use core::cell::RefCell;
+use core::fmt;
+use cortex_m::interrupt::{free, Mutex};
+use cortex_m::peripheral::NVIC;
+use embedded_hal::blocking::serial as bserial;
+use embedded_hal::serial;
+use heapless::spsc::{Consumer, Producer, Queue};
+use microbit::pac::{self, interrupt};
+
+#[cfg(feature = "v1")]
+use microbit::{
+    hal::uart::{self, Baudrate, Parity, Uart},
+    pac::UART0,
+};
+
+#[cfg(feature = "v2")]
+use core::sync::atomic::{compiler_fence, Ordering};
+#[cfg(feature = "v2")]
+use microbit::{
+    hal::uarte::{self, Baudrate, Parity, Uarte},
+    pac::UARTE0,
+};
+
+/// Size of the ring buffers [`crate::board_serial!`] uses unless told otherwise. Note that a
+/// `heapless` queue holds one byte less than its size.
+pub const DEFAULT_CAPACITY: usize = 64;
+
+/// Most bytes sent in one DMA transfer on the UARTE.
+#[cfg(feature = "v2")]
+pub const TX_CHUNK: usize = 16;
+
+/// Size of each of the two DMA buffers reception takes turns with on the UARTE.
+#[cfg(feature = "v2")]
+pub const RX_CHUNK: usize = 32;
+
+/// Reading and writing never fail: bytes which get lost are counted in [`Stats`] instead.
+#[derive(Debug)]
+pub enum Error {}
+
+/// Counts of received bytes that were lost, see [`Serial::stats`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    /// Bytes dropped because the receive ring buffer was full, the application did not keep up.
+    pub dropped: u32,
+    /// Bytes the peripheral lost before the interrupt handler got to them.
+    pub overruns: u32,
+    /// Framing and parity errors and breaks on the line.
+    pub line_errors: u32,
+}
+
+/// The memory a [`Serial`] needs, it has to stay around for the rest of the program.
+/// [`crate::board_serial!`] takes care of that.
+pub struct Buffers<const RX: usize, const TX: usize> {
+    rx: Queue<u8, RX>,
+    tx: Queue<u8, TX>,
+    driver: Option<Driver<RX, TX>>,
+}
+
+impl<const RX: usize, const TX: usize> Buffers<RX, TX> {
+    pub const fn new() -> Self {
+        Buffers {
+            rx: Queue::new(),
+            tx: Queue::new(),
+            driver: None,
+        }
+    }
+}
+
+impl<const RX: usize, const TX: usize> Default for Buffers<RX, TX> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The interrupt handler's view of the port, without the buffer sizes in its type.
+trait Handler: Send {
+    fn on_interrupt(&mut self);
+    /// Hands received bytes still held by the peripheral over to the ring buffer soon, because
+    /// the application ran out of them.
+    fn flush_rx(&mut self);
+    fn stats(&self) -> Stats;
+    fn tx_idle(&self) -> bool;
+}
+
+static HANDLER: Mutex<RefCell<Option<&'static mut dyn Handler>>> = Mutex::new(RefCell::new(None));
+
+/// The serial port connected to the USB interface chip, see [`crate::board_serial!`]. `RX` and
+/// `TX` are the sizes of the receive and transmit ring buffers.
+pub struct Serial<const RX: usize = DEFAULT_CAPACITY, const TX: usize = DEFAULT_CAPACITY> {
+    rx: Consumer<'static, u8, RX>,
+    tx: Producer<'static, u8, TX>,
+}
+
+impl<const RX: usize, const TX: usize> Serial<RX, TX> {
+    /// Sets up the port at 115200 baud and starts receiving.
+    #[cfg(feature = "v1")]
+    pub fn new(uart: UART0, pins: uart::Pins, buffers: &'static mut Buffers<RX, TX>) -> Self {
+        let uart = Uart::new(uart, pins, Parity::EXCLUDED, Baudrate::BAUD115200).free();
+        Self::start(buffers, |rx, tx| Driver::new(uart, rx, tx))
+    }
+
+    /// Sets up the port at 115200 baud and starts receiving.
+    #[cfg(feature = "v2")]
+    pub fn new(uarte: UARTE0, pins: uarte::Pins, buffers: &'static mut Buffers<RX, TX>) -> Self {
+        let (uarte, _pins) = Uarte::new(uarte, pins, Parity::EXCLUDED, Baudrate::BAUD115200).free();
+        Self::start(buffers, |rx, tx| Driver::new(uarte, rx, tx))
+    }
+
+    fn start<F>(buffers: &'static mut Buffers<RX, TX>, new_driver: F) -> Self
+    where
+        F: FnOnce(Producer<'static, u8, RX>, Consumer<'static, u8, TX>) -> Driver<RX, TX>,
+    {
+        let Buffers { rx, tx, driver } = buffers;
+        let (rx_producer, rx_consumer) = rx.split();
+        let (tx_producer, tx_consumer) = tx.split();
+
+        let driver = driver.insert(new_driver(rx_producer, tx_consumer));
+        driver.listen();
+        free(|cs| *HANDLER.borrow(cs).borrow_mut() = Some(driver));
+        #[allow(unsafe_code)]
+        unsafe {
+            NVIC::unmask(INTERRUPT);
+        }
+
+        Serial {
+            rx: rx_consumer,
+            tx: tx_producer,
+        }
+    }
+
+    fn receive(&mut self) -> Option<u8> {
+        let byte = self.rx.dequeue();
+        if byte.is_none() {
+            free(|cs| {
+                if let Some(handler) = HANDLER.borrow(cs).borrow_mut().as_mut() {
+                    handler.flush_rx();
+                }
+            });
+        }
+        byte
+    }
+
+    /// How many received bytes were lost so far.
+    pub fn stats(&self) -> Stats {
+        free(|cs| {
+            HANDLER
+                .borrow(cs)
+                .borrow()
+                .as_ref()
+                .map(|handler| handler.stats())
+                .unwrap_or_default()
+        })
+    }
+}
+
+impl<const RX: usize, const TX: usize> fmt::Write for Serial<RX, TX> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            nb::block!(serial::Write::write(self, byte)).map_err(|_| fmt::Error)?;
+        }
+        Ok(())
+    }
+}
+
+impl<const RX: usize, const TX: usize> serial::Write<u8> for Serial<RX, TX> {
+    type Error = Error;
+
+    fn write(&mut self, word: u8) -> nb::Result<(), Self::Error> {
+        let queued = self.tx.enqueue(word);
+        // Let the interrupt handler start sending in case it is idle
+        NVIC::pend(INTERRUPT);
+        queued.map_err(|_| nb::Error::WouldBlock)
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Self::Error> {
+        let idle = free(|cs| {
+            HANDLER
+                .borrow(cs)
+                .borrow()
+                .as_ref()
+                .is_none_or(|handler| handler.tx_idle())
+        });
+        if idle && self.tx.len() == 0 {
+            Ok(())
+        } else {
+            Err(nb::Error::WouldBlock)
+        }
+    }
+}
+
+impl<const RX: usize, const TX: usize> bserial::write::Default<u8> for Serial<RX, TX> {}
+
+impl<const RX: usize, const TX: usize> serial::Read<u8> for Serial<RX, TX> {
+    type Error = Error;
+
+    fn read(&mut self) -> nb::Result<u8, Self::Error> {
+        self.receive().ok_or(nb::Error::WouldBlock)
+    }
+}
+
+impl<const RX: usize, const TX: usize> shell::Console for Serial<RX, TX> {
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            nb::block!(serial::Write::write(self, *byte)).ok();
+        }
+    }
+
+    fn read_byte(&mut self) -> Option<u8> {
+        self.receive()
+    }
+}
+
+#[cfg(feature = "v1")]
+const INTERRUPT: pac::Interrupt = pac::Interrupt::UART0;
+
+#[cfg(feature = "v2")]
+const INTERRUPT: pac::Interrupt = pac::Interrupt::UARTE0_UART0;
+
+#[cfg(feature = "v1")]
+#[interrupt]
+fn UART0() {
+    on_interrupt();
+}
+
+#[cfg(feature = "v2")]
+#[interrupt]
+fn UARTE0_UART0() {
+    on_interrupt();
+}
+
+fn on_interrupt() {
+    free(|cs| {
+        if let Some(handler) = HANDLER.borrow(cs).borrow_mut().as_mut() {
+            handler.on_interrupt();
+        }
+    });
+}
+
+#[cfg(feature = "v1")]
+struct Driver<const RX: usize, const TX: usize> {
+    uart: UART0,
+    rx: Producer<'static, u8, RX>,
+    tx: Consumer<'static, u8, TX>,
+    tx_busy: bool,
+    stats: Stats,
+}
+
+#[cfg(feature = "v1")]
+impl<const RX: usize, const TX: usize> Driver<RX, TX> {
+    fn new(uart: UART0, rx: Producer<'static, u8, RX>, tx: Consumer<'static, u8, TX>) -> Self {
+        Driver {
+            uart,
+            rx,
+            tx,
+            // The HAL writes a dummy byte while setting up, which ends with a TXDRDY event
+            tx_busy: true,
+            stats: Stats::default(),
+        }
+    }
+
+    fn listen(&mut self) {
+        self.uart
+            .intenset
+            .write(|w| w.rxdrdy().set().txdrdy().set().error().set());
+    }
+}
+
+#[cfg(feature = "v1")]
+impl<const RX: usize, const TX: usize> Handler for Driver<RX, TX> {
+    fn on_interrupt(&mut self) {
+        let uart = &self.uart;
+        if uart.events_error.read().bits() != 0 {
+            uart.events_error.reset();
+            let source = uart.errorsrc.read();
+            if source.overrun().bit_is_set() {
+                self.stats.overruns += 1;
+            }
+            if source.parity().bit_is_set()
+                || source.framing().bit_is_set()
+                || source.break_().bit_is_set()
+            {
+                self.stats.line_errors += 1;
+            }
+            // The bits are cleared by writing ones
+            #[allow(unsafe_code)]
+            uart.errorsrc.write(|w| unsafe { w.bits(source.bits()) });
+        }
+
+        if uart.events_rxdrdy.read().bits() != 0 {
+            uart.events_rxdrdy.reset();
+            let byte = uart.rxd.read().bits() as u8;
+            if self.rx.enqueue(byte).is_err() {
+                self.stats.dropped += 1;
+            }
+        }
+
+        if uart.events_txdrdy.read().bits() != 0 {
+            uart.events_txdrdy.reset();
+            self.tx_busy = false;
+        }
+        if !self.tx_busy {
+            if let Some(byte) = self.tx.dequeue() {
+                #[allow(unsafe_code)]
+                uart.txd.write(|w| unsafe { w.bits(byte as u32) });
+                self.tx_busy = true;
+            }
+        }
+    }
+
+    /// The UART hands over every byte as it arrives.
+    fn flush_rx(&mut self) {}
+
+    fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    fn tx_idle(&self) -> bool {
+        !self.tx_busy
+    }
+}
+
+#[cfg(feature = "v2")]
+struct Driver<const RX: usize, const TX: usize> {
+    uarte: UARTE0,
+    rx: Producer<'static, u8, RX>,
+    tx: Consumer<'static, u8, TX>,
+    rx_dma: [[u8; RX_CHUNK]; 2],
+    /// Which of `rx_dma` the UARTE is receiving into
+    rx_active: usize,
+    /// STOPRX was triggered and reception has not started again yet
+    rx_stopping: bool,
+    /// FLUSHRX was triggered and its ENDRX has not come yet
+    rx_flushing: bool,
+    tx_dma: [u8; TX_CHUNK],
+    tx_busy: bool,
+    stats: Stats,
+}
+
+#[cfg(feature = "v2")]
+impl<const RX: usize, const TX: usize> Driver<RX, TX> {
+    fn new(uarte: UARTE0, rx: Producer<'static, u8, RX>, tx: Consumer<'static, u8, TX>) -> Self {
+        Driver {
+            uarte,
+            rx,
+            tx,
+            rx_dma: [[0; RX_CHUNK]; 2],
+            rx_active: 0,
+            rx_stopping: false,
+            rx_flushing: false,
+            tx_dma: [0; TX_CHUNK],
+            tx_busy: false,
+            stats: Stats::default(),
+        }
+    }
+
+    fn listen(&mut self) {
+        let uarte = &self.uarte;
+        uarte.intenset.write(|w| {
+            w.endrx()
+                .set()
+                .rxstarted()
+                .set()
+                .rxto()
+                .set()
+                .error()
+                .set()
+                .endtx()
+                .set()
+        });
+        self.start_rx();
+    }
+
+    /// The buffers live inside the `'static` [`Buffers`], so their addresses stay valid for the
+    /// DMA.
+    fn start_rx(&mut self) {
+        self.rx_active = 0;
+        self.rx_stopping = false;
+        self.rx_flushing = false;
+        self.uarte.shorts.write(|w| w.endrx_startrx().enabled());
+        self.point_rx_at(0);
+        compiler_fence(Ordering::SeqCst);
+        #[allow(unsafe_code)]
+        self.uarte.tasks_startrx.write(|w| unsafe { w.bits(1) });
+    }
+
+    fn point_rx_at(&mut self, buffer: usize) {
+        let ptr = self.rx_dma[buffer].as_mut_ptr() as u32;
+        #[allow(unsafe_code)]
+        self.uarte.rxd.ptr.write(|w| unsafe { w.ptr().bits(ptr) });
+        #[allow(unsafe_code)]
+        self.uarte
+            .rxd
+            .maxcnt
+            .write(|w| unsafe { w.maxcnt().bits(RX_CHUNK as _) });
+    }
+}
+
+#[cfg(feature = "v2")]
+impl<const RX: usize, const TX: usize> Handler for Driver<RX, TX> {
+    fn on_interrupt(&mut self) {
+        if self.uarte.events_error.read().bits() != 0 {
+            self.uarte.events_error.reset();
+            let source = self.uarte.errorsrc.read();
+            if source.overrun().bit_is_set() {
+                self.stats.overruns += 1;
+            }
+            if source.parity().bit_is_set()
+                || source.framing().bit_is_set()
+                || source.break_().bit_is_set()
+            {
+                self.stats.line_errors += 1;
+            }
+            // The bits are cleared by writing ones
+            #[allow(unsafe_code)]
+            self.uarte
+                .errorsrc
+                .write(|w| unsafe { w.bits(source.bits()) });
+        }
+
+        // ENDRX has to be handled before RXSTARTED: the buffer which was just filled is the one
+        // the next reception goes into. A buffer ends full, partly filled after STOPRX, or with
+        // what was left in the FIFO after FLUSHRX.
+        if self.uarte.events_endrx.read().bits() != 0 {
+            self.uarte.events_endrx.reset();
+            compiler_fence(Ordering::SeqCst);
+            let amount = self.uarte.rxd.amount.read().bits() as usize;
+            for &byte in &self.rx_dma[self.rx_active][..amount.min(RX_CHUNK)] {
+                if self.rx.enqueue(byte).is_err() {
+                    self.stats.dropped += 1;
+                }
+            }
+            self.rx_active ^= 1;
+            if self.rx_flushing {
+                self.start_rx();
+            }
+        }
+        if self.uarte.events_rxstarted.read().bits() != 0 {
+            self.uarte.events_rxstarted.reset();
+            self.point_rx_at(self.rx_active ^ 1);
+        }
+        if self.uarte.events_rxto.read().bits() != 0 {
+            // Only comes after `flush_rx` stopped reception, whose ENDRX came first and handed the
+            // buffer over. Bytes that arrived since are still in the FIFO of the UARTE. FLUSHRX
+            // moves them into the idle buffer and raises ENDRX, even when there were none, which
+            // hands them over and starts reception again.
+            self.uarte.events_rxto.reset();
+            self.point_rx_at(self.rx_active);
+            self.rx_flushing = true;
+            compiler_fence(Ordering::SeqCst);
+            #[allow(unsafe_code)]
+            self.uarte.tasks_flushrx.write(|w| unsafe { w.bits(1) });
+        }
+
+        if self.uarte.events_endtx.read().bits() != 0 {
+            self.uarte.events_endtx.reset();
+            self.tx_busy = false;
+        }
+        if !self.tx_busy {
+            let mut len = 0;
+            while len < TX_CHUNK {
+                match self.tx.dequeue() {
+                    Some(byte) => self.tx_dma[len] = byte,
+                    None => break,
+                }
+                len += 1;
+            }
+            if len > 0 {
+                let ptr = self.tx_dma.as_ptr() as u32;
+                #[allow(unsafe_code)]
+                self.uarte.txd.ptr.write(|w| unsafe { w.ptr().bits(ptr) });
+                #[allow(unsafe_code)]
+                self.uarte
+                    .txd
+                    .maxcnt
+                    .write(|w| unsafe { w.maxcnt().bits(len as _) });
+                compiler_fence(Ordering::SeqCst);
+                #[allow(unsafe_code)]
+                self.uarte.tasks_starttx.write(|w| unsafe { w.bits(1) });
+                self.tx_busy = true;
+            }
+        }
+    }
+
+    fn flush_rx(&mut self) {
+        if self.rx_stopping || self.uarte.events_rxdrdy.read().bits() == 0 {
+            return;
+        }
+        // Bytes received from now on set RXDRDY again, and get flushed the next time
+        self.uarte.events_rxdrdy.reset();
+        // Without the shortcut the ENDRX that STOPRX causes does not start reception again
+        self.uarte.shorts.reset();
+        self.rx_stopping = true;
+        #[allow(unsafe_code)]
+        self.uarte.tasks_stoprx.write(|w| unsafe { w.bits(1) });
+    }
+
+    fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    fn tx_idle(&self) -> bool {
+        !self.tx_busy
+    }
+}
@@ -0,0 +1,127 @@
+//! Sleeping until the accelerometer feels a jolt.
+//!
+//! [`arm`] makes the LSM303AGR accelerometer raise its INT1 pin once the acceleration on any axis
+//! goes above a threshold. Gravity is filtered out of what the interrupt looks at, but not out of
+//! the samples. The pin stays raised until the interrupt is read, which [`Wakeup::sleep`] does
+//! right before it leaves the CPU waiting for the pin through the GPIOTE port event. Unlike a
+//! GPIOTE channel, which needs the high frequency clock to watch its pin, the port event comes
+//! from the pin's own level detection and lets the chip sleep properly. The accelerometer keeps
+//! measuring in the meantime, so with [`crate::fifo`] on the samples leading up to the jolt are
+//! still there after it.
+//!
+//! On the v1 board INT1 has a pin to itself, P0.28. On the v2 board it is wired to P0.25, the
+//! interrupt line of the sensor bus, which is active low, so the accelerometer is set up to drive
+//! it low rather than high.
+use crate::bus::read_register;
+use crate::error::{Bus, Error};
+use crate::selftest::ACCELEROMETER_ADDR;
+use cortex_m::asm;
+use cortex_m::peripheral::{NVIC, SCB};
+use embedded_hal::blocking::i2c::{Write, WriteRead};
+use lsm303agr::AccelScale;
+use microbit::hal::gpio::{Input, Pin};
+use microbit::hal::gpiote::{Gpiote, GpioteInputPin};
+use microbit::pac::{Interrupt, GPIOTE};
+
+const CTRL_REG2_A: u8 = 0x21;
+const CTRL_REG3_A: u8 = 0x22;
+const CTRL_REG5_A: u8 = 0x24;
+const CTRL_REG6_A: u8 = 0x25;
+const REFERENCE_A: u8 = 0x26;
+const INT1_CFG_A: u8 = 0x30;
+const INT1_SRC_A: u8 = 0x31;
+const INT1_THS_A: u8 = 0x32;
+const INT1_DURATION_A: u8 = 0x33;
+
+/// In CTRL_REG2_A, high-pass filter what the first interrupt generator looks at.
+const HPIS1: u8 = 1 << 0;
+/// In CTRL_REG3_A, the first interrupt generator drives INT1.
+const I1_AOI1: u8 = 1 << 6;
+/// In CTRL_REG5_A, INT1 stays raised until INT1_SRC_A is read.
+const LIR_INT1: u8 = 1 << 3;
+/// In CTRL_REG6_A, the interrupt pins are active low.
+const H_LACTIVE: u8 = 1 << 1;
+const POLARITY: u8 = if cfg!(feature = "v2") { H_LACTIVE } else { 0 };
+/// In INT1_CFG_A, a high event on any of the axes.
+const XHIE_YHIE_ZHIE: u8 = 0b0010_1010;
+
+/// Sets the accelerometer up to raise INT1 when the acceleration on an axis goes above
+/// `threshold` mg, measured at `scale`. Needs doing again after the sensor was set up again.
+pub fn arm<B>(bus: &mut B, threshold: u32, scale: AccelScale) -> Result<(), Error>
+where
+    B: Write<Error = Bus> + WriteRead<Error = Bus>,
+{
+    // The threshold register has 7 bits, worth more the larger the scale
+    let mg_per_digit = match scale {
+        AccelScale::G2 => 16,
+        AccelScale::G4 => 32,
+        AccelScale::G8 => 62,
+        AccelScale::G16 => 186,
+    };
+    let digits = (threshold / mg_per_digit).clamp(1, 0x7f) as u8;
+
+    bus.write(ACCELEROMETER_ADDR, &[CTRL_REG2_A, HPIS1])?;
+    bus.write(ACCELEROMETER_ADDR, &[CTRL_REG3_A, I1_AOI1])?;
+    // Leave the FIFO as it is
+    let reg5 = read_register(bus, ACCELEROMETER_ADDR, CTRL_REG5_A)?;
+    bus.write(ACCELEROMETER_ADDR, &[CTRL_REG5_A, reg5 | LIR_INT1])?;
+    bus.write(ACCELEROMETER_ADDR, &[CTRL_REG6_A, POLARITY])?;
+    bus.write(ACCELEROMETER_ADDR, &[INT1_THS_A, digits])?;
+    bus.write(ACCELEROMETER_ADDR, &[INT1_DURATION_A, 0])?;
+    bus.write(ACCELEROMETER_ADDR, &[INT1_CFG_A, XHIE_YHIE_ZHIE])?;
+    // Reading the reference starts the filter over from the acceleration as it is now
+    read_register(bus, ACCELEROMETER_ADDR, REFERENCE_A)?;
+    read_register(bus, ACCELEROMETER_ADDR, INT1_SRC_A)?;
+    Ok(())
+}
+
+/// The accelerometer interrupt pin watched by the GPIOTE port event, see
+/// [`crate::board_wakeup!`].
+pub struct Wakeup<MODE> {
+    gpiote: Gpiote,
+    _pin: Pin<Input<MODE>>,
+}
+
+impl<MODE> Wakeup<MODE>
+where
+    Pin<Input<MODE>>: GpioteInputPin,
+{
+    pub fn new(gpiote: GPIOTE, pin: Pin<Input<MODE>>) -> Self {
+        let gpiote = Gpiote::new(gpiote);
+        let port = gpiote.port();
+        let event = port.input_pin(&pin);
+        #[cfg(feature = "v1")]
+        event.high();
+        #[cfg(feature = "v2")]
+        event.low();
+        // The interrupt is never taken, but it being pending is what wakes the CPU up
+        port.enable_interrupt();
+        Wakeup { gpiote, _pin: pin }
+    }
+
+    /// Reads the interrupt of the accelerometer on `bus` so that it can be raised again, then
+    /// waits for that with the CPU asleep.
+    pub fn sleep<B>(&mut self, bus: &mut B, scb: &mut SCB) -> Result<(), Error>
+    where
+        B: Write<Error = Bus> + WriteRead<Error = Bus>,
+    {
+        // A pin raised before the interrupt is read would wake the CPU up straight away, one
+        // raised after it still does
+        self.reset();
+        read_register(bus, ACCELEROMETER_ADDR, INT1_SRC_A)?;
+
+        // With the GPIOTE interrupt left disabled in the NVIC, it becoming pending only ends the
+        // WFE
+        scb.set_sevonpend();
+        while !self.gpiote.port().is_event_triggered() {
+            asm::wfe();
+        }
+        self.reset();
+        Ok(())
+    }
+
+    fn reset(&self) {
+        self.gpiote.port().reset_events();
+        NVIC::unpend(Interrupt::GPIOTE);
+    }
+}
@@ -0,0 +1,732 @@
+//! Translated from <https://github.com/lancaster-university/codal-microbit-v2/blob/006abf5566774fbcf674c0c7df27e8a9d20013de/source/MicroBitCompassCalibrator.cpp>
+
+/// # Calibration
+/// One very important thing to do before using a sensor and trying to develop an application using
+/// it is verifying that it's output is actually correct. If this does not happen to be the case we
+/// need to calibrate the sensor (alternatively it could also be broken but that's rather unlikely
+/// in this case).
+///
+/// Ideally the magnetometer samples taken while rotating the board lie on a sphere around the
+/// origin whose radius is the strength of the earth's field. Nearby magnets and ferrous parts
+/// distort that in two ways:
+/// - *hard-iron* effects add a constant offset, moving the center of the sphere.
+/// - *soft-iron* effects stretch and shear the field, turning the sphere into a rotated ellipsoid.
+///
+/// We fit a general ellipsoid to the samples with linear least squares and derive the offset plus
+/// a symmetric 3x3 matrix that maps the ellipsoid back onto a sphere.
+use crate::linalg::{self, Matrix3, Vector3, IDENTITY};
+use crate::Measurement;
+use libm::sqrt;
+
+/// An ellipsoid has 9 degrees of freedom, ask for a few samples more than that.
+const MIN_SAMPLES: usize = 12;
+/// Fraction of the octants around the fitted center that must contain a sample.
+const MIN_COVERAGE: f32 = 0.5;
+/// Largest acceptable RMS deviation from the fitted sphere, relative to its radius.
+const MAX_RESIDUAL: f32 = 0.05;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Calibration {
+    /// Hard-iron offset, subtracted from every sample.
+    hard_iron: Measurement,
+    /// Soft-iron correction, applied after removing the offset.
+    soft_iron: [[f32; 3]; 3],
+    /// Magnitude of a calibrated sample.
+    radius: u32,
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Calibration {
+            hard_iron: Measurement { x: 0, y: 0, z: 0 },
+            soft_iron: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+            radius: 0,
+        }
+    }
+}
+
+impl Calibration {
+    pub(crate) fn from_parts(
+        hard_iron: Measurement,
+        soft_iron: [[f32; 3]; 3],
+        radius: u32,
+    ) -> Self {
+        Calibration {
+            hard_iron,
+            soft_iron,
+            radius,
+        }
+    }
+
+    pub fn hard_iron(&self) -> Measurement {
+        self.hard_iron
+    }
+
+    pub fn soft_iron(&self) -> [[f32; 3]; 3] {
+        self.soft_iron
+    }
+
+    pub fn radius(&self) -> u32 {
+        self.radius
+    }
+}
+
+/// How trustworthy a calibration is.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quality {
+    /// Number of samples the calibration was computed from.
+    pub samples: usize,
+    /// RMS distance of the calibrated samples from the fitted sphere, relative to its radius.
+    pub residual: f32,
+    /// Fraction of the eight octants around the fitted center that contain at least one sample.
+    /// Samples taken in only a few orientations leave the fit poorly constrained.
+    pub coverage: f32,
+}
+
+/// Reasons for rejecting a calibration.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CalibrationError {
+    /// Not enough samples to fit anything yet.
+    TooFewSamples { samples: usize, required: usize },
+    /// The samples do not describe an ellipsoid or a sphere at all, e.g. because they all lie in
+    /// one plane.
+    Degenerate,
+    /// The samples only cover a small part of the possible orientations.
+    PoorCoverage(Quality),
+    /// The samples are too far from the fitted shape, the field was probably disturbed while
+    /// collecting them.
+    PoorFit(Quality),
+}
+
+/// Maps samples into a space where they have zero mean and roughly unit size. Fitting in there
+/// keeps the normal equations well conditioned even though the raw samples are in the tens of
+/// thousands.
+struct Normalization {
+    mean: Vector3,
+    scale: f64,
+}
+
+impl Normalization {
+    fn new(data: &[Measurement]) -> Option<Self> {
+        let n = data.len() as f64;
+        let mut mean = [0.0; 3];
+        for point in data {
+            mean[0] += point.x as f64 / n;
+            mean[1] += point.y as f64 / n;
+            mean[2] += point.z as f64 / n;
+        }
+
+        let mut scale = 0.0;
+        for point in data {
+            let p = to_vector(point);
+            let d = [p[0] - mean[0], p[1] - mean[1], p[2] - mean[2]];
+            scale += linalg::dot(&d, &d) / n;
+        }
+        let scale = sqrt(scale);
+
+        if scale > 0.0 {
+            Some(Normalization { mean, scale })
+        } else {
+            None
+        }
+    }
+
+    fn apply(&self, point: &Measurement) -> Vector3 {
+        let p = to_vector(point);
+        [
+            (p[0] - self.mean[0]) / self.scale,
+            (p[1] - self.mean[1]) / self.scale,
+            (p[2] - self.mean[2]) / self.scale,
+        ]
+    }
+
+    fn revert(&self, p: &Vector3) -> Vector3 {
+        [
+            p[0] * self.scale + self.mean[0],
+            p[1] * self.scale + self.mean[1],
+            p[2] * self.scale + self.mean[2],
+        ]
+    }
+}
+
+fn to_vector(m: &Measurement) -> Vector3 {
+    [m.x as f64, m.y as f64, m.z as f64]
+}
+
+fn to_measurement(v: &Vector3) -> Measurement {
+    Measurement {
+        x: libm::round(v[0]) as i32,
+        y: libm::round(v[1]) as i32,
+        z: libm::round(v[2]) as i32,
+    }
+}
+
+fn to_f32(m: &Matrix3) -> [[f32; 3]; 3] {
+    let mut out = [[0.0; 3]; 3];
+    for (out_row, row) in out.iter_mut().zip(m.iter()) {
+        for (out_value, value) in out_row.iter_mut().zip(row.iter()) {
+            *out_value = *value as f32;
+        }
+    }
+    out
+}
+
+/// Computes a calibration from raw magnetometer samples, expressed in the ENU frame (see
+/// [`measurement_to_enu`]).
+///
+/// The samples should cover as many orientations of the board as possible. If they do not pin
+/// down an ellipsoid this falls back to a sphere fit, which only corrects the hard-iron offset.
+/// Calibrations which do not pass the [`Quality`] checks are rejected, the caller should collect
+/// more samples and try again.
+pub fn calibrate(data: &[Measurement]) -> Result<Calibration, CalibrationError> {
+    if data.len() < MIN_SAMPLES {
+        return Err(CalibrationError::TooFewSamples {
+            samples: data.len(),
+            required: MIN_SAMPLES,
+        });
+    }
+
+    let normalization = Normalization::new(data).ok_or(CalibrationError::Degenerate)?;
+    let calibration = fit_ellipsoid(data, &normalization)
+        .or_else(|| fit_sphere(data, &normalization))
+        .ok_or(CalibrationError::Degenerate)?;
+
+    let quality = quality(data, &calibration);
+    if quality.coverage < MIN_COVERAGE {
+        Err(CalibrationError::PoorCoverage(quality))
+    } else if quality.residual > MAX_RESIDUAL {
+        Err(CalibrationError::PoorFit(quality))
+    } else {
+        Ok(calibration)
+    }
+}
+
+/// Rates how well `calibration` fits the (ENU frame) samples it was computed from.
+///
+/// A calibration with a radius of 0 has no sphere to compare the samples with, its residual is
+/// infinite so that it fails every check.
+pub fn quality(data: &[Measurement], calibration: &Calibration) -> Quality {
+    let mut octants = 0u8;
+    let mut squares = 0.0;
+    for point in data {
+        let corrected = correct(point, calibration);
+        let octant = (corrected.x >= 0) as u8
+            | ((corrected.y >= 0) as u8) << 1
+            | ((corrected.z >= 0) as u8) << 2;
+        octants |= 1 << octant;
+
+        let error = sqrt(linalg::dot(&to_vector(&corrected), &to_vector(&corrected)))
+            / calibration.radius as f64
+            - 1.0;
+        squares += error * error;
+    }
+
+    Quality {
+        samples: data.len(),
+        residual: if calibration.radius == 0 {
+            f32::INFINITY
+        } else if data.is_empty() {
+            0.0
+        } else {
+            sqrt(squares / data.len() as f64) as f32
+        },
+        coverage: octants.count_ones() as f32 / 8.0,
+    }
+}
+
+/// Fits `a x² + b y² + c z² + 2d xy + 2e xz + 2f yz + 2g x + 2h y + 2i z = 1`.
+fn fit_ellipsoid(data: &[Measurement], normalization: &Normalization) -> Option<Calibration> {
+    let mut normal = [[0.0; 9]; 9];
+    let mut rhs = [0.0; 9];
+    for point in data {
+        let [x, y, z] = normalization.apply(point);
+        let row = [
+            x * x,
+            y * y,
+            z * z,
+            2.0 * x * y,
+            2.0 * x * z,
+            2.0 * y * z,
+            2.0 * x,
+            2.0 * y,
+            2.0 * z,
+        ];
+        for i in 0..9 {
+            for j in 0..9 {
+                normal[i][j] += row[i] * row[j];
+            }
+            rhs[i] += row[i];
+        }
+    }
+
+    let v = linalg::solve(normal, rhs)?;
+    let quadric: Matrix3 = [[v[0], v[3], v[4]], [v[3], v[1], v[5]], [v[4], v[5], v[2]]];
+
+    // Completing the square gives (p - center)ᵀ Q (p - center) = 1 + centerᵀ Q center
+    let center = linalg::solve(quadric, [-v[6], -v[7], -v[8]])?;
+    let k = 1.0 + linalg::dot(&center, &linalg::mul_vector(&quadric, &center));
+    if k <= 0.0 {
+        return None;
+    }
+
+    // Back in sensor units the ellipsoid is (p - center)ᵀ E (p - center) = 1, where E has the
+    // eigenvalues 1 / radius² along the axes of the ellipsoid.
+    let (mut values, vectors) = linalg::symmetric_eigen(quadric);
+    let mut volume = 1.0;
+    for value in values.iter_mut() {
+        *value /= k * normalization.scale * normalization.scale;
+        if *value <= 0.0 {
+            // Not an ellipsoid but some hyperboloid, the data is garbage
+            return None;
+        }
+        volume /= sqrt(*value);
+    }
+    let radius = libm::cbrt(volume);
+
+    // E^½ maps the ellipsoid onto the unit sphere without rotating it, scale it back up so the
+    // calibrated samples keep their usual magnitude.
+    for value in values.iter_mut() {
+        *value = radius * sqrt(*value);
+    }
+    let soft_iron = linalg::from_eigen(&values, &vectors);
+
+    Some(Calibration {
+        hard_iron: to_measurement(&normalization.revert(&center)),
+        soft_iron: to_f32(&soft_iron),
+        radius: to_radius(radius)?,
+    })
+}
+
+/// Fits `x² + y² + z² = 2 cx x + 2 cy y + 2 cz z + d`.
+fn fit_sphere(data: &[Measurement], normalization: &Normalization) -> Option<Calibration> {
+    let mut normal = [[0.0; 4]; 4];
+    let mut rhs = [0.0; 4];
+    for point in data {
+        let [x, y, z] = normalization.apply(point);
+        let row = [2.0 * x, 2.0 * y, 2.0 * z, 1.0];
+        for i in 0..4 {
+            for j in 0..4 {
+                normal[i][j] += row[i] * row[j];
+            }
+            rhs[i] += row[i] * (x * x + y * y + z * z);
+        }
+    }
+
+    let v = linalg::solve(normal, rhs)?;
+    let center = [v[0], v[1], v[2]];
+    let radius2 = v[3] + linalg::dot(&center, &center);
+    if radius2 <= 0.0 {
+        return None;
+    }
+
+    Some(Calibration {
+        hard_iron: to_measurement(&normalization.revert(&center)),
+        soft_iron: to_f32(&IDENTITY),
+        radius: to_radius(sqrt(radius2) * normalization.scale)?,
+    })
+}
+
+/// The radius of a fit, if it is one a calibration can be rated by.
+fn to_radius(radius: f64) -> Option<u32> {
+    (radius.is_finite() && radius >= 1.0 && radius <= u32::MAX as f64).then_some(radius as u32)
+}
+
+/// Applies `calibration` to a raw magnetometer sample.
+pub fn calibrated_measurement(measurement: &Measurement, calibration: &Calibration) -> Measurement {
+    enu_to_cartesian(&correct(&measurement_to_enu(measurement), calibration))
+}
+
+/// Applies `calibration` to a sample in the ENU frame.
+fn correct(enu: &Measurement, calibration: &Calibration) -> Measurement {
+    let d = [
+        (enu.x - calibration.hard_iron.x) as f32,
+        (enu.y - calibration.hard_iron.y) as f32,
+        (enu.z - calibration.hard_iron.z) as f32,
+    ];
+    let m = &calibration.soft_iron;
+    Measurement {
+        x: (m[0][0] * d[0] + m[0][1] * d[1] + m[0][2] * d[2]) as i32,
+        y: (m[1][0] * d[0] + m[1][1] * d[1] + m[1][2] * d[2]) as i32,
+        z: (m[2][0] * d[0] + m[2][1] * d[1] + m[2][2] * d[2]) as i32,
+    }
+}
+
+/// Converts a raw sample from the sensor frame into the East-North-Up frame the calibration works
+/// in.
+pub fn measurement_to_enu(measurement: &Measurement) -> Measurement {
+    Measurement {
+        x: -measurement.y,
+        y: -measurement.x,
+        z: measurement.z,
+    }
+}
+
+fn enu_to_cartesian(measurement: &Measurement) -> Measurement {
+    Measurement {
+        x: -measurement.y,
+        y: -measurement.x,
+        z: measurement.z,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::f64::consts::PI;
+
+    /// Roughly evenly spread points on a unit sphere (Fibonacci lattice).
+    fn unit_sphere(n: usize) -> Vec<Vector3> {
+        let golden = PI * (3.0 - sqrt(5.0));
+        (0..n)
+            .map(|i| {
+                let z = 1.0 - 2.0 * (i as f64 + 0.5) / n as f64;
+                let r = sqrt(1.0 - z * z);
+                let phi = golden * i as f64;
+                [r * phi.cos(), r * phi.sin(), z]
+            })
+            .collect()
+    }
+
+    /// Distorts the unit sphere with `center + distortion * u`, adding up to `noise` of
+    /// deterministic jitter to each axis.
+    fn distorted(
+        center: Measurement,
+        distortion: &Matrix3,
+        n: usize,
+        noise: f64,
+    ) -> Vec<Measurement> {
+        let mut seed: u32 = 0x1234_5678;
+        let mut jitter = move || {
+            seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            ((seed >> 8) as f64 / (1 << 24) as f64 * 2.0 - 1.0) * noise
+        };
+        unit_sphere(n)
+            .iter()
+            .map(|u| {
+                let p = linalg::mul_vector(distortion, u);
+                Measurement {
+                    x: center.x + (p[0] + jitter()) as i32,
+                    y: center.y + (p[1] + jitter()) as i32,
+                    z: center.z + (p[2] + jitter()) as i32,
+                }
+            })
+            .collect()
+    }
+
+    /// A symmetric distortion with the given radii along axes rotated about z and then x.
+    fn soft_iron(radii: Vector3, yaw: f64, roll: f64) -> Matrix3 {
+        let (sy, cy) = (yaw.sin(), yaw.cos());
+        let (sr, cr) = (roll.sin(), roll.cos());
+        let rz = [[cy, -sy, 0.0], [sy, cy, 0.0], [0.0, 0.0, 1.0]];
+        let rx = [[1.0, 0.0, 0.0], [0.0, cr, -sr], [0.0, sr, cr]];
+        let mut rotation = [[0.0; 3]; 3];
+        for i in 0..3 {
+            for j in 0..3 {
+                rotation[i][j] = (0..3).map(|k| rx[i][k] * rz[k][j]).sum();
+            }
+        }
+        linalg::from_eigen(&radii, &rotation)
+    }
+
+    fn calibrate_all(data: &[Measurement], calibration: &Calibration) -> Vec<Measurement> {
+        // The data is already in the ENU frame, so go back to the sensor frame before applying
+        // the calibration like the firmware does.
+        data.iter()
+            .map(|m| measurement_to_enu(&calibrated_measurement(&enu_to_cartesian(m), calibration)))
+            .collect()
+    }
+
+    fn magnitude(m: &Measurement) -> f64 {
+        let v = to_vector(m);
+        sqrt(linalg::dot(&v, &v))
+    }
+
+    /// Relative spread of the magnitudes, 0 for a perfect sphere around the origin.
+    fn spread(data: &[Measurement]) -> f64 {
+        let magnitudes: Vec<f64> = data.iter().map(magnitude).collect();
+        let max = magnitudes.iter().cloned().fold(f64::MIN, f64::max);
+        let min = magnitudes.iter().cloned().fold(f64::MAX, f64::min);
+        (max - min) / max
+    }
+
+    fn assert_close(a: Measurement, b: Measurement, tolerance: i32) {
+        assert!(
+            (a.x - b.x).abs() <= tolerance
+                && (a.y - b.y).abs() <= tolerance
+                && (a.z - b.z).abs() <= tolerance,
+            "{:?} is not within {} of {:?}",
+            a,
+            tolerance,
+            b
+        );
+    }
+
+    #[test]
+    fn sphere_around_origin_needs_no_correction() {
+        let data = distorted(
+            Measurement::default(),
+            &soft_iron([40_000.0; 3], 0.0, 0.0),
+            200,
+            0.0,
+        );
+        let calibration = calibrate(&data).unwrap();
+
+        assert_close(calibration.hard_iron, Measurement::default(), 5);
+        assert!((calibration.radius as f64 - 40_000.0).abs() < 50.0);
+        for (i, row) in calibration.soft_iron.iter().enumerate() {
+            for (j, value) in row.iter().enumerate() {
+                let expected = if i == j { 1.0 } else { 0.0 };
+                assert!((value - expected).abs() < 1e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn hard_iron_offset_is_found() {
+        let center = Measurement {
+            x: 12_000,
+            y: -7_500,
+            z: 3_300,
+        };
+        let data = distorted(center, &soft_iron([45_000.0; 3], 0.0, 0.0), 200, 0.0);
+        let calibration = calibrate(&data).unwrap();
+
+        assert_close(calibration.hard_iron, center, 5);
+    }
+
+    #[test]
+    fn partial_coverage_hard_iron_offset_is_found() {
+        // Only the upper half of the sphere, the plain average is biased towards +z
+        let center = Measurement {
+            x: 1_000,
+            y: 2_000,
+            z: -3_000,
+        };
+        let data: Vec<Measurement> =
+            distorted(center, &soft_iron([40_000.0; 3], 0.0, 0.0), 400, 0.0)
+                .into_iter()
+                .filter(|m| m.z > center.z)
+                .collect();
+        let calibration = calibrate(&data).unwrap();
+
+        assert_close(calibration.hard_iron, center, 20);
+    }
+
+    #[test]
+    fn axis_aligned_ellipsoid_is_made_round() {
+        let center = Measurement {
+            x: -5_000,
+            y: 8_000,
+            z: 1_000,
+        };
+        let distortion = soft_iron([30_000.0, 45_000.0, 38_000.0], 0.0, 0.0);
+        let data = distorted(center, &distortion, 300, 0.0);
+        let calibration = calibrate(&data).unwrap();
+
+        assert_close(calibration.hard_iron, center, 5);
+        assert!(spread(&calibrate_all(&data, &calibration)) < 1e-3);
+    }
+
+    #[test]
+    fn rotated_ellipsoid_is_made_round() {
+        let center = Measurement {
+            x: 20_000,
+            y: -3_000,
+            z: 9_000,
+        };
+        let distortion = soft_iron([28_000.0, 50_000.0, 41_000.0], 0.6, -0.4);
+        let data = distorted(center, &distortion, 300, 0.0);
+        let calibration = calibrate(&data).unwrap();
+
+        assert_close(calibration.hard_iron, center, 5);
+        assert!(spread(&calibrate_all(&data, &calibration)) < 1e-3);
+        // A diagonal only correction can not undo the shear
+        assert!(calibration.soft_iron[0][1].abs() > 0.05);
+    }
+
+    #[test]
+    fn rotated_ellipsoid_heading_is_recovered() {
+        let center = Measurement {
+            x: 20_000,
+            y: -3_000,
+            z: 9_000,
+        };
+        let distortion = soft_iron([28_000.0, 50_000.0, 41_000.0], 0.6, -0.4);
+        let data = distorted(center, &distortion, 300, 0.0);
+        let calibration = calibrate(&data).unwrap();
+
+        for (u, m) in unit_sphere(300)
+            .iter()
+            .zip(calibrate_all(&data, &calibration))
+        {
+            if u[2].abs() > 0.9 {
+                // The heading is poorly defined close to the poles
+                continue;
+            }
+            let expected = libm::atan2(u[1], u[0]);
+            let actual = libm::atan2(m.y as f64, m.x as f64);
+            let mut error = (actual - expected).abs();
+            if error > PI {
+                error = 2.0 * PI - error;
+            }
+            assert!(
+                error < 0.5_f64.to_radians(),
+                "heading off by {} degrees",
+                error.to_degrees()
+            );
+        }
+    }
+
+    #[test]
+    fn noisy_ellipsoid_is_made_rounder() {
+        let center = Measurement {
+            x: 2_000,
+            y: 4_000,
+            z: -6_000,
+        };
+        let distortion = soft_iron([30_000.0, 48_000.0, 39_000.0], -0.3, 0.8);
+        let data = distorted(center, &distortion, 100, 1_500.0);
+        let calibration = calibrate(&data).unwrap();
+
+        assert_close(calibration.hard_iron, center, 800);
+        assert!(spread(&calibrate_all(&data, &calibration)) < 0.15);
+    }
+
+    #[test]
+    fn planar_data_is_rejected() {
+        // The board was only turned around its z axis
+        let center = Measurement {
+            x: 3_000,
+            y: -2_000,
+            z: 500,
+        };
+        let data: Vec<Measurement> = (0..36)
+            .map(|i| {
+                let angle = i as f64 * PI / 18.0;
+                Measurement {
+                    x: center.x + (40_000.0 * angle.cos()) as i32,
+                    y: center.y + (40_000.0 * angle.sin()) as i32,
+                    z: center.z,
+                }
+            })
+            .collect();
+
+        assert_eq!(calibrate(&data), Err(CalibrationError::Degenerate));
+    }
+
+    #[test]
+    fn identical_samples_are_rejected() {
+        let data = [Measurement { x: 1, y: 2, z: 3 }; 25];
+        assert_eq!(calibrate(&data), Err(CalibrationError::Degenerate));
+    }
+
+    #[test]
+    fn too_few_samples_are_rejected() {
+        let data = distorted(
+            Measurement::default(),
+            &soft_iron([40_000.0; 3], 0.0, 0.0),
+            11,
+            0.0,
+        );
+        assert_eq!(
+            calibrate(&data),
+            Err(CalibrationError::TooFewSamples {
+                samples: 11,
+                required: MIN_SAMPLES
+            })
+        );
+        assert_eq!(
+            calibrate(&[]),
+            Err(CalibrationError::TooFewSamples {
+                samples: 0,
+                required: MIN_SAMPLES
+            })
+        );
+    }
+
+    #[test]
+    fn small_cap_is_rejected_for_coverage() {
+        // All samples within ~35 degrees of +x
+        let data: Vec<Measurement> = distorted(
+            Measurement::default(),
+            &soft_iron([40_000.0; 3], 0.0, 0.0),
+            2_000,
+            0.0,
+        )
+        .into_iter()
+        .filter(|m| m.x > 33_000 && m.y > 0 && m.z > 0)
+        .collect();
+        assert!(data.len() >= MIN_SAMPLES);
+
+        match calibrate(&data) {
+            Err(CalibrationError::PoorCoverage(quality)) => {
+                assert!(quality.coverage < MIN_COVERAGE)
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn disturbed_samples_are_rejected_for_fit() {
+        let data = distorted(
+            Measurement::default(),
+            &soft_iron([40_000.0; 3], 0.0, 0.0),
+            100,
+            12_000.0,
+        );
+
+        match calibrate(&data) {
+            Err(CalibrationError::PoorFit(quality)) => assert!(quality.residual > MAX_RESIDUAL),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn zero_radius_has_the_worst_quality() {
+        let data = distorted(
+            Measurement::default(),
+            &soft_iron([40_000.0; 3], 0.0, 0.0),
+            100,
+            0.0,
+        );
+        for data in [&data[..], &[]] {
+            let quality = quality(data, &Calibration::default());
+            assert_eq!(quality.residual, f32::INFINITY);
+            assert!(quality.residual > MAX_RESIDUAL);
+        }
+    }
+
+    #[test]
+    fn fits_without_a_radius_are_rejected() {
+        assert_eq!(to_radius(40_000.0), Some(40_000));
+        for radius in [0.0, 0.5, -1.0, f64::NAN, f64::INFINITY, 1e12] {
+            assert_eq!(to_radius(radius), None, "{}", radius);
+        }
+    }
+
+    #[test]
+    fn good_fit_quality() {
+        let distortion = soft_iron([28_000.0, 50_000.0, 41_000.0], 0.6, -0.4);
+        let data = distorted(
+            Measurement {
+                x: 20_000,
+                y: -3_000,
+                z: 9_000,
+            },
+            &distortion,
+            300,
+            0.0,
+        );
+        let quality = quality(&data, &calibrate(&data).unwrap());
+
+        assert_eq!(quality.samples, 300);
+        assert_eq!(quality.coverage, 1.0);
+        assert!(quality.residual < 1e-3);
+    }
+
+    #[test]
+    fn enu_round_trip() {
+        let m = Measurement { x: 1, y: -2, z: 3 };
+        assert_eq!(enu_to_cartesian(&measurement_to_enu(&m)), m);
+    }
+}
@@ -0,0 +1,174 @@
+//! Turning a magnetic heading into a true one.
+//!
+//! Magnetic north is off from true (geographic) north by the local declination, which depends on
+//! where on earth the board is. Declinations are given in degrees, positive when magnetic north
+//! lies east of true north, the way they are printed on maps.
+//!
+//! With the `declination-table` feature the crate also carries a coarse declination grid, so the
+//! declination can be derived from a latitude and longitude instead of being looked up by hand.
+use crate::heading::wrap;
+
+/// Converts a magnetic heading (see [`crate::heading`]) into a true heading, given the local
+/// declination in degrees. The result is again in the range `-PI..=PI`.
+///
+/// Headings are measured counter clockwise, and true north lies `declination` degrees west of
+/// (counter clockwise from) magnetic north, so the declination is added.
+pub fn true_heading(magnetic: f32, declination: f32) -> f32 {
+    wrap(magnetic + declination.to_radians())
+}
+
+#[cfg(feature = "declination-table")]
+pub use table::lookup;
+
+#[cfg(feature = "declination-table")]
+mod table {
+    use crate::heading::modulo;
+
+    /// Declination in whole degrees for latitudes -80 to 80 and longitudes -180 to 160, both in
+    /// steps of 10 and 20 degrees. Generated by `tools/declination_table.py` from the IGRF-13
+    /// model for 2020, truncated to degree 6. That is good to a few degrees at mid latitudes and
+    /// gets steadily worse towards the magnetic poles. The field also drifts by a few tenths of a
+    /// degree per year, so regenerate it once in a while.
+    #[rustfmt::skip]
+    const TABLE: [[i16; 18]; 17] = [
+        [128, 105, 85, 68, 52, 36, 21, 6, -9, -24, -41, -58, -77, -97, -119, -145, -174, 155], // -80
+        [88, 73, 62, 52, 41, 29, 16, 4, -8, -21, -35, -51, -67, -83, -101, -126, 177, 114], // -70
+        [50, 47, 44, 40, 33, 23, 11, 0, -10, -20, -33, -48, -62, -72, -76, -64, 26, 49], // -60
+        [31, 32, 32, 31, 27, 18, 4, -8, -15, -22, -34, -48, -57, -58, -46, -15, 14, 27], // -50
+        [22, 23, 23, 23, 21, 12, -3, -17, -22, -24, -33, -43, -47, -41, -22, -3, 10, 19], // -40
+        [17, 17, 17, 17, 16, 7, -10, -24, -26, -22, -23, -30, -32, -23, -9, 1, 8, 14], // -30
+        [13, 14, 13, 13, 11, 3, -14, -25, -23, -15, -10, -14, -18, -12, -3, 2, 6, 11], // -20
+        [11, 11, 11, 10, 8, -1, -16, -23, -18, -9, -2, -4, -9, -6, -1, 1, 4, 9], // -10
+        [10, 10, 9, 9, 6, -3, -16, -20, -13, -5, 1, 0, -4, -3, 0, 0, 2, 7], // 0
+        [9, 9, 9, 9, 5, -5, -16, -17, -9, -2, 2, 2, -1, -2, -1, -1, 0, 6], // 10
+        [8, 9, 10, 10, 5, -6, -16, -14, -7, -1, 3, 3, 1, 0, -1, -3, -2, 3], // 20
+        [6, 10, 12, 11, 5, -7, -16, -13, -6, 0, 4, 4, 3, 1, -2, -6, -6, 0], // 30
+        [5, 11, 15, 13, 5, -9, -17, -13, -5, 1, 4, 6, 6, 4, -2, -8, -9, -3], // 40
+        [3, 12, 17, 16, 5, -12, -19, -15, -7, 0, 6, 9, 11, 8, -1, -11, -13, -6], // 50
+        [2, 12, 19, 19, 4, -18, -25, -20, -10, -1, 7, 14, 18, 15, 1, -13, -15, -9], // 60
+        [1, 12, 20, 20, -2, -29, -33, -26, -14, -2, 10, 20, 27, 26, 9, -13, -17, -10], // 70
+        [1, 12, 18, 6, -36, -50, -44, -32, -18, -3, 11, 25, 37, 44, 37, 6, -11, -8], // 80
+    ];
+
+    const LATITUDE_STEP: f32 = 10.0;
+    const LONGITUDE_STEP: f32 = 20.0;
+
+    /// Approximate declination in degrees at the given latitude (north positive) and longitude
+    /// (east positive), both in degrees.
+    ///
+    /// Interpolates bilinearly between the four surrounding grid points. Latitudes beyond 80
+    /// degrees are clamped to the edge of the grid.
+    pub fn lookup(latitude: f32, longitude: f32) -> f32 {
+        let rows = TABLE.len();
+        let cols = TABLE[0].len();
+
+        let row = ((latitude + 80.0) / LATITUDE_STEP).clamp(0.0, (rows - 1) as f32);
+        let col = modulo((longitude + 180.0) / LONGITUDE_STEP, cols as f32);
+        let (r0, c0) = ((row as usize).min(rows - 2), col as usize % cols);
+        let (r1, c1) = (r0 + 1, (c0 + 1) % cols);
+        let (fr, fc) = (row - r0 as f32, col - c0 as f32);
+
+        // Near the magnetic poles neighbouring points can be more than half a turn apart, so
+        // interpolate the angles relative to one corner rather than the raw values.
+        let base = TABLE[r0][c0] as f32;
+        let corner = |r: usize, c: usize| base + difference(TABLE[r][c] as f32, base);
+        let value = (corner(r0, c0) * (1.0 - fc) + corner(r0, c1) * fc) * (1.0 - fr)
+            + (corner(r1, c0) * (1.0 - fc) + corner(r1, c1) * fc) * fr;
+        modulo(value + 180.0, 360.0) - 180.0
+    }
+
+    /// `a - b` in degrees, taking the shorter way around the circle.
+    fn difference(a: f32, b: f32) -> f32 {
+        modulo(a - b + 180.0, 360.0) - 180.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::f32::consts::PI;
+
+    #[test]
+    fn east_declination_turns_heading_counter_clockwise() {
+        assert!((true_heading(0.0, 10.0) - 10.0_f32.to_radians()).abs() < 1e-6);
+        assert!((true_heading(0.5, -5.0) - (0.5 - 5.0_f32.to_radians())).abs() < 1e-6);
+        assert!((true_heading(1.0, 0.0) - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn result_wraps_around() {
+        let heading = true_heading(PI - 0.1, 20.0);
+        assert!((heading - (-PI - 0.1 + 20.0_f32.to_radians())).abs() < 1e-5);
+
+        let heading = true_heading(-PI + 0.1, -20.0);
+        assert!((heading - (PI + 0.1 - 20.0_f32.to_radians())).abs() < 1e-5);
+
+        for magnetic in [-3.0_f32, -1.0, 0.0, 2.0, PI] {
+            for declination in [-179.0, -30.0, 0.0, 45.0, 180.0] {
+                let heading = true_heading(magnetic, declination);
+                assert!(
+                    (-PI..=PI).contains(&heading),
+                    "{} {}",
+                    magnetic,
+                    declination
+                );
+            }
+        }
+    }
+
+    #[cfg(feature = "declination-table")]
+    mod table {
+        use super::super::lookup;
+
+        fn assert_close(latitude: f32, longitude: f32, expected: f32, tolerance: f32) {
+            let declination = lookup(latitude, longitude);
+            assert!(
+                (declination - expected).abs() < tolerance,
+                "{}, {}: got {}, expected {}",
+                latitude,
+                longitude,
+                declination,
+                expected
+            );
+        }
+
+        #[test]
+        fn grid_points_are_exact() {
+            assert_close(0.0, -180.0, 10.0, 1e-4);
+            assert_close(50.0, 0.0, 0.0, 1e-4);
+            assert_close(-30.0, 140.0, 8.0, 1e-4);
+            assert_close(80.0, 160.0, -8.0, 1e-4);
+        }
+
+        #[test]
+        fn known_places() {
+            // 2020 declinations from the full model
+            assert_close(51.5, -0.1, 0.2, 3.0); // London
+            assert_close(47.6, -122.3, 15.5, 3.0); // Seattle
+            assert_close(40.7, -74.0, -12.9, 3.0); // New York
+            assert_close(-33.9, 151.2, 12.6, 3.0); // Sydney
+            assert_close(35.7, 139.7, -7.6, 3.0); // Tokyo
+        }
+
+        #[test]
+        fn longitude_wraps_around() {
+            assert_close(10.0, 180.0, lookup(10.0, -180.0), 1e-4);
+            assert_close(10.0, 170.0, lookup(10.0, -190.0), 1e-4);
+            // Between the last and the first column
+            assert_close(0.0, 170.0, 8.5, 1e-4);
+        }
+
+        #[test]
+        fn polar_latitudes_are_clamped() {
+            assert_close(90.0, 0.0, lookup(80.0, 0.0), 1e-4);
+            assert_close(-90.0, 0.0, lookup(-80.0, 0.0), 1e-4);
+        }
+
+        #[test]
+        fn interpolation_takes_the_short_way_around() {
+            // -174 and 155 are 29 degrees apart across the +-180 seam, not 329
+            let declination = lookup(-80.0, 150.0);
+            assert!(declination.abs() > 165.0, "got {}", declination);
+        }
+    }
+}
@@ -0,0 +1,252 @@
+//! Steadying the heading before it is shown.
+//!
+//! Every sample is noisy, and close to the boundary between two sectors quantising each one on
+//! its own makes the display flicker between them. [`HeadingFilter`] low-pass filters the
+//! heading and [`Hysteresis`] makes the displayed sector stick until the heading clearly moved
+//! on.
+use crate::heading::wrap;
+use crate::rose::sector;
+use core::f32::consts::PI;
+use libm::{atan2f, cosf, fabsf, sinf};
+
+/// Exponential moving average of headings.
+///
+/// Averaging the angles themselves breaks down at `PI`, where a heading of `PI - 0.1` and one of
+/// `-PI + 0.1` would average to 0, pointing the opposite way. Instead the headings are averaged as
+/// unit vectors and the angle is taken from the result, the circular mean.
+#[derive(Debug, Clone, Copy)]
+pub struct HeadingFilter {
+    smoothing: f32,
+    cos: f32,
+    sin: f32,
+    primed: bool,
+}
+
+impl HeadingFilter {
+    /// `smoothing` is the weight of the history, between 0 (no filtering at all) and 1 (ignore new
+    /// samples). With samples arriving at 10Hz, 0.7 settles within about a second.
+    pub fn new(smoothing: f32) -> Self {
+        HeadingFilter {
+            smoothing: smoothing.clamp(0.0, 1.0),
+            cos: 0.0,
+            sin: 0.0,
+            primed: false,
+        }
+    }
+
+    /// Adds a sample and returns the filtered heading. The first sample is taken as is.
+    pub fn update(&mut self, heading: f32) -> f32 {
+        let (sin, cos) = (sinf(heading), cosf(heading));
+        if self.primed {
+            self.cos = self.smoothing * self.cos + (1.0 - self.smoothing) * cos;
+            self.sin = self.smoothing * self.sin + (1.0 - self.smoothing) * sin;
+        } else {
+            self.cos = cos;
+            self.sin = sin;
+            self.primed = true;
+        }
+
+        if self.cos == 0.0 && self.sin == 0.0 {
+            // Exactly opposite samples cancelled out, there is no mean. Follow the latest one.
+            self.cos = cos;
+            self.sin = sin;
+        }
+        self.heading().unwrap_or(heading)
+    }
+
+    /// The filtered heading, `None` before the first sample.
+    pub fn heading(&self) -> Option<f32> {
+        self.primed.then(|| atan2f(self.sin, self.cos))
+    }
+
+    /// Forgets the history, the next sample is taken as is.
+    pub fn reset(&mut self) {
+        self.primed = false;
+    }
+}
+
+/// Quantises headings into sectors like [`sector`], but only leaves the current sector once the
+/// heading is more than `margin` radians beyond its edge.
+#[derive(Debug, Clone, Copy)]
+pub struct Hysteresis {
+    sectors: usize,
+    margin: f32,
+    current: Option<usize>,
+}
+
+impl Hysteresis {
+    /// `margin` should stay below half a sector, otherwise a heading could be closer to the
+    /// middle of a neighbouring sector and still not switch to it.
+    pub fn new(sectors: usize, margin: f32) -> Self {
+        Hysteresis {
+            sectors,
+            margin,
+            current: None,
+        }
+    }
+
+    /// Returns the sector to show for `heading`.
+    pub fn update(&mut self, heading: f32) -> usize {
+        let width = 2.0 * PI / self.sectors as f32;
+        let next = match self.current {
+            Some(current) => {
+                let center = current as f32 * width;
+                if fabsf(wrap(heading - center)) > width / 2.0 + self.margin {
+                    sector(heading, self.sectors)
+                } else {
+                    current
+                }
+            }
+            None => sector(heading, self.sectors),
+        };
+        self.current = Some(next);
+        next
+    }
+
+    /// The sector returned by the last update.
+    pub fn current(&self) -> Option<usize> {
+        self.current
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic noise in `-amplitude..amplitude`.
+    struct Noise(u32);
+
+    impl Noise {
+        fn next(&mut self, amplitude: f32) -> f32 {
+            self.0 = self.0.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            ((self.0 >> 8) as f32 / (1 << 24) as f32 * 2.0 - 1.0) * amplitude
+        }
+    }
+
+    fn angle_difference(a: f32, b: f32) -> f32 {
+        fabsf(wrap(a - b))
+    }
+
+    fn changes(sectors: impl Iterator<Item = usize>) -> usize {
+        let mut changes = 0;
+        let mut last = None;
+        for sector in sectors {
+            if last.is_some() && last != Some(sector) {
+                changes += 1;
+            }
+            last = Some(sector);
+        }
+        changes
+    }
+
+    #[test]
+    fn first_sample_is_passed_through() {
+        let mut filter = HeadingFilter::new(0.9);
+        assert_eq!(filter.heading(), None);
+        assert!((filter.update(1.2) - 1.2).abs() < 1e-6);
+    }
+
+    #[test]
+    fn mean_across_the_wraparound() {
+        let mut filter = HeadingFilter::new(0.5);
+        let mut noise = Noise(1);
+        for i in 0..200 {
+            let sample = if i % 2 == 0 { PI - 0.1 } else { -PI + 0.1 };
+            let heading = filter.update(wrap(sample + noise.next(0.05)));
+            if i > 10 {
+                assert!(angle_difference(heading, PI) < 0.1, "got {}", heading);
+            }
+        }
+    }
+
+    #[test]
+    fn noise_is_reduced() {
+        let mut filter = HeadingFilter::new(0.8);
+        let mut noise = Noise(7);
+        let mut raw = 0.0;
+        let mut filtered = 0.0;
+        for i in 0..1000 {
+            let sample = wrap(2.0 + noise.next(0.3));
+            let heading = filter.update(sample);
+            if i > 20 {
+                raw += angle_difference(sample, 2.0);
+                filtered += angle_difference(heading, 2.0);
+            }
+        }
+        assert!(filtered < raw / 2.0, "raw {} filtered {}", raw, filtered);
+    }
+
+    #[test]
+    fn follows_a_turn() {
+        let mut filter = HeadingFilter::new(0.7);
+        for _ in 0..10 {
+            filter.update(0.0);
+        }
+        let mut heading = 0.0;
+        for _ in 0..30 {
+            heading = filter.update(1.5);
+        }
+        assert!(angle_difference(heading, 1.5) < 0.01);
+    }
+
+    #[test]
+    fn opposite_samples_do_not_get_stuck() {
+        let mut filter = HeadingFilter::new(0.5);
+        filter.update(0.0);
+        let heading = filter.update(PI);
+        assert!(heading.is_finite());
+        filter.reset();
+        assert!((filter.update(-1.0) + 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn hysteresis_stops_flicker_at_a_boundary() {
+        // North east starts at PI / 8 with eight sectors
+        let boundary = PI / 8.0;
+        let mut noise = Noise(3);
+        let samples: Vec<f32> = (0..500).map(|_| boundary + noise.next(0.08)).collect();
+
+        let raw = changes(samples.iter().map(|h| sector(*h, 8)));
+        let mut hysteresis = Hysteresis::new(8, 0.1);
+        let held = changes(samples.iter().map(|h| hysteresis.update(*h)));
+
+        assert!(raw > 100, "raw {}", raw);
+        assert_eq!(held, 0);
+    }
+
+    #[test]
+    fn filter_and_hysteresis_together() {
+        let boundary = 5.0 * PI / 8.0;
+        let mut noise = Noise(11);
+        let mut filter = HeadingFilter::new(0.7);
+        let mut hysteresis = Hysteresis::new(16, 0.05);
+        let sectors = (0..500).map(|_| {
+            let heading = filter.update(wrap(boundary + noise.next(0.3)));
+            hysteresis.update(heading)
+        });
+        assert!(changes(sectors) <= 2);
+    }
+
+    #[test]
+    fn hysteresis_switches_once_past_the_margin() {
+        let mut hysteresis = Hysteresis::new(8, 0.1);
+        let boundary = PI / 8.0;
+        assert_eq!(hysteresis.update(0.0), 0);
+        assert_eq!(hysteresis.update(boundary + 0.05), 0);
+        assert_eq!(hysteresis.update(boundary + 0.15), 1);
+        assert_eq!(hysteresis.update(boundary - 0.05), 1);
+        assert_eq!(hysteresis.update(boundary - 0.15), 0);
+        assert_eq!(hysteresis.current(), Some(0));
+    }
+
+    #[test]
+    fn hysteresis_wraps_around() {
+        let mut hysteresis = Hysteresis::new(8, 0.1);
+        assert_eq!(hysteresis.update(PI), 4);
+        // Just past the edge of west on the other side of the wraparound
+        assert_eq!(hysteresis.update(-7.0 * PI / 8.0 + 0.05), 4);
+        assert_eq!(hysteresis.update(-7.0 * PI / 8.0 + 0.15), 5);
+        // A big jump switches straight away
+        assert_eq!(hysteresis.update(0.0), 0);
+    }
+}
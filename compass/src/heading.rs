@@ -0,0 +1,160 @@
+//! Turning magnetometer samples into a heading.
+//!
+//! Headings are the angle of the horizontal part of the magnetic field, measured counter
+//! clockwise from the x axis of the board in radians, in the range `-PI..=PI`. Both sensors are
+//! expected in the same (sensor) frame, with the accelerometer reading about +1g on z while the
+//! board lies flat.
+use crate::Measurement;
+use core::f32::consts::PI;
+use libm::{atan2f, cosf, fmodf, sinf};
+
+/// Orientation of the board relative to the horizontal plane, in radians.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Attitude {
+    /// Rotation around the x axis.
+    pub roll: f32,
+    /// Rotation around the y axis.
+    pub pitch: f32,
+}
+
+/// Derives roll and pitch from the direction of gravity. Only meaningful while the board is not
+/// being accelerated otherwise.
+pub fn attitude(accel: &Measurement) -> Attitude {
+    let (x, y, z) = (accel.x as f32, accel.y as f32, accel.z as f32);
+    let roll = atan2f(y, z);
+    let pitch = atan2f(-x, y * sinf(roll) + z * cosf(roll));
+    Attitude { roll, pitch }
+}
+
+/// Heading of a board lying flat.
+pub fn heading(mag: &Measurement) -> f32 {
+    atan2f(mag.y as f32, mag.x as f32)
+}
+
+/// Heading of a tilted board: the magnetic field is rotated back into the horizontal plane using
+/// the attitude from `accel` before taking its angle. Equal to [`heading`] when the board is flat.
+pub fn tilt_compensated_heading(accel: &Measurement, mag: &Measurement) -> f32 {
+    let Attitude { roll, pitch } = attitude(accel);
+    let (x, y, z) = (mag.x as f32, mag.y as f32, mag.z as f32);
+    let (sin_roll, cos_roll) = (sinf(roll), cosf(roll));
+    let (sin_pitch, cos_pitch) = (sinf(pitch), cosf(pitch));
+
+    let horizontal_x = x * cos_pitch + (y * sin_roll + z * cos_roll) * sin_pitch;
+    let horizontal_y = y * cos_roll - z * sin_roll;
+    atan2f(horizontal_y, horizontal_x)
+}
+
+/// Brings any angle into the range `-PI..=PI` used for headings.
+pub fn wrap(angle: f32) -> f32 {
+    let wrapped = modulo(angle + PI, 2.0 * PI) - PI;
+    if wrapped == -PI {
+        PI
+    } else {
+        wrapped
+    }
+}
+
+/// Remainder of `a / b` with the sign of `b`, `rem_euclid` is not available without std.
+pub(crate) fn modulo(a: f32, b: f32) -> f32 {
+    let r = fmodf(a, b);
+    if r < 0.0 {
+        r + b
+    } else {
+        r
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Matrix = [[f32; 3]; 3];
+
+    fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+        let mut m = [[0.0; 3]; 3];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+            }
+        }
+        m
+    }
+
+    /// Board to world rotation for the given yaw, pitch and roll (applied in that order).
+    fn rotation(yaw: f32, pitch: f32, roll: f32) -> Matrix {
+        let (sy, cy) = (yaw.sin(), yaw.cos());
+        let (sp, cp) = (pitch.sin(), pitch.cos());
+        let (sr, cr) = (roll.sin(), roll.cos());
+        let z = [[cy, -sy, 0.0], [sy, cy, 0.0], [0.0, 0.0, 1.0]];
+        let y = [[cp, 0.0, sp], [0.0, 1.0, 0.0], [-sp, 0.0, cp]];
+        let x = [[1.0, 0.0, 0.0], [0.0, cr, -sr], [0.0, sr, cr]];
+        multiply(&z, &multiply(&y, &x))
+    }
+
+    /// Expresses a world frame vector in the frame of a board with the given orientation.
+    fn to_board(r: &Matrix, v: [f32; 3]) -> Measurement {
+        let m = |i: usize| (0..3).map(|k| r[k][i] * v[k]).sum::<f32>() as i32;
+        Measurement {
+            x: m(0),
+            y: m(1),
+            z: m(2),
+        }
+    }
+
+    fn angle_difference(a: f32, b: f32) -> f32 {
+        let d = (a - b).rem_euclid(2.0 * PI);
+        d.min(2.0 * PI - d)
+    }
+
+    // Roughly the field in central Europe in nT: pointing north and steeply down
+    const FIELD: [f32; 3] = [21_000.0, 0.0, -44_000.0];
+    const GRAVITY: [f32; 3] = [0.0, 0.0, 1_000.0];
+
+    #[test]
+    fn flat_board_matches_plain_heading() {
+        for yaw in [-2.5_f32, -1.0, 0.0, 0.3, 1.7, 3.0] {
+            let r = rotation(yaw, 0.0, 0.0);
+            let accel = to_board(&r, GRAVITY);
+            let mag = to_board(&r, FIELD);
+
+            assert!(angle_difference(tilt_compensated_heading(&accel, &mag), heading(&mag)) < 1e-3);
+            assert!(angle_difference(heading(&mag), -yaw) < 1e-3);
+        }
+    }
+
+    #[test]
+    fn attitude_is_recovered() {
+        let r = rotation(0.8, 0.4, -0.3);
+        let attitude = attitude(&to_board(&r, GRAVITY));
+        assert!((attitude.pitch - 0.4).abs() < 1e-2);
+        assert!((attitude.roll + 0.3).abs() < 1e-2);
+    }
+
+    #[test]
+    fn tilt_does_not_change_heading() {
+        for yaw in [-2.5_f32, -1.0, 0.0, 0.3, 1.7, 3.0] {
+            for (pitch, roll) in [(0.3, 0.0), (0.0, -0.4), (-0.5, 0.6), (0.7, 0.7)] {
+                let r = rotation(yaw, pitch, roll);
+                let accel = to_board(&r, GRAVITY);
+                let mag = to_board(&r, FIELD);
+
+                let compensated = tilt_compensated_heading(&accel, &mag);
+                assert!(
+                    angle_difference(compensated, -yaw) < 1.0_f32.to_radians(),
+                    "yaw {} pitch {} roll {}: got {}",
+                    yaw,
+                    pitch,
+                    roll,
+                    compensated
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn uncompensated_heading_is_off_when_tilted() {
+        let r = rotation(1.0, 0.0, 0.5);
+        let mag = to_board(&r, FIELD);
+        assert!(angle_difference(heading(&mag), -1.0) > 0.1);
+    }
+}
@@ -0,0 +1,44 @@
+//! Compass math shared by the applications in this workspace.
+#![cfg_attr(not(test), no_std)]
+#![deny(unsafe_code)]
+
+pub mod calibration;
+pub mod declination;
+pub mod filter;
+pub mod heading;
+mod linalg;
+pub mod rose;
+pub mod storage;
+
+/// A sample from a three axis sensor.
+///
+/// Mirrors `lsm303agr::Measurement` so the math does not depend on the sensor driver. Enable the
+/// `lsm303agr` feature to get conversions between the two.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Measurement {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+#[cfg(feature = "lsm303agr")]
+impl From<lsm303agr::Measurement> for Measurement {
+    fn from(m: lsm303agr::Measurement) -> Self {
+        Measurement {
+            x: m.x,
+            y: m.y,
+            z: m.z,
+        }
+    }
+}
+
+#[cfg(feature = "lsm303agr")]
+impl From<Measurement> for lsm303agr::Measurement {
+    fn from(m: Measurement) -> Self {
+        lsm303agr::Measurement {
+            x: m.x,
+            y: m.y,
+            z: m.z,
+        }
+    }
+}
@@ -0,0 +1,164 @@
+//! The little bit of dense linear algebra the calibration fit needs. Everything works on small,
+//! fixed size arrays so it stays allocation free.
+#![allow(clippy::needless_range_loop)]
+
+use libm::fabs;
+
+pub type Vector3 = [f64; 3];
+pub type Matrix3 = [[f64; 3]; 3];
+
+pub const IDENTITY: Matrix3 = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+
+/// Solves `a * x = b` using Gaussian elimination with partial pivoting. Returns `None` if `a` is
+/// (numerically) singular.
+pub fn solve<const N: usize>(mut a: [[f64; N]; N], mut b: [f64; N]) -> Option<[f64; N]> {
+    // Anything below this relative to the largest pivot is treated as zero.
+    const EPSILON: f64 = 1e-12;
+
+    let mut largest = 0.0;
+    for row in a.iter() {
+        for value in row.iter() {
+            largest = f64::max(largest, fabs(*value));
+        }
+    }
+    if largest == 0.0 {
+        return None;
+    }
+
+    for col in 0..N {
+        let mut pivot = col;
+        for row in col + 1..N {
+            if fabs(a[row][col]) > fabs(a[pivot][col]) {
+                pivot = row;
+            }
+        }
+        if fabs(a[pivot][col]) <= EPSILON * largest {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+
+        for row in col + 1..N {
+            let factor = a[row][col] / a[col][col];
+            for k in col..N {
+                a[row][k] -= factor * a[col][k];
+            }
+            b[row] -= factor * b[col];
+        }
+    }
+
+    let mut x = [0.0; N];
+    for row in (0..N).rev() {
+        let mut sum = b[row];
+        for k in row + 1..N {
+            sum -= a[row][k] * x[k];
+        }
+        x[row] = sum / a[row][row];
+    }
+    Some(x)
+}
+
+pub fn mul_vector(m: &Matrix3, v: &Vector3) -> Vector3 {
+    [
+        m[0][0] * v[0] + m[0][1] * v[1] + m[0][2] * v[2],
+        m[1][0] * v[0] + m[1][1] * v[1] + m[1][2] * v[2],
+        m[2][0] * v[0] + m[2][1] * v[1] + m[2][2] * v[2],
+    ]
+}
+
+pub fn dot(a: &Vector3, b: &Vector3) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+/// Eigen decomposition of a symmetric matrix using cyclic Jacobi rotations.
+///
+/// Returns the eigenvalues and a matrix whose columns are the matching eigenvectors.
+pub fn symmetric_eigen(mut a: Matrix3) -> (Vector3, Matrix3) {
+    const SWEEPS: usize = 50;
+
+    let mut v = IDENTITY;
+    for _ in 0..SWEEPS {
+        let off_diagonal = a[0][1] * a[0][1] + a[0][2] * a[0][2] + a[1][2] * a[1][2];
+        if off_diagonal < 1e-30 {
+            break;
+        }
+
+        for (p, q) in [(0, 1), (0, 2), (1, 2)] {
+            if a[p][q] == 0.0 {
+                continue;
+            }
+            // Pick the rotation angle which zeroes a[p][q], see Numerical Recipes section 11.1.
+            let theta = (a[q][q] - a[p][p]) / (2.0 * a[p][q]);
+            let sign = if theta >= 0.0 { 1.0 } else { -1.0 };
+            let t = sign / (fabs(theta) + libm::sqrt(theta * theta + 1.0));
+            let c = 1.0 / libm::sqrt(t * t + 1.0);
+            let s = t * c;
+
+            for k in 0..3 {
+                let akp = a[k][p];
+                let akq = a[k][q];
+                a[k][p] = c * akp - s * akq;
+                a[k][q] = s * akp + c * akq;
+            }
+            for k in 0..3 {
+                let apk = a[p][k];
+                let aqk = a[q][k];
+                a[p][k] = c * apk - s * aqk;
+                a[q][k] = s * apk + c * aqk;
+            }
+            for row in v.iter_mut() {
+                let vkp = row[p];
+                let vkq = row[q];
+                row[p] = c * vkp - s * vkq;
+                row[q] = s * vkp + c * vkq;
+            }
+        }
+    }
+
+    ([a[0][0], a[1][1], a[2][2]], v)
+}
+
+/// Builds `V * diag(values) * V^T` from an eigen decomposition.
+pub fn from_eigen(values: &Vector3, vectors: &Matrix3) -> Matrix3 {
+    let mut m = [[0.0; 3]; 3];
+    for (i, row) in m.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..3)
+                .map(|k| vectors[i][k] * values[k] * vectors[j][k])
+                .sum();
+        }
+    }
+    m
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn solve_small_system() {
+        let a = [[2.0, 1.0, -1.0], [-3.0, -1.0, 2.0], [-2.0, 1.0, 2.0]];
+        let x = solve(a, [8.0, -11.0, -3.0]).unwrap();
+        for (value, expected) in x.iter().zip([2.0, 3.0, -1.0]) {
+            assert!((value - expected).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn singular_system_is_rejected() {
+        let a = [[1.0, 2.0], [2.0, 4.0]];
+        assert!(solve(a, [1.0, 2.0]).is_none());
+    }
+
+    #[test]
+    fn eigen_decomposition_reconstructs_matrix() {
+        let a = [[4.0, 1.0, 0.5], [1.0, 3.0, -0.25], [0.5, -0.25, 2.0]];
+        let (values, vectors) = symmetric_eigen(a);
+        let b = from_eigen(&values, &vectors);
+        for i in 0..3 {
+            for j in 0..3 {
+                assert!((a[i][j] - b[i][j]).abs() < 1e-9);
+            }
+        }
+    }
+}
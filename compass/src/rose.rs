@@ -0,0 +1,523 @@
+//! Turning a heading into something the 5x5 LED matrix can show.
+//!
+//! Images are indexed `[row][column]` with row 0 at the top, like the images the micro:bit
+//! display takes, and hold brightness levels from 0 (off) to [`MAX_BRIGHTNESS`]. A heading of 0
+//! points to the right of the display and `PI / 2` to the top, see [`crate::heading`].
+use core::f32::consts::PI;
+use libm::{floorf, fmodf, roundf};
+
+pub use matrix::Image;
+
+/// Brightest level the non-blocking display supports.
+pub const MAX_BRIGHTNESS: u8 = 9;
+
+/// Number of points of the compass rose drawn by [`rose`].
+pub const POINTS: usize = 16;
+
+/// The outer ring of the matrix, counter clockwise starting at the right edge, one LED for every
+/// point of the rose.
+const OUTER: [(usize, usize); POINTS] = [
+    (2, 4),
+    (1, 4),
+    (0, 4),
+    (0, 3),
+    (0, 2),
+    (0, 1),
+    (0, 0),
+    (1, 0),
+    (2, 0),
+    (3, 0),
+    (4, 0),
+    (4, 1),
+    (4, 2),
+    (4, 3),
+    (4, 4),
+    (3, 4),
+];
+
+/// The ring around the center LED, in the same order as [`OUTER`].
+const INNER: [(usize, usize); 8] = [
+    (2, 3),
+    (1, 3),
+    (1, 2),
+    (1, 1),
+    (2, 1),
+    (3, 1),
+    (3, 2),
+    (3, 3),
+];
+
+const CENTER: (usize, usize) = (2, 2);
+
+/// Splits the circle into `sectors` equal slices and returns the one `heading` falls into.
+///
+/// Sector 0 is centered on a heading of 0 and the numbers grow counter clockwise, so with 8
+/// sectors 0 is east, 2 north and 5 south west. Headings exactly on a boundary go to the sector
+/// counter clockwise of it.
+pub fn sector(heading: f32, sectors: usize) -> usize {
+    let width = 2.0 * PI / sectors as f32;
+    let index = floorf(heading / width + 0.5);
+    (fmodf(index, sectors as f32) + sectors as f32) as usize % sectors
+}
+
+/// Glyph for one of the [`POINTS`] points of the compass rose, as returned by
+/// `sector(heading, POINTS)`: a spoke from the center to the edge of the matrix.
+pub fn rose(point: usize) -> Image {
+    let mut image = [[0; 5]; 5];
+    let point = point % POINTS;
+    // Points between two LEDs of the inner ring bend towards the nearer cardinal direction, that
+    // keeps north-north-east and east-north-east apart.
+    let inner = if point % 4 == 3 {
+        (point / 2 + 1) % INNER.len()
+    } else {
+        point / 2
+    };
+
+    for (row, col) in [CENTER, INNER[inner], OUTER[point]] {
+        image[row][col] = MAX_BRIGHTNESS;
+    }
+    image
+}
+
+/// A needle pointing at `heading` at any angle rather than only a fixed number of points. Where
+/// it falls between two LEDs both are lit, each with a brightness according to how close the
+/// needle passes by.
+pub fn needle(heading: f32) -> Image {
+    let mut image = [[0; 5]; 5];
+    image[CENTER.0][CENTER.1] = MAX_BRIGHTNESS;
+    spread(&mut image, &INNER, heading);
+    spread(&mut image, &OUTER, heading);
+    image
+}
+
+/// Lights the two LEDs of `ring` on either side of `heading`.
+fn spread(image: &mut Image, ring: &[(usize, usize)], heading: f32) {
+    let position = fmodf(heading / (2.0 * PI), 1.0) * ring.len() as f32;
+    let position = if position < 0.0 {
+        position + ring.len() as f32
+    } else {
+        position
+    };
+    let before = floorf(position);
+    let fraction = position - before;
+    let before = before as usize % ring.len();
+    let after = (before + 1) % ring.len();
+
+    let level = |weight: f32| roundf(weight * MAX_BRIGHTNESS as f32) as u8;
+    let (row, col) = ring[before];
+    image[row][col] = image[row][col].max(level(1.0 - fraction));
+    let (row, col) = ring[after];
+    image[row][col] = image[row][col].max(level(fraction));
+}
+
+/// The arrow all the others are turns of, pointing up.
+pub const NORTH: Image = {
+    const X: u8 = MAX_BRIGHTNESS;
+    [
+        [0, 0, X, 0, 0],
+        [0, X, X, X, 0],
+        [X, 0, X, 0, X],
+        [0, 0, X, 0, 0],
+        [0, 0, X, 0, 0],
+    ]
+};
+
+/// Arrows for the eight sectors returned by `sector(heading, 8)`, east first and then counter
+/// clockwise.
+pub const ARROWS: [Image; 8] = [
+    rotate(&NORTH, 6),
+    rotate(&NORTH, 7),
+    NORTH,
+    rotate(&NORTH, 1),
+    rotate(&NORTH, 2),
+    rotate(&NORTH, 3),
+    rotate(&NORTH, 4),
+    rotate(&NORTH, 5),
+];
+
+/// Turns an image counter clockwise by `eighths` eighths of a full turn.
+///
+/// A 5x5 grid can't be turned by 45 degrees exactly. Every LED moves along its ring around the
+/// center, by one position on the inner ring and two on the outer one, which keeps straight
+/// lines through the center straight. An LED in the middle of an outer edge would then end up in
+/// a corner, further out than it was, so unless it is the end of such a line it goes to the
+/// corner of the inner ring instead, like a true 45 degree turn would. Whole quarter turns are
+/// exact, an odd number of eighths adds a single eighth to them.
+pub const fn rotate(image: &Image, eighths: usize) -> Image {
+    let image = if eighths % 2 == 1 {
+        eighth(image)
+    } else {
+        *image
+    };
+    shift(&image, eighths / 2 * 2)
+}
+
+/// One eighth of [`rotate`].
+const fn eighth(image: &Image) -> Image {
+    let mut turned = shift(image, 1);
+    let mut side = 0;
+    while side < 4 {
+        // The inner LED between the center and the middle of this edge
+        let (row, col) = INNER[2 * side];
+        if image[row][col] == 0 {
+            let (from_row, from_col) = OUTER[4 * side];
+            let (row, col) = OUTER[4 * side + 2];
+            turned[row][col] = 0;
+            // Free, it would have come from the unlit inner LED
+            let (row, col) = INNER[2 * side + 1];
+            turned[row][col] = image[from_row][from_col];
+        }
+        side += 1;
+    }
+    turned
+}
+
+/// Moves every LED along its ring around the center, `steps` positions on the inner ring and
+/// twice as many on the outer one.
+const fn shift(image: &Image, steps: usize) -> Image {
+    let mut shifted = [[0; 5]; 5];
+    shifted[CENTER.0][CENTER.1] = image[CENTER.0][CENTER.1];
+
+    let mut i = 0;
+    while i < INNER.len() {
+        let (row, col) = INNER[i];
+        let (to_row, to_col) = INNER[(i + steps) % INNER.len()];
+        shifted[to_row][to_col] = image[row][col];
+        i += 1;
+    }
+    let mut i = 0;
+    while i < OUTER.len() {
+        let (row, col) = OUTER[i];
+        let (to_row, to_col) = OUTER[(i + 2 * steps) % OUTER.len()];
+        shifted[to_row][to_col] = image[row][col];
+        i += 1;
+    }
+    shifted
+}
+
+/// Flips an image left to right.
+pub const fn mirror(image: &Image) -> Image {
+    let mut mirrored = [[0; 5]; 5];
+    let mut row = 0;
+    while row < 5 {
+        let mut col = 0;
+        while col < 5 {
+            mirrored[row][4 - col] = image[row][col];
+            col += 1;
+        }
+        row += 1;
+    }
+    mirrored
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The if-ladder `led_compass` used before, returning the sector number.
+    fn eight_sectors(theta: f32) -> usize {
+        if theta < -7. * PI / 8. {
+            4
+        } else if theta < -5. * PI / 8. {
+            5
+        } else if theta < -3. * PI / 8. {
+            6
+        } else if theta < -PI / 8. {
+            7
+        } else if theta < PI / 8. {
+            0
+        } else if theta < 3. * PI / 8. {
+            1
+        } else if theta < 5. * PI / 8. {
+            2
+        } else if theta < 7. * PI / 8. {
+            3
+        } else {
+            4
+        }
+    }
+
+    fn lit(image: &Image) -> usize {
+        image.iter().flatten().filter(|level| **level > 0).count()
+    }
+
+    #[test]
+    fn matches_the_old_eight_way_mapping() {
+        for step in -1000..=1000 {
+            let theta = step as f32 * PI / 1000.0;
+            assert_eq!(sector(theta, 8), eight_sectors(theta), "theta {}", theta);
+        }
+    }
+
+    #[test]
+    fn sixteen_sectors() {
+        let width = 2.0 * PI / 16.0;
+        assert_eq!(sector(0.0, 16), 0);
+        assert_eq!(sector(PI / 2.0, 16), 4);
+        assert_eq!(sector(PI, 16), 8);
+        assert_eq!(sector(-PI, 16), 8);
+        assert_eq!(sector(-PI / 2.0, 16), 12);
+        assert_eq!(sector(0.49 * width, 16), 0);
+        assert_eq!(sector(0.51 * width, 16), 1);
+        assert_eq!(sector(-0.51 * width, 16), 15);
+    }
+
+    #[test]
+    fn sector_ignores_whole_turns() {
+        for sectors in [4, 8, 16] {
+            for step in -20..20 {
+                let theta = step as f32 * 0.3;
+                assert_eq!(sector(theta, sectors), sector(theta + 2.0 * PI, sectors));
+                assert_eq!(sector(theta, sectors), sector(theta - 4.0 * PI, sectors));
+            }
+        }
+    }
+
+    #[test]
+    fn rose_points_are_distinct_spokes() {
+        for point in 0..POINTS {
+            let image = rose(point);
+            assert_eq!(lit(&image), 3, "point {}", point);
+            assert_eq!(image[2][2], MAX_BRIGHTNESS);
+            for other in point + 1..POINTS {
+                assert_ne!(image, rose(other), "{} and {}", point, other);
+            }
+        }
+    }
+
+    #[test]
+    fn cardinal_rose_points() {
+        let north = rose(4);
+        assert_eq!(north[0][2], MAX_BRIGHTNESS);
+        assert_eq!(north[1][2], MAX_BRIGHTNESS);
+        let east = rose(0);
+        assert_eq!(east[2][4], MAX_BRIGHTNESS);
+        assert_eq!(east[2][3], MAX_BRIGHTNESS);
+        let south_west = rose(10);
+        assert_eq!(south_west[4][0], MAX_BRIGHTNESS);
+        assert_eq!(south_west[3][1], MAX_BRIGHTNESS);
+    }
+
+    #[test]
+    fn needle_on_a_point_matches_the_rose() {
+        for point in (0..POINTS).step_by(2) {
+            let heading = point as f32 * 2.0 * PI / POINTS as f32;
+            assert_eq!(needle(heading), rose(point), "point {}", point);
+        }
+    }
+
+    #[test]
+    fn needle_between_points_is_shared() {
+        // Halfway between east and east-north-east on the outer ring
+        let image = needle(PI / 16.0);
+        assert_eq!(image[2][4], 5);
+        assert_eq!(image[1][4], 5);
+        // A quarter of the way from east to north east on the inner ring
+        assert_eq!(image[2][3], 7);
+        assert_eq!(image[1][3], 2);
+    }
+
+    #[test]
+    fn needle_wraps_around() {
+        assert_eq!(needle(-0.1), needle(2.0 * PI - 0.1));
+        let image = needle(-PI / 16.0);
+        assert_eq!(image[2][4], 5);
+        assert_eq!(image[3][4], 5);
+    }
+
+    /// Quarter turn counter clockwise, the plain way.
+    fn quarter_turn(image: &Image) -> Image {
+        let mut turned = [[0; 5]; 5];
+        for (row, line) in image.iter().enumerate() {
+            for (col, level) in line.iter().enumerate() {
+                turned[4 - col][row] = *level;
+            }
+        }
+        turned
+    }
+
+    /// Flips an image along the diagonal from the bottom left to the top right corner.
+    fn transpose_anti(image: &Image) -> Image {
+        let mut flipped = [[0; 5]; 5];
+        for (row, line) in image.iter().enumerate() {
+            for (col, level) in line.iter().enumerate() {
+                flipped[4 - col][4 - row] = *level;
+            }
+        }
+        flipped
+    }
+
+    /// Flips an image top to bottom.
+    fn upside_down(image: &Image) -> Image {
+        let mut flipped = *image;
+        flipped.reverse();
+        flipped
+    }
+
+    #[test]
+    fn two_eighths_are_a_quarter_turn() {
+        let image: Image =
+            core::array::from_fn(|row| core::array::from_fn(|col| (row * 5 + col) as u8));
+        assert_eq!(rotate(&image, 2), quarter_turn(&image));
+        assert_eq!(rotate(&image, 8), image);
+        assert_eq!(rotate(&rotate(&image, 3), 5), image);
+    }
+
+    #[test]
+    fn an_eighth_keeps_lines_through_the_center() {
+        let image: Image =
+            core::array::from_fn(|row| core::array::from_fn(|col| (row * 5 + col) as u8 + 1));
+        assert_eq!(rotate(&image, 1), shift(&image, 1));
+        assert_eq!(rotate(&rotate(&image, 1), 1), rotate(&image, 2));
+    }
+
+    #[test]
+    fn an_eighth_pulls_loose_edge_leds_in() {
+        let mut image = [[0; 5]; 5];
+        image[0][2] = 1;
+        image[2][0] = 2;
+        image[2][1] = 3;
+        let mut turned = [[0; 5]; 5];
+        // Nothing between it and the center, so it goes to the inner corner
+        turned[1][1] = 1;
+        // The end of a line through the center, which stays straight
+        turned[4][0] = 2;
+        turned[3][1] = 3;
+        assert_eq!(rotate(&image, 1), turned);
+    }
+
+    #[test]
+    fn a_full_turn_brings_north_back() {
+        assert_eq!(rotate(&NORTH, 8), NORTH);
+        // Through the other cardinal arrows a quarter at a time. Single eighths don't add up like
+        // that, turning a diagonal arrow by another one pulls its barbs in.
+        let mut arrow = NORTH;
+        for quarter in 1..4 {
+            arrow = rotate(&arrow, 2);
+            assert_eq!(
+                arrow,
+                ARROWS[(2 + 2 * quarter) % 8],
+                "after {} quarters",
+                quarter
+            );
+        }
+        assert_eq!(rotate(&arrow, 2), NORTH);
+    }
+
+    #[test]
+    fn opposite_arrows_are_flipped() {
+        let (east, north, west, south) = (ARROWS[0], ARROWS[2], ARROWS[4], ARROWS[6]);
+        assert_eq!(south, upside_down(&north));
+        assert_eq!(west, mirror(&east));
+        assert_eq!(ARROWS[3], mirror(&ARROWS[1]));
+        assert_eq!(ARROWS[7], upside_down(&ARROWS[1]));
+    }
+
+    #[test]
+    fn south_arrow() {
+        const X: u8 = MAX_BRIGHTNESS;
+        let south = [
+            [0, 0, X, 0, 0],
+            [0, 0, X, 0, 0],
+            [X, 0, X, 0, X],
+            [0, X, X, X, 0],
+            [0, 0, X, 0, 0],
+        ];
+        assert_eq!(ARROWS[6], south);
+    }
+
+    #[test]
+    fn diagonal_arrow() {
+        const X: u8 = MAX_BRIGHTNESS;
+        let north_east = [
+            [0, 0, 0, 0, X],
+            [0, X, X, X, 0],
+            [0, 0, X, X, 0],
+            [0, X, 0, X, 0],
+            [X, 0, 0, 0, 0],
+        ];
+        assert_eq!(ARROWS[1], north_east);
+    }
+
+    /// The cardinal arrows `led_compass` drew by hand, named for the way they point. Its own names
+    /// had east and west the other way around, and south was a hook rather than an arrow, so that
+    /// one is north upside down. Its diagonal arrows weren't turns of its north arrow at all.
+    mod drawn {
+        use crate::rose::Image;
+
+        pub const NORTH: Image = [
+            [0, 0, 1, 0, 0],
+            [0, 1, 1, 1, 0],
+            [1, 0, 1, 0, 1],
+            [0, 0, 1, 0, 0],
+            [0, 0, 1, 0, 0],
+        ];
+        pub const EAST: Image = [
+            [0, 0, 1, 0, 0],
+            [0, 0, 0, 1, 0],
+            [1, 1, 1, 1, 1],
+            [0, 0, 0, 1, 0],
+            [0, 0, 1, 0, 0],
+        ];
+        pub const SOUTH: Image = [
+            [0, 0, 1, 0, 0],
+            [0, 0, 1, 0, 0],
+            [1, 0, 1, 0, 1],
+            [0, 1, 1, 1, 0],
+            [0, 0, 1, 0, 0],
+        ];
+        pub const WEST: Image = [
+            [0, 0, 1, 0, 0],
+            [0, 1, 0, 0, 0],
+            [1, 1, 1, 1, 1],
+            [0, 1, 0, 0, 0],
+            [0, 0, 1, 0, 0],
+        ];
+    }
+
+    #[test]
+    fn cardinal_arrows_are_the_hand_drawn_ones() {
+        let drawn = [drawn::EAST, drawn::NORTH, drawn::WEST, drawn::SOUTH];
+        for (quarter, drawn) in drawn.into_iter().enumerate() {
+            let drawn = drawn.map(|row| row.map(|led| led * MAX_BRIGHTNESS));
+            assert_eq!(ARROWS[2 * quarter], drawn, "sector {}", 2 * quarter);
+        }
+    }
+
+    #[test]
+    fn arrows_are_distinct() {
+        for (i, a) in ARROWS.iter().enumerate() {
+            for b in &ARROWS[i + 1..] {
+                assert_ne!(a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn arrows_are_symmetric() {
+        assert_eq!(mirror(&NORTH), NORTH);
+        // East and west, north east and north west etc. are mirror images
+        for sector in 0..8 {
+            assert_eq!(
+                ARROWS[(12 - sector) % 8],
+                mirror(&ARROWS[sector]),
+                "sector {}",
+                sector
+            );
+        }
+        // The diagonal arrows are symmetric along their own axis
+        assert_eq!(transpose_anti(&ARROWS[1]), ARROWS[1]);
+        assert_eq!(transpose_anti(&ARROWS[5]), ARROWS[5]);
+    }
+
+    #[test]
+    fn arrows_point_the_right_way() {
+        // The tip of each arrow lies on the outer ring in the direction of its sector
+        for (sector, arrow) in ARROWS.iter().enumerate() {
+            let (row, col) = OUTER[2 * sector];
+            assert_eq!(arrow[row][col], MAX_BRIGHTNESS, "sector {}", sector);
+            let (row, col) = OUTER[(2 * sector + 8) % OUTER.len()];
+            assert_eq!(arrow[row][col], MAX_BRIGHTNESS, "tail of sector {}", sector);
+        }
+    }
+}
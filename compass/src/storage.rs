@@ -0,0 +1,200 @@
+//! Binary format used to keep a [`Calibration`] in flash across power cycles.
+//!
+//! All fields are little endian:
+//!
+//! | offset | size | content                                |
+//! |--------|------|----------------------------------------|
+//! | 0      | 4    | magic, `b"CCAL"`                       |
+//! | 4      | 2    | format version, [`VERSION`]            |
+//! | 6      | 2    | payload length in bytes                |
+//! | 8      | 12   | hard-iron offset, x/y/z as `i32`       |
+//! | 20     | 36   | soft-iron matrix, row major `f32`      |
+//! | 56     | 4    | radius as `u32`                        |
+//! | 60     | 4    | CRC-32 (IEEE) of bytes 0 to 59         |
+//!
+//! The record is a multiple of 4 bytes long, so it can be written to the nRF flash word by word.
+use crate::calibration::Calibration;
+use crate::Measurement;
+
+/// Size of an encoded record in bytes.
+pub const RECORD_LEN: usize = 64;
+
+/// Current version of the format. Bump it whenever the layout or the meaning of a field changes,
+/// so that old records are recalculated rather than misread.
+pub const VERSION: u16 = 1;
+
+const MAGIC: [u8; 4] = *b"CCAL";
+const HEADER_LEN: usize = 8;
+const PAYLOAD_LEN: usize = 52;
+const CRC_OFFSET: usize = HEADER_LEN + PAYLOAD_LEN;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    /// No record was ever written, e.g. the flash page is still erased.
+    Missing,
+    /// The record was written by a different version of the firmware.
+    UnsupportedVersion(u16),
+    /// The checksum does not match, the record was damaged or only partially written.
+    Corrupt,
+}
+
+pub fn encode(calibration: &Calibration) -> [u8; RECORD_LEN] {
+    let mut record = [0; RECORD_LEN];
+    let mut offset = 0;
+    let mut put = |bytes: &[u8]| {
+        record[offset..offset + bytes.len()].copy_from_slice(bytes);
+        offset += bytes.len();
+    };
+
+    put(&MAGIC);
+    put(&VERSION.to_le_bytes());
+    put(&(PAYLOAD_LEN as u16).to_le_bytes());
+
+    let hard_iron = calibration.hard_iron();
+    for value in [hard_iron.x, hard_iron.y, hard_iron.z] {
+        put(&value.to_le_bytes());
+    }
+    for row in calibration.soft_iron() {
+        for value in row {
+            put(&value.to_le_bytes());
+        }
+    }
+    put(&calibration.radius().to_le_bytes());
+
+    let crc = crc32(&record[..CRC_OFFSET]);
+    record[CRC_OFFSET..].copy_from_slice(&crc.to_le_bytes());
+    record
+}
+
+pub fn decode(record: &[u8]) -> Result<Calibration, DecodeError> {
+    if record.len() < RECORD_LEN || record[..4] != MAGIC {
+        return Err(DecodeError::Missing);
+    }
+
+    let version = u16::from_le_bytes([record[4], record[5]]);
+    if version != VERSION {
+        return Err(DecodeError::UnsupportedVersion(version));
+    }
+
+    let crc = u32::from_le_bytes(word(record, CRC_OFFSET));
+    if crc != crc32(&record[..CRC_OFFSET]) {
+        return Err(DecodeError::Corrupt);
+    }
+    if u16::from_le_bytes([record[6], record[7]]) as usize != PAYLOAD_LEN {
+        return Err(DecodeError::Corrupt);
+    }
+
+    let hard_iron = Measurement {
+        x: i32::from_le_bytes(word(record, 8)),
+        y: i32::from_le_bytes(word(record, 12)),
+        z: i32::from_le_bytes(word(record, 16)),
+    };
+    let mut soft_iron = [[0.0; 3]; 3];
+    for (i, row) in soft_iron.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = f32::from_le_bytes(word(record, 20 + 4 * (3 * i + j)));
+        }
+    }
+    let radius = u32::from_le_bytes(word(record, 56));
+
+    Ok(Calibration::from_parts(hard_iron, soft_iron, radius))
+}
+
+fn word(record: &[u8], offset: usize) -> [u8; 4] {
+    [
+        record[offset],
+        record[offset + 1],
+        record[offset + 2],
+        record[offset + 3],
+    ]
+}
+
+/// CRC-32 as used by zlib and Ethernet. Bitwise rather than table driven, we only ever run it over
+/// a handful of bytes at boot.
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn calibration() -> Calibration {
+        Calibration::from_parts(
+            Measurement {
+                x: 12_345,
+                y: -6_789,
+                z: 42,
+            },
+            [[1.1, -0.05, 0.02], [-0.05, 0.93, 0.1], [0.02, 0.1, 1.0]],
+            48_000,
+        )
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn round_trip() {
+        let record = encode(&calibration());
+        assert_eq!(decode(&record), Ok(calibration()));
+    }
+
+    #[test]
+    fn layout_is_stable() {
+        let record = encode(&calibration());
+        assert_eq!(&record[..8], &[b'C', b'C', b'A', b'L', 1, 0, 52, 0]);
+        assert_eq!(&record[8..12], &12_345i32.to_le_bytes());
+        assert_eq!(&record[20..24], &1.1f32.to_le_bytes());
+        assert_eq!(&record[56..60], &48_000u32.to_le_bytes());
+    }
+
+    #[test]
+    fn erased_flash_is_missing() {
+        assert_eq!(decode(&[0xFF; RECORD_LEN]), Err(DecodeError::Missing));
+        assert_eq!(decode(&[0x00; RECORD_LEN]), Err(DecodeError::Missing));
+        assert_eq!(
+            decode(&encode(&calibration())[..RECORD_LEN - 1]),
+            Err(DecodeError::Missing)
+        );
+    }
+
+    #[test]
+    fn other_version_is_rejected() {
+        let mut record = encode(&calibration());
+        record[4] = 7;
+        assert_eq!(decode(&record), Err(DecodeError::UnsupportedVersion(7)));
+    }
+
+    #[test]
+    fn flipped_bit_is_corrupt() {
+        for offset in 6..RECORD_LEN {
+            let mut record = encode(&calibration());
+            record[offset] ^= 0x10;
+            assert_eq!(
+                decode(&record),
+                Err(DecodeError::Corrupt),
+                "offset {}",
+                offset
+            );
+        }
+    }
+
+    #[test]
+    fn partially_written_record_is_corrupt() {
+        // Power lost after the first half was programmed, the rest is still erased
+        let mut record = encode(&calibration());
+        record[32..].fill(0xFF);
+        assert_eq!(decode(&record), Err(DecodeError::Corrupt));
+    }
+}
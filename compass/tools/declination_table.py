@@ -0,0 +1,69 @@
+"""Generates the declination grid in src/declination.rs.
+
+Evaluates the IGRF-13 main field model (epoch 2020.0) truncated to degree 6 on a spherical earth,
+which is within a couple of degrees of the full model away from the magnetic poles. Run it with
+`python3 tools/declination_table.py` and paste the output over `TABLE`.
+"""
+import math
+
+# IGRF-13 Gauss coefficients for epoch 2020.0 in nT, as (n, m, g, h), up to degree 6
+COEFFICIENTS = [
+    (1, 0, -29404.8, 0.0), (1, 1, -1450.9, 4652.5),
+    (2, 0, -2499.6, 0.0), (2, 1, 2982.0, -2991.6), (2, 2, 1677.0, -734.6),
+    (3, 0, 1363.2, 0.0), (3, 1, -2381.2, -82.1), (3, 2, 1236.2, 241.9), (3, 3, 525.7, -543.4),
+    (4, 0, 903.0, 0.0), (4, 1, 809.5, 281.9), (4, 2, 86.3, -158.4), (4, 3, -309.4, 199.7),
+    (4, 4, 48.0, -349.7),
+    (5, 0, -234.3, 0.0), (5, 1, 363.2, 47.7), (5, 2, 187.8, 208.3), (5, 3, -140.7, -121.2),
+    (5, 4, -151.2, 32.3), (5, 5, 13.5, 98.9),
+    (6, 0, 66.0, 0.0), (6, 1, 65.5, -19.1), (6, 2, 72.9, 25.1), (6, 3, -121.5, 52.8),
+    (6, 4, -36.2, -64.5), (6, 5, 13.5, 8.9), (6, 6, -64.7, 68.1),
+]
+DEGREE = 6
+
+
+def legendre(theta):
+    """Schmidt semi-normalised associated Legendre functions P[n][m] and their derivatives with
+    respect to the colatitude theta."""
+    c, s = math.cos(theta), math.sin(theta)
+    p = [[0.0] * (DEGREE + 1) for _ in range(DEGREE + 1)]
+    dp = [[0.0] * (DEGREE + 1) for _ in range(DEGREE + 1)]
+    p[0][0] = 1.0
+    for n in range(1, DEGREE + 1):
+        for m in range(n + 1):
+            if n == m:
+                k = 1.0 if n == 1 else math.sqrt(1 - 1 / (2 * n))
+                p[n][m] = k * s * p[n - 1][m - 1]
+                dp[n][m] = k * (s * dp[n - 1][m - 1] + c * p[n - 1][m - 1])
+            else:
+                a = (2 * n - 1) / math.sqrt(n * n - m * m)
+                b = math.sqrt(((n - 1) ** 2 - m * m) / (n * n - m * m)) if n - 1 >= m else 0.0
+                p[n][m] = a * c * p[n - 1][m] - (b * p[n - 2][m] if n >= 2 else 0.0)
+                dp[n][m] = a * (c * dp[n - 1][m] - s * p[n - 1][m]) - (
+                    b * dp[n - 2][m] if n >= 2 else 0.0
+                )
+    return p, dp
+
+
+def declination(latitude, longitude):
+    """Declination in degrees, east positive."""
+    theta = math.radians(90 - latitude)
+    phi = math.radians(longitude)
+    p, dp = legendre(theta)
+    s = math.sin(theta)
+    north = east = 0.0
+    for n, m, g, h in COEFFICIENTS:
+        north += (g * math.cos(m * phi) + h * math.sin(m * phi)) * dp[n][m]
+        if s > 1e-9:
+            east += m / s * (g * math.sin(m * phi) - h * math.cos(m * phi)) * p[n][m]
+    return math.degrees(math.atan2(east, north))
+
+
+LATITUDES = range(-80, 81, 10)
+LONGITUDES = range(-180, 180, 20)
+
+if __name__ == "__main__":
+    print("const TABLE: [[i16; %d]; %d] = [" % (len(LONGITUDES), len(LATITUDES)))
+    for lat in LATITUDES:
+        row = ", ".join("%d" % round(declination(lat, lon)) for lon in LONGITUDES)
+        print("    [%s], // %d" % (row, lat))
+    print("];")
@@ -0,0 +1,270 @@
+//! A board running the `i2c` app, seen through its serial shell.
+//!
+//! The shell is made for people at a terminal, so this does what they would: it types a command
+//! and a carriage return, skips the echo and reads up to the next prompt.
+use protocol::frame::{Decoder, DELIMITER};
+use protocol::message::{self, Request, Response};
+use protocol::stream::{Format, Sample, CSV_HEADER, STOP};
+use std::io::{self, ErrorKind, Read, Write};
+use std::time::{Duration, Instant};
+
+/// What the shell prints at the start of a line when it waits for the next command.
+const PROMPT: &[u8] = b"> ";
+
+/// How long the board may stay silent in the middle of an answer before it counts as gone.
+const PATIENCE: Duration = Duration::from_secs(3);
+
+pub struct Board<P> {
+    port: P,
+    buffer: [u8; 256],
+    start: usize,
+    end: usize,
+    heard: Instant,
+    /// The message of the last response.
+    response: Vec<u8>,
+}
+
+impl<P: Read + Write> Board<P> {
+    /// Takes over a port, which should have a read timeout of a fraction of a second, and waits
+    /// for the board to be at an empty prompt. That also ends a stream a previous run left going.
+    pub fn new(port: P) -> io::Result<Self> {
+        let mut board = Board {
+            port,
+            buffer: [0; 256],
+            start: 0,
+            end: 0,
+            heard: Instant::now(),
+            response: Vec::new(),
+        };
+        board.stop()?;
+        Ok(board)
+    }
+
+    pub fn into_inner(self) -> P {
+        self.port
+    }
+
+    /// Runs a command and returns what it printed.
+    pub fn command(&mut self, line: &str) -> io::Result<String> {
+        self.send(line)?;
+        let mut output = Vec::new();
+        while !at_prompt(&output) {
+            if let Some(byte) = self.read_byte()? {
+                output.push(byte);
+            }
+        }
+        output.truncate(output.len() - PROMPT.len());
+        Ok(String::from_utf8_lossy(&output).into_owned())
+    }
+
+    /// Sends a binary request and waits for the response. Responses that arrive damaged are
+    /// skipped, so they end in a timeout.
+    pub fn request(&mut self, request: &Request) -> io::Result<Response<'_>> {
+        let mut buffer = [0; message::MAX_FRAME];
+        let frame = message::encode(request, &mut buffer).map_err(invalid)?;
+        self.write(frame)?;
+        let mut decoder = Decoder::<{ message::MAX_FRAME }>::new();
+        loop {
+            let Some(byte) = self.read_byte()? else {
+                continue;
+            };
+            // Anything the shell printed before comes out as a damaged frame
+            if let Some(Ok(message)) = decoder.feed(byte) {
+                self.response = message.to_vec();
+                break;
+            }
+        }
+        message::decode(Ok(&self.response)).map_err(invalid)
+    }
+
+    /// Starts streaming `sensors`, which is what the `stream` command takes: `accel`, `mag` or
+    /// `both`. The stream runs until [`Samples::stop`] or [`Board::stop`].
+    pub fn stream(&mut self, sensors: &str, format: Format) -> io::Result<Samples<'_, P>> {
+        let name = match format {
+            Format::Text => "text",
+            Format::Csv => "csv",
+            Format::Binary => "binary",
+        };
+        self.send(&format!("stream {sensors} {name}"))?;
+        Ok(Samples {
+            board: self,
+            format,
+            decoder: Decoder::new(),
+            pending: Vec::new(),
+            dropped: 0,
+        })
+    }
+
+    /// Stops a stream, or throws away the line typed so far, and waits for the prompt.
+    pub fn stop(&mut self) -> io::Result<()> {
+        self.write(&[STOP])?;
+        // Samples still on their way may end in something that looks like a prompt, so it only
+        // counts once the board goes quiet
+        let mut tail = Vec::new();
+        loop {
+            match self.read_byte()? {
+                Some(byte) => tail.push(byte),
+                None if tail.ends_with(PROMPT) => return Ok(()),
+                None => {}
+            }
+            if tail.len() > PROMPT.len() {
+                tail.remove(0);
+            }
+        }
+    }
+
+    /// Types `line` and skips its echo.
+    fn send(&mut self, line: &str) -> io::Result<()> {
+        self.write(format!("{line}\r").as_bytes())?;
+        let mut echo = Vec::new();
+        while !echo.ends_with(b"\r\n") {
+            if let Some(byte) = self.read_byte()? {
+                echo.push(byte);
+            }
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.port.write_all(bytes)?;
+        // The board has until `PATIENCE` from now to answer
+        self.heard = Instant::now();
+        Ok(())
+    }
+
+    /// The next byte from the board, or `None` when the port timed out waiting for one.
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        if self.start == self.end {
+            self.end = match self.port.read(&mut self.buffer) {
+                Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+                Ok(read) => read,
+                Err(error)
+                    if matches!(error.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock) =>
+                {
+                    if self.heard.elapsed() > PATIENCE {
+                        return Err(io::Error::new(
+                            ErrorKind::TimedOut,
+                            "the board stopped answering",
+                        ));
+                    }
+                    return Ok(None);
+                }
+                Err(error) => return Err(error),
+            };
+            self.start = 0;
+            self.heard = Instant::now();
+        }
+        self.start += 1;
+        Ok(Some(self.buffer[self.start - 1]))
+    }
+}
+
+fn invalid(error: message::Error) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, format!("{error:?}"))
+}
+
+/// How the shell starts the line saying why a command failed.
+const ERROR: &str = "error: ";
+
+fn board_error(message: &str) -> io::Error {
+    let message = message.trim();
+    io::Error::other(message.strip_prefix(ERROR).unwrap_or(message).to_owned())
+}
+
+/// Whether `output` ends in a prompt at the start of a line.
+fn at_prompt(output: &[u8]) -> bool {
+    match output.strip_suffix(PROMPT) {
+        Some(before) => before.is_empty() || before.ends_with(b"\n"),
+        None => false,
+    }
+}
+
+/// The samples of a running stream.
+///
+/// Samples that arrive damaged are skipped and counted. An error from the board ends the
+/// iteration with that error, as does a board that stops sending for a few seconds.
+pub struct Samples<'a, P> {
+    board: &'a mut Board<P>,
+    format: Format,
+    decoder: Decoder<{ Sample::MAX_FRAME }>,
+    /// The bytes since the last line or frame end, to recognise the board's error messages by.
+    pending: Vec<u8>,
+    dropped: usize,
+}
+
+impl<P: Read + Write> Samples<'_, P> {
+    /// How many samples were lost to corruption so far.
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    pub fn stop(self) -> io::Result<()> {
+        self.board.stop()
+    }
+
+    /// What the board printed instead of streaming, if it is back at the prompt.
+    fn refusal(&self) -> Option<io::Error> {
+        if !at_prompt(&self.pending) {
+            return None;
+        }
+        let message = &self.pending[..self.pending.len() - PROMPT.len()];
+        Some(board_error(&String::from_utf8_lossy(message)))
+    }
+
+    fn next_sample(&mut self) -> io::Result<Sample> {
+        loop {
+            let Some(byte) = self.board.read_byte()? else {
+                // Samples keep coming until the board gives up on the stream
+                if let Some(error) = self.refusal() {
+                    return Err(error);
+                }
+                continue;
+            };
+
+            if self.format == Format::Binary {
+                if byte == DELIMITER {
+                    self.pending.clear();
+                } else {
+                    self.pending.push(byte);
+                }
+                match self.decoder.feed(byte) {
+                    Some(Ok(frame)) => match Sample::from_bytes(frame) {
+                        Some(sample) => return Ok(sample),
+                        None => self.dropped += 1,
+                    },
+                    Some(Err(_)) => self.dropped += 1,
+                    None => {}
+                }
+                continue;
+            }
+
+            if byte != b'\n' {
+                self.pending.push(byte);
+                continue;
+            }
+            let line = String::from_utf8_lossy(&self.pending).into_owned();
+            self.pending.clear();
+            let line = line.trim();
+            if line.is_empty() || line == CSV_HEADER {
+                continue;
+            }
+            let sample = match self.format {
+                Format::Csv => Sample::parse_csv(line),
+                _ => Sample::parse_text(line),
+            };
+            match sample {
+                Some(sample) => return Ok(sample),
+                None if line.starts_with(ERROR) => return Err(board_error(line)),
+                None => self.dropped += 1,
+            }
+        }
+    }
+}
+
+impl<P: Read + Write> Iterator for Samples<'_, P> {
+    type Item = io::Result<Sample>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.next_sample())
+    }
+}
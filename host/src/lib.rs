@@ -0,0 +1,10 @@
+//! The host side of the `i2c` app's serial shell.
+//!
+//! [`Board`] runs shell commands and binary requests on a board and decodes the samples of its
+//! `stream` command with the same [`protocol`] code the firmware encodes them with, and [`Log`]
+//! keeps them as CSV or JSON. The `host` binary puts both on the command line.
+pub mod board;
+pub mod log;
+
+pub use board::{Board, Samples};
+pub use log::{Log, LogFormat};
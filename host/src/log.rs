@@ -0,0 +1,102 @@
+//! Sample logs for other tools to read.
+use protocol::stream::{Sample, CSV_HEADER};
+use std::io::{self, Write};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum LogFormat {
+    /// The same lines as a `csv` stream, header included.
+    Csv,
+    /// A JSON object per line, with the CSV columns as keys.
+    Json,
+}
+
+/// Writes samples one line at a time, so that a log cut short by Ctrl-C is only missing the
+/// samples after it.
+pub struct Log<W> {
+    out: W,
+    format: LogFormat,
+}
+
+impl<W: Write> Log<W> {
+    pub fn new(mut out: W, format: LogFormat) -> io::Result<Self> {
+        if format == LogFormat::Csv {
+            write!(out, "{}\r\n", CSV_HEADER)?;
+        }
+        Ok(Log { out, format })
+    }
+
+    pub fn write(&mut self, sample: &Sample) -> io::Result<()> {
+        let mut line = String::new();
+        match self.format {
+            LogFormat::Csv => sample.write_csv(&mut line),
+            LogFormat::Json => {
+                use std::fmt::Write;
+                writeln!(
+                    line,
+                    "{{\"time_us\":{},\"sensor\":\"{}\",\"x\":{},\"y\":{},\"z\":{}}}",
+                    sample.timestamp,
+                    sample.source.short_name(),
+                    sample.x,
+                    sample.y,
+                    sample.z
+                )
+            }
+        }
+        .expect("formatting into a String");
+        self.out.write_all(line.as_bytes())?;
+        self.out.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protocol::stream::Source;
+
+    const SAMPLES: [Sample; 2] = [
+        Sample {
+            timestamp: 0,
+            source: Source::Accelerometer,
+            x: -16,
+            y: 980,
+            z: 2,
+        },
+        Sample {
+            timestamp: 20_000,
+            source: Source::Magnetometer,
+            x: 150,
+            y: -3_000,
+            z: 45_000,
+        },
+    ];
+
+    fn log(format: LogFormat) -> String {
+        let mut log = Log::new(Vec::new(), format).unwrap();
+        for sample in &SAMPLES {
+            log.write(sample).unwrap();
+        }
+        String::from_utf8(log.into_inner()).unwrap()
+    }
+
+    #[test]
+    fn csv_reads_back() {
+        let csv = log(LogFormat::Csv);
+        let mut lines = csv.lines();
+        assert_eq!(lines.next(), Some(CSV_HEADER));
+        let samples: Vec<_> = lines.map(|line| Sample::parse_csv(line).unwrap()).collect();
+        assert_eq!(samples, SAMPLES);
+    }
+
+    #[test]
+    fn json_has_a_line_per_sample() {
+        assert_eq!(
+            log(LogFormat::Json),
+            "{\"time_us\":0,\"sensor\":\"accel\",\"x\":-16,\"y\":980,\"z\":2}\n\
+             {\"time_us\":20000,\"sensor\":\"mag\",\"x\":150,\"y\":-3000,\"z\":45000}\n"
+        );
+    }
+}
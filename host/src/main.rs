@@ -0,0 +1,118 @@
+//! Talks to the `i2c` app over its serial port.
+//!
+//! ```text
+//! host send accel odr 400
+//! host stream both --count 1000 --output run.csv
+//! host --port /dev/ttyUSB0 stream accel --log json
+//! ```
+use clap::{Parser, Subcommand, ValueEnum};
+use host::{Board, Log, LogFormat};
+use protocol::stream::Format;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Duration;
+
+#[derive(Parser)]
+#[command(about = "Runs commands on the i2c app and logs its sample streams")]
+struct Cli {
+    /// The serial port of the board
+    #[arg(short, long, default_value = "/dev/ttyACM0")]
+    port: String,
+    /// The baud rate the board runs its serial port at
+    #[arg(short, long, default_value_t = 115_200)]
+    baud: u32,
+    #[command(subcommand)]
+    action: Action,
+}
+
+#[derive(Subcommand)]
+enum Action {
+    /// Runs a shell command and prints its output
+    Send {
+        #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+    },
+    /// Streams samples into a log until the count is reached or forever
+    Stream {
+        #[arg(value_parser = ["accel", "mag", "both"])]
+        sensors: String,
+        /// How the board sends the samples. Only binary catches corrupted ones
+        #[arg(short, long, value_enum, default_value_t = Wire::Binary)]
+        wire: Wire,
+        /// Stops after this many samples
+        #[arg(short = 'n', long)]
+        count: Option<usize>,
+        /// The log file, standard output if not given
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        #[arg(short, long, value_enum, default_value_t = LogFormat::Csv)]
+        log: LogFormat,
+    },
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Wire {
+    /// Text lines without timestamps
+    Text,
+    /// Text lines with timestamps
+    Csv,
+    /// Framed records with a checksum
+    Binary,
+}
+
+impl From<Wire> for Format {
+    fn from(wire: Wire) -> Self {
+        match wire {
+            Wire::Text => Format::Text,
+            Wire::Csv => Format::Csv,
+            Wire::Binary => Format::Binary,
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("error: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(cli: Cli) -> io::Result<()> {
+    let port = serialport::new(&cli.port, cli.baud)
+        .timeout(Duration::from_millis(100))
+        .open()?;
+    let mut board = Board::new(port)?;
+
+    match cli.action {
+        Action::Send { command } => {
+            print!("{}", board.command(&command.join(" "))?);
+        }
+        Action::Stream {
+            sensors,
+            wire,
+            count,
+            output,
+            log,
+        } => {
+            let out: Box<dyn Write> = match output {
+                Some(path) => Box::new(File::create(path)?),
+                None => Box::new(io::stdout()),
+            };
+            let mut log = Log::new(out, log)?;
+            let mut samples = board.stream(&sensors, wire.into())?;
+            for sample in samples.by_ref().take(count.unwrap_or(usize::MAX)) {
+                log.write(&sample?)?;
+            }
+            if samples.dropped() > 0 {
+                eprintln!("{} damaged samples dropped", samples.dropped());
+            }
+            samples.stop()?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,276 @@
+//! The host side against a stand-in board on the other end of a pseudo terminal. The stand-in runs
+//! the real shell with a made up command table, so everything from the echo to the framing goes
+//! through the same code as on the board.
+#![cfg(unix)]
+use host::Board;
+use protocol::message::{
+    self, AccelMode, Config, Failure, Link, MagMode, Received, Request, Response,
+};
+use protocol::stream::{Format, Sample, Source, CSV_HEADER, STOP};
+use serialport::{SerialPort, TTYPort};
+use shell::{Arg, Args, Command, Console, Error, LineEditor};
+use std::fmt;
+use std::io::{ErrorKind, Read, Write};
+use std::thread;
+use std::time::Duration;
+
+struct Port(TTYPort);
+
+impl fmt::Write for Port {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0.write_all(s.as_bytes()).map_err(|_| fmt::Error)
+    }
+}
+
+impl Console for Port {
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        self.0.write_all(bytes).ok();
+    }
+
+    fn read_byte(&mut self) -> Option<u8> {
+        let mut byte = [0];
+        let waiting = self.0.bytes_to_read().ok()?;
+        (waiting > 0 && self.0.read(&mut byte).ok()? == 1).then_some(byte[0])
+    }
+}
+
+/// The frame of the accelerometer sample with this number is damaged on the way.
+const DAMAGED: i32 = 3;
+
+fn accelerometer(_: &mut (), _: &Args, out: &mut dyn Console) -> Result<(), Error<'static>> {
+    write!(out, "Accelerometer: x 1 y 2 z 3\r\n").ok();
+    Ok(())
+}
+
+/// Streams made up samples every millisecond, numbered in `x`.
+fn stream(_: &mut (), args: &Args, out: &mut dyn Console) -> Result<(), Error<'static>> {
+    let sources: &[Source] = match args.choice(0) {
+        0 => &[Source::Accelerometer],
+        1 => return Err(Error::Failed("magnetometer not responding")),
+        _ => &[Source::Accelerometer, Source::Magnetometer],
+    };
+    let format = [Format::Text, Format::Csv, Format::Binary][args.choice(1)];
+    if format == Format::Csv {
+        write!(out, "{}\r\n", CSV_HEADER).ok();
+    }
+
+    let mut frame = [0; Sample::MAX_FRAME];
+    for n in 0..10_000 {
+        if out.read_byte() == Some(STOP) {
+            break;
+        }
+        thread::sleep(Duration::from_millis(1));
+        for &source in sources {
+            let sample = Sample {
+                timestamp: n as u32 * 1000,
+                source,
+                x: n,
+                y: -n,
+                z: 1000,
+            };
+            match format {
+                Format::Text => {
+                    sample.write_text(out).ok();
+                }
+                Format::Csv => {
+                    sample.write_csv(out).ok();
+                }
+                Format::Binary => {
+                    let len = sample.encode(&mut frame).len();
+                    if n == DAMAGED && source == Source::Accelerometer {
+                        // Any other byte but a delimiter
+                        frame[5] = frame[5] % 0xfe + 1;
+                    }
+                    out.write_bytes(&frame[..len]);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+const COMMANDS: &[Command<()>] = &[
+    Command {
+        name: "accelerometer",
+        help: "Reads one accelerometer sample",
+        args: &[],
+        run: accelerometer,
+    },
+    Command {
+        name: "stream",
+        help: "Streams numbered samples",
+        args: &[
+            Arg::choice("sensors", &["accel", "mag", "both"]),
+            Arg::choice("format", &["text", "csv", "binary"]),
+        ],
+        run: stream,
+    },
+];
+
+const CONFIG: Config = Config {
+    accel_odr_hz: 50,
+    accel_scale_g: 2,
+    accel_mode: AccelMode::Normal,
+    mag_odr_hz: 50,
+    mag_mode: MagMode::Continuous,
+};
+
+fn answer(request: Result<Request, message::Error>) -> Response<'static> {
+    match request {
+        Ok(Request::GetConfig) => Response::Config(CONFIG),
+        Ok(_) => Response::Error(Failure::Failed("not in this test")),
+        Err(error) => Response::Error(error.into()),
+    }
+}
+
+/// The main loop of the `i2c` app, until the host closes its end.
+fn run_board(port: TTYPort) {
+    let mut port = Port(port);
+    let mut editor: LineEditor<64, 4, _> = LineEditor::new("> ", COMMANDS);
+    editor.prompt(&mut port);
+    let mut link = Link::new();
+    let mut byte = [0];
+    loop {
+        match port.0.read(&mut byte) {
+            Ok(1) => {}
+            Err(error) if error.kind() == ErrorKind::TimedOut => continue,
+            _ => return,
+        }
+        match link.feed(byte[0]) {
+            Received::Text(text) => {
+                for &byte in text {
+                    if let Some(line) = editor.feed(byte, &mut port) {
+                        shell::execute(COMMANDS, &mut (), line, &mut port);
+                        editor.prompt(&mut port);
+                    }
+                }
+            }
+            Received::Message(message) => {
+                let mut buffer = [0; message::MAX_FRAME];
+                let response = answer(message::decode(message));
+                port.write_bytes(message::encode(&response, &mut buffer).unwrap());
+            }
+            Received::Nothing => {}
+        }
+    }
+}
+
+fn connect() -> Board<TTYPort> {
+    let (host, board) = TTYPort::pair().unwrap();
+    thread::spawn(move || run_board(board));
+    Board::new(host).unwrap()
+}
+
+fn take(board: &mut Board<TTYPort>, sensors: &str, format: Format, count: usize) -> Vec<Sample> {
+    let mut samples = board.stream(sensors, format).unwrap();
+    let taken = samples.by_ref().take(count).map(Result::unwrap).collect();
+    samples.stop().unwrap();
+    taken
+}
+
+fn numbers(samples: &[Sample]) -> Vec<i32> {
+    samples.iter().map(|sample| sample.x).collect()
+}
+
+#[test]
+fn commands_return_their_output() {
+    let mut board = connect();
+    assert_eq!(
+        board.command("accelerometer").unwrap(),
+        "Accelerometer: x 1 y 2 z 3\r\n"
+    );
+    assert_eq!(
+        board.command("gyro").unwrap(),
+        "error: unknown command \"gyro\", try \"help\"\r\n"
+    );
+    assert_eq!(board.command("").unwrap(), "");
+}
+
+#[test]
+fn text_streams_have_no_timestamps() {
+    let mut board = connect();
+    let samples = take(&mut board, "accel", Format::Text, 5);
+    assert_eq!(numbers(&samples), [0, 1, 2, 3, 4]);
+    assert!(samples.iter().all(|sample| sample.timestamp == 0));
+    assert!(board.command("accelerometer").is_ok());
+}
+
+#[test]
+fn csv_streams_have_timestamps() {
+    let mut board = connect();
+    let samples = take(&mut board, "both", Format::Csv, 4);
+    let sources: Vec<_> = samples.iter().map(|sample| sample.source).collect();
+    assert_eq!(
+        sources,
+        [
+            Source::Accelerometer,
+            Source::Magnetometer,
+            Source::Accelerometer,
+            Source::Magnetometer
+        ]
+    );
+    let timestamps: Vec<_> = samples.iter().map(|sample| sample.timestamp).collect();
+    assert_eq!(timestamps, [0, 0, 1000, 1000]);
+}
+
+#[test]
+fn binary_streams_skip_damaged_frames() {
+    let mut board = connect();
+    let mut samples = board.stream("accel", Format::Binary).unwrap();
+    let taken: Vec<_> = samples.by_ref().take(5).map(Result::unwrap).collect();
+    assert_eq!(numbers(&taken), [0, 1, 2, 4, 5]);
+    assert_eq!(taken[4].timestamp, 5000);
+    assert_eq!(samples.dropped(), 1);
+    samples.stop().unwrap();
+    assert!(board.command("accelerometer").is_ok());
+}
+
+#[test]
+fn refused_streams_are_errors() {
+    let mut board = connect();
+    for format in [Format::Text, Format::Csv, Format::Binary] {
+        let error = board
+            .stream("mag", format)
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(error.to_string(), "magnetometer not responding");
+        board.stop().unwrap();
+    }
+}
+
+#[test]
+fn connecting_stops_a_stream_left_running() {
+    let mut board = connect();
+    let mut samples = board.stream("both", Format::Binary).unwrap();
+    assert!(samples.next().unwrap().is_ok());
+    drop(samples);
+
+    let mut board = Board::new(board.into_inner()).unwrap();
+    assert_eq!(
+        board.command("accelerometer").unwrap(),
+        "Accelerometer: x 1 y 2 z 3\r\n"
+    );
+}
+
+#[test]
+fn requests_work_next_to_the_shell() {
+    let mut board = connect();
+    assert_eq!(
+        board.request(&Request::GetConfig).unwrap(),
+        Response::Config(CONFIG)
+    );
+    assert_eq!(
+        board.command("accelerometer").unwrap(),
+        "Accelerometer: x 1 y 2 z 3\r\n"
+    );
+    assert_eq!(
+        board.request(&Request::ReadAccel).unwrap(),
+        Response::Error(Failure::Failed("not in this test"))
+    );
+    assert_eq!(
+        board.command("accelerometer").unwrap(),
+        "Accelerometer: x 1 y 2 z 3\r\n"
+    );
+}
@@ -0,0 +1,145 @@
+//! Raw access to the I2C buses, for diagnosing the sensor and boards on the edge connector.
+//!
+//! Writing registers behind the back of the sensor driver leaves it with stale copies of them,
+//! the sensor commands still show and change what they last set.
+use board::error::{self, Bus};
+use board::selftest::{
+    ACCELEROMETER_ADDR, ACCELEROMETER_ID, ACCELEROMETER_ID_REG, MAGNETOMETER_ADDR, MAGNETOMETER_ID,
+    MAGNETOMETER_ID_REG,
+};
+use embedded_hal::blocking::i2c::{Read, Write, WriteRead};
+use shell::command::name_of;
+use shell::{Args, Console, Error, Value};
+
+use crate::commands::App;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Which {
+    /// The bus of the LSM303AGR.
+    Internal,
+    /// The bus on the edge connector, which has its own peripheral on the v2 board only.
+    #[cfg(feature = "v2")]
+    External,
+}
+
+#[cfg(feature = "v1")]
+pub const BUSES: [(&str, Which); 1] = [("internal", Which::Internal)];
+
+#[cfg(feature = "v2")]
+pub const BUSES: [(&str, Which); 2] =
+    [("internal", Which::Internal), ("external", Which::External)];
+
+/// The most registers a single `i2c read` reads.
+pub const MAX_COUNT: i32 = 16;
+
+/// Devices `i2c scan` recognises by the ID in their WHO_AM_I register: the address, the
+/// register, the ID and the name. The older v1 boards had the MMA8653 and MAG3110 instead of the
+/// LSM303AGR.
+const KNOWN: [(u8, u8, u8, &str); 4] = [
+    (
+        ACCELEROMETER_ADDR,
+        ACCELEROMETER_ID_REG,
+        ACCELEROMETER_ID,
+        "LSM303AGR accelerometer",
+    ),
+    (
+        MAGNETOMETER_ADDR,
+        MAGNETOMETER_ID_REG,
+        MAGNETOMETER_ID,
+        "LSM303AGR magnetometer",
+    ),
+    (0x1d, 0x0d, 0x5a, "MMA8653 accelerometer"),
+    (0x0e, 0x07, 0xc4, "MAG3110 magnetometer"),
+];
+
+/// The addresses a scan tries, leaving out the ones I2C reserves.
+const ADDRESSES: core::ops::RangeInclusive<u8> = 0x08..=0x77;
+
+/// Either of the buses.
+trait Raw: Write<Error = Bus> + Read<Error = Bus> + WriteRead<Error = Bus> {}
+
+impl<T: Write<Error = Bus> + Read<Error = Bus> + WriteRead<Error = Bus>> Raw for T {}
+
+fn selected(app: &mut App) -> &mut dyn Raw {
+    match app.selected {
+        Which::Internal => &mut app.bus,
+        #[cfg(feature = "v2")]
+        Which::External => &mut app.external,
+    }
+}
+
+fn failure(error: Bus) -> Error<'static> {
+    match error {
+        Bus::Nack => Error::Failed("nothing answered at that address"),
+        Bus::Failed => error::Error::Bus(error).into(),
+    }
+}
+
+pub fn bus(app: &mut App, args: &Args, out: &mut dyn Console) -> Result<(), Error<'static>> {
+    if let Some(Value::Choice(which)) = args.get(0) {
+        app.selected = BUSES[which].1;
+    }
+    write!(out, "i2c bus {}\r\n", name_of(&BUSES, &app.selected)).ok();
+    Ok(())
+}
+
+/// Lists the addresses that acknowledge a read, with the name of the device if it is a known
+/// one.
+pub fn scan(app: &mut App, _: &Args, out: &mut dyn Console) -> Result<(), Error<'static>> {
+    let bus = selected(app);
+    let mut found = 0;
+    for address in ADDRESSES {
+        // The nRF51 cannot tell a missing device from a failed transfer, so anything but an
+        // answer counts as nobody there
+        if bus.read(address, &mut [0]).is_err() {
+            continue;
+        }
+        found += 1;
+        write!(out, "0x{:02x}", address).ok();
+        for &(_, register, id, name) in KNOWN.iter().filter(|known| known.0 == address) {
+            let mut read = [0];
+            if bus.write_read(address, &[register], &mut read).is_ok() && read[0] == id {
+                write!(out, "  {}", name).ok();
+            }
+        }
+        out.write_str("\r\n").ok();
+    }
+    write!(out, "{} found\r\n", found).ok();
+    Ok(())
+}
+
+/// Prints `count` registers starting at `register`, one per line. The LSM303AGR accelerometer
+/// only moves on to the next register with the top bit of `register` set.
+pub fn read(app: &mut App, args: &Args, out: &mut dyn Console) -> Result<(), Error<'static>> {
+    let address = args.int(0) as u8;
+    let register = args.int(1) as u8;
+    let count = args.int_or(2, 1) as usize;
+    let mut values = [0; MAX_COUNT as usize];
+    let values = &mut values[..count];
+    selected(app)
+        .write_read(address, &[register], values)
+        .map_err(failure)?;
+    for (offset, value) in values.iter().enumerate() {
+        write!(
+            out,
+            "0x{:02x}: 0x{:02x}\r\n",
+            register.wrapping_add(offset as u8),
+            value
+        )
+        .ok();
+    }
+    Ok(())
+}
+
+/// Writes the values given to `register` and the ones after it.
+pub fn write(app: &mut App, args: &Args, _: &mut dyn Console) -> Result<(), Error<'static>> {
+    let address = args.int(0) as u8;
+    // The register, then the values
+    let mut bytes = [0; 5];
+    let mut len = 0;
+    while let Some(Value::Int(value)) = args.get(len + 1) {
+        bytes[len] = value as u8;
+        len += 1;
+    }
+    selected(app).write(address, &bytes[..len]).map_err(failure)
+}
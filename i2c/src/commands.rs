@@ -0,0 +1,370 @@
+//! The commands of the sensor shell. Each one is a row in `COMMANDS`, the shell takes care of
+//! looking them up, checking their arguments and printing `help`.
+use board::error::Counters;
+use lsm303agr::Measurement;
+use microbit::hal::timer::{Periodic, Timer};
+use microbit::pac::TIMER0;
+#[cfg(feature = "v2")]
+use microbit::pac::TWIM1;
+use protocol::stream::{Format, Sample, Source, CSV_HEADER, STOP};
+use shell::command::{name_of, names};
+use shell::{Arg, Args, Command, Console, Error, Value};
+
+use crate::bus::{self, Which, BUSES};
+use crate::sensor::{Sensor, ACCEL_MODES, ACCEL_ODRS, ACCEL_SCALES, MAG_MODES, MAG_ODRS};
+
+/// What the commands work with.
+pub struct App {
+    pub sensor: Sensor,
+    /// The sensor bus, shared with the sensor driver.
+    pub bus: board::SharedI2c<'static>,
+    #[cfg(feature = "v2")]
+    pub external: board::I2c<TWIM1>,
+    /// The bus the `i2c` commands use.
+    pub selected: Which,
+    /// Counts microseconds, for the timestamps of streamed samples.
+    pub clock: Timer<TIMER0, Periodic>,
+    /// Serial and protocol errors, the sensor counts its own.
+    pub errors: Counters,
+}
+
+/// The most samples a single `read` prints
+const MAX_COUNT: i32 = 100;
+
+/// Which sensors `stream` reads, the accelerometer and the magnetometer.
+const SOURCES: [(&str, (bool, bool)); 3] = [
+    ("accel", (true, false)),
+    ("mag", (false, true)),
+    ("both", (true, true)),
+];
+
+const FORMATS: [(&str, Format); 3] = [
+    ("text", Format::Text),
+    ("csv", Format::Csv),
+    ("binary", Format::Binary),
+];
+
+pub const COMMANDS: &[Command<App>] = &[
+    Command {
+        name: "accelerometer",
+        help: "Reads one accelerometer sample",
+        args: &[],
+        run: accelerometer,
+    },
+    Command {
+        name: "magnetometer",
+        help: "Reads one magnetometer sample",
+        args: &[],
+        run: magnetometer,
+    },
+    Command {
+        name: "accel read",
+        help: "Reads accelerometer samples, one by default",
+        args: &[Arg::int("count", 1, MAX_COUNT).optional()],
+        run: accel_read,
+    },
+    Command {
+        name: "accel odr",
+        help: "Shows or sets the accelerometer data rate in Hz",
+        args: &[Arg::choice("rate", &names(&ACCEL_ODRS)).optional()],
+        run: accel_odr,
+    },
+    Command {
+        name: "accel scale",
+        help: "Shows or sets the accelerometer full scale in g",
+        args: &[Arg::choice("scale", &names(&ACCEL_SCALES)).optional()],
+        run: accel_scale,
+    },
+    Command {
+        name: "accel mode",
+        help: "Shows or sets the accelerometer power mode",
+        args: &[Arg::choice("mode", &names(&ACCEL_MODES)).optional()],
+        run: accel_mode,
+    },
+    Command {
+        name: "mag read",
+        help: "Reads magnetometer samples, one by default",
+        args: &[Arg::int("count", 1, MAX_COUNT).optional()],
+        run: mag_read,
+    },
+    Command {
+        name: "mag odr",
+        help: "Shows or sets the magnetometer data rate in Hz",
+        args: &[Arg::choice("rate", &names(&MAG_ODRS)).optional()],
+        run: mag_odr,
+    },
+    Command {
+        name: "mag mode",
+        help: "Shows or sets the magnetometer mode",
+        args: &[Arg::choice("mode", &names(&MAG_MODES)).optional()],
+        run: mag_mode,
+    },
+    Command {
+        name: "stream",
+        help: "Sends samples at the data rate until Ctrl-C, as text by default",
+        args: &[
+            Arg::choice("sensors", &names(&SOURCES)),
+            Arg::choice("format", &names(&FORMATS)).optional(),
+        ],
+        run: stream,
+    },
+    Command {
+        name: "config",
+        help: "Shows the whole sensor configuration",
+        args: &[],
+        run: config,
+    },
+    Command {
+        name: "i2c bus",
+        help: "Shows or sets the bus the other i2c commands use",
+        args: &[Arg::choice("bus", &names(&BUSES)).optional()],
+        run: bus::bus,
+    },
+    Command {
+        name: "i2c scan",
+        help: "Lists the addresses that answer on the bus, and the devices it knows",
+        args: &[],
+        run: bus::scan,
+    },
+    Command {
+        name: "i2c read",
+        help: "Reads registers of a device, one by default",
+        args: &[
+            Arg::int("address", 0, 0x7f),
+            Arg::int("register", 0, 0xff),
+            Arg::int("count", 1, bus::MAX_COUNT).optional(),
+        ],
+        run: bus::read,
+    },
+    Command {
+        name: "i2c write",
+        help: "Writes registers of a device, starting at the one given",
+        args: &[
+            Arg::int("address", 0, 0x7f),
+            Arg::int("register", 0, 0xff),
+            Arg::int("value", 0, 0xff),
+            Arg::int("value", 0, 0xff).optional(),
+            Arg::int("value", 0, 0xff).optional(),
+            Arg::int("value", 0, 0xff).optional(),
+        ],
+        run: bus::write,
+    },
+    Command {
+        name: "selftest",
+        help: "Runs the self-test of the sensor, which takes about a second",
+        args: &[],
+        run: selftest,
+    },
+    Command {
+        name: "errors",
+        help: "Shows how many errors there were and what was done about them",
+        args: &[],
+        run: errors,
+    },
+];
+
+fn accelerometer(app: &mut App, _: &Args, out: &mut dyn Console) -> Result<(), Error<'static>> {
+    read_accel(app, 1, out)
+}
+
+fn magnetometer(app: &mut App, _: &Args, out: &mut dyn Console) -> Result<(), Error<'static>> {
+    read_mag(app, 1, out)
+}
+
+fn accel_read(app: &mut App, args: &Args, out: &mut dyn Console) -> Result<(), Error<'static>> {
+    read_accel(app, args.int_or(0, 1), out)
+}
+
+fn mag_read(app: &mut App, args: &Args, out: &mut dyn Console) -> Result<(), Error<'static>> {
+    read_mag(app, args.int_or(0, 1), out)
+}
+
+/// Prints the next `count` samples, waiting for each one to become available.
+fn read_accel(app: &mut App, count: i32, out: &mut dyn Console) -> Result<(), Error<'static>> {
+    for _ in 0..count {
+        let data = app.sensor.accel()?;
+        sample(Source::Accelerometer, data, 0).write_text(out).ok();
+    }
+    Ok(())
+}
+
+fn read_mag(app: &mut App, count: i32, out: &mut dyn Console) -> Result<(), Error<'static>> {
+    for _ in 0..count {
+        let data = app.sensor.mag()?;
+        sample(Source::Magnetometer, data, 0).write_text(out).ok();
+    }
+    Ok(())
+}
+
+/// Sends samples as they come in until the stop byte arrives.
+fn stream(app: &mut App, args: &Args, out: &mut dyn Console) -> Result<(), Error<'static>> {
+    let (accel, mag) = SOURCES[args.choice(0)].1;
+    let format = match args.get(1) {
+        Some(Value::Choice(format)) => FORMATS[format].1,
+        _ => Format::Text,
+    };
+    if format == Format::Csv {
+        write!(out, "{}\r\n", CSV_HEADER).ok();
+    }
+
+    let start = app.clock.read();
+    let mut frame = [0; Sample::MAX_FRAME];
+    while out.read_byte() != Some(STOP) {
+        let accel = if accel {
+            ready(app.sensor.try_accel())?
+        } else {
+            None
+        };
+        let mag = if mag {
+            ready(app.sensor.try_mag())?
+        } else {
+            None
+        };
+        let timestamp = app.clock.read().wrapping_sub(start);
+        let samples = [
+            accel.map(|data| sample(Source::Accelerometer, data, timestamp)),
+            mag.map(|data| sample(Source::Magnetometer, data, timestamp)),
+        ];
+        for sample in samples.iter().flatten() {
+            match format {
+                Format::Text => {
+                    sample.write_text(out).ok();
+                }
+                Format::Csv => {
+                    sample.write_csv(out).ok();
+                }
+                Format::Binary => out.write_bytes(sample.encode(&mut frame)),
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn sample(source: Source, data: Measurement, timestamp: u32) -> Sample {
+    Sample {
+        timestamp,
+        source,
+        x: data.x,
+        y: data.y,
+        z: data.z,
+    }
+}
+
+/// `None` while there is no new sample.
+fn ready<T>(result: nb::Result<T, Error<'static>>) -> Result<Option<T>, Error<'static>> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(nb::Error::WouldBlock) => Ok(None),
+        Err(nb::Error::Other(error)) => Err(error),
+    }
+}
+
+// The settings print themselves the way they are typed, so that the output of `config` can be
+// pasted back in
+
+fn accel_odr(app: &mut App, args: &Args, out: &mut dyn Console) -> Result<(), Error<'static>> {
+    if let Some(Value::Choice(rate)) = args.get(0) {
+        app.sensor.set_accel_odr(ACCEL_ODRS[rate].1)?;
+    }
+    let config = app.sensor.config();
+    write!(
+        out,
+        "accel odr {}\r\n",
+        name_of(&ACCEL_ODRS, &config.accel_odr)
+    )
+    .ok();
+    Ok(())
+}
+
+fn accel_scale(app: &mut App, args: &Args, out: &mut dyn Console) -> Result<(), Error<'static>> {
+    if let Some(Value::Choice(scale)) = args.get(0) {
+        app.sensor.set_accel_scale(ACCEL_SCALES[scale].1)?;
+    }
+    let config = app.sensor.config();
+    write!(
+        out,
+        "accel scale {}\r\n",
+        name_of(&ACCEL_SCALES, &config.accel_scale)
+    )
+    .ok();
+    Ok(())
+}
+
+fn accel_mode(app: &mut App, args: &Args, out: &mut dyn Console) -> Result<(), Error<'static>> {
+    if let Some(Value::Choice(mode)) = args.get(0) {
+        app.sensor.set_accel_mode(ACCEL_MODES[mode].1)?;
+    }
+    let config = app.sensor.config();
+    write!(
+        out,
+        "accel mode {}\r\n",
+        name_of(&ACCEL_MODES, &config.accel_mode)
+    )
+    .ok();
+    Ok(())
+}
+
+fn mag_odr(app: &mut App, args: &Args, out: &mut dyn Console) -> Result<(), Error<'static>> {
+    if let Some(Value::Choice(rate)) = args.get(0) {
+        app.sensor.set_mag_odr(MAG_ODRS[rate].1)?;
+    }
+    let config = app.sensor.config();
+    write!(out, "mag odr {}\r\n", name_of(&MAG_ODRS, &config.mag_odr)).ok();
+    Ok(())
+}
+
+fn mag_mode(app: &mut App, args: &Args, out: &mut dyn Console) -> Result<(), Error<'static>> {
+    if let Some(Value::Choice(mode)) = args.get(0) {
+        app.sensor.set_mag_mode(MAG_MODES[mode].1)?;
+    }
+    let config = app.sensor.config();
+    write!(
+        out,
+        "mag mode {}\r\n",
+        name_of(&MAG_MODES, &config.mag_mode)
+    )
+    .ok();
+    Ok(())
+}
+
+fn config(app: &mut App, _: &Args, out: &mut dyn Console) -> Result<(), Error<'static>> {
+    let config = app.sensor.config();
+    write!(
+        out,
+        "accel odr {}\r\n",
+        name_of(&ACCEL_ODRS, &config.accel_odr)
+    )
+    .ok();
+    write!(
+        out,
+        "accel scale {}\r\n",
+        name_of(&ACCEL_SCALES, &config.accel_scale)
+    )
+    .ok();
+    write!(
+        out,
+        "accel mode {}\r\n",
+        name_of(&ACCEL_MODES, &config.accel_mode)
+    )
+    .ok();
+    write!(out, "mag odr {}\r\n", name_of(&MAG_ODRS, &config.mag_odr)).ok();
+    write!(
+        out,
+        "mag mode {}\r\n",
+        name_of(&MAG_MODES, &config.mag_mode)
+    )
+    .ok();
+    Ok(())
+}
+
+fn errors(app: &mut App, _: &Args, out: &mut dyn Console) -> Result<(), Error<'static>> {
+    write!(out, "{}\r\n", app.errors + app.sensor.errors()).ok();
+    Ok(())
+}
+
+/// The self-test puts the registers it changes back, so the sensor carries on as it was.
+fn selftest(app: &mut App, _: &Args, out: &mut dyn Console) -> Result<(), Error<'static>> {
+    let mut bus = app.bus;
+    write!(out, "{}\r\n", board::selftest::run(&mut bus)).ok();
+    Ok(())
+}
@@ -2,43 +2,26 @@
 #![no_std]
 
 use cortex_m_rt::entry;
-use rtt_target::{rtt_init_print};
-use panic_rtt_target as _;
-use core::str;
+use microbit::display::blocking::Display;
 use microbit::hal::prelude::*;
+use microbit::hal::Timer;
+use panic_rtt_target as _;
+use rtt_target::rtt_init_print;
 
-#[cfg(feature = "v1")]
-use microbit::{
-    hal::twi,
-    pac::twi0::frequency::FREQUENCY_A,
-    hal::uart,
-    hal::uart::{Baudrate, Parity}
-};
-
-#[cfg(feature="v2")]
-use microbit::{
-    hal::twim,
-    pac::twim0::frequency::FREQUENCY_A,
-    hal::prelude::*,
-    hal::uarte,
-    hal::uarte::{Baudrate, Parity}
-};
-
-use lsm303agr::{AccelOutputDataRate, Lsm303agr, MagOutputDataRate, mode, interface::I2cInterface};
-use heapless::Vec;
-use nb::block;
+use board::error::{Counters, Error};
+use core::cell::RefCell;
 use core::fmt::Write;
+use nb::block;
+use shell::LineEditor;
 
-#[cfg(feature="v2")]
-mod serial_setup;
-#[cfg(feature="v2")]
-use serial_setup::UartePort;
-
-const ACCELEROMETER_ADDR: u8 = 0b0011001;
-const MAGNETOMETER_ADDR: u8 = 0b0011110;
-
-const ACCELEROMETER_ID_REG: u8 = 0x0f;
-const MAGNETOMETER_ID_REG: u8 = 0x4f;
+mod bus;
+mod commands;
+mod requests;
+mod sensor;
+use bus::Which;
+use commands::{App, COMMANDS};
+use protocol::message::{Link, Received};
+use sensor::Sensor;
 
 #[entry]
 fn main() -> ! {
@@ -46,57 +29,68 @@ fn main() -> ! {
 
     let board = microbit::Board::take().unwrap();
 
-    #[cfg(feature = "v1")]
-    let mut serial = uart::Uart::new(board.UART0, board.uart.into(), Parity::EXCLUDED, Baudrate::BAUD115200);
-
-    #[cfg(feature = "v2")]
-    let mut serial = {
-        let serial = uarte::Uarte::new(board.UARTE0, board.uart.into(), Parity::EXCLUDED, Baudrate::BAUD115200);
-        UartePort::new(serial)
-    };
-
-    #[cfg(feature = "v1")]
-    let mut i2c = twi::Twi::new(board.TWI0, board.i2c.into(), FREQUENCY_A::K100);
-
+    let mut serial = board::board_serial!(board);
+    let i2c = board::board_i2c!(board);
+    // Shared by the sensor driver and the `i2c` commands
+    let bus = cortex_m::singleton!(: RefCell<board::I2c> = RefCell::new(i2c)).unwrap();
+    let mut bus = board::SharedI2c::new(bus);
     #[cfg(feature = "v2")]
-    let i2c = {twim::Twim::new(board.TWIM0, board.i2c_internal.into(), FREQUENCY_A::K100)};
+    let external = board::board_i2c_external!(board);
+
+    // Before the driver sets the sensor up, a tick or a cross on the display for a second
+    let report = board::selftest::run(&mut bus);
+    write!(serial, "{}\r\n", report).ok();
+    let mut display = Display::new(board.display_pins);
+    display.show(&mut Timer::new(board.TIMER1), report.image(), 1000);
+
+    // Starts at 50Hz, the `accel` and `mag` commands change that
+    let sensor = Sensor::new(bus);
+    if !sensor.is_set_up() {
+        write!(
+            serial,
+            "error: sensor not set up, commands will try again\r\n"
+        )
+        .ok();
+    }
 
-    // Code from documentation
-    let mut sensor = Lsm303agr::new_with_i2c(i2c);
-    sensor.init().unwrap();
-    sensor.set_accel_odr(AccelOutputDataRate::Hz50).unwrap();
-    sensor.set_mag_odr(MagOutputDataRate::Hz50).unwrap();
-    let mut sensor = sensor.into_mag_continuous().ok().unwrap();
+    // Free running at 1MHz, for the timestamps of `stream`
+    let mut clock = Timer::periodic(board.TIMER0);
+    clock.start(u32::MAX);
+    let mut app = App {
+        sensor,
+        bus,
+        #[cfg(feature = "v2")]
+        external,
+        selected: Which::Internal,
+        clock,
+        errors: Counters::default(),
+    };
 
+    let mut editor: LineEditor<32, 4, _> = LineEditor::new("> ", COMMANDS);
+    editor.prompt(&mut serial);
+    // Programs send binary requests on the same line, see `protocol::message`
+    let mut link = Link::new();
+    let mut lost = serial.stats();
     loop {
-        let mut buffer:Vec<u8,32> = Vec::new();
-        loop {
-            let byte = block!(serial.read()).unwrap();
-            block!(serial.write(byte)).unwrap();
-            if byte == 13 {
-                break;
-            }
-
-            if buffer.push(byte).is_err() {
-                write!(serial, "error: buffer full\r\n").unwrap();
-                break;
-            }
-        }
-        let command = str::from_utf8(&buffer).unwrap().trim();
-        if command == "accelerometer" {
-            while !sensor.accel_status().unwrap().xyz_new_data {
-
-            }
-            let data = sensor.accel_data().unwrap();
-            write!(serial, "Accelerometer: x {} y {} z {}\r\n", data.x, data.y, data.z).unwrap();
-        } else if command == "magnetometer" {
-            while !sensor.mag_status().unwrap().xyz_new_data {
-
+        let Ok(byte) = block!(serial.read());
+        match link.feed(byte) {
+            Received::Text(text) => {
+                for &byte in text {
+                    let Some(line) = editor.feed(byte, &mut serial) else {
+                        continue;
+                    };
+                    shell::execute(COMMANDS, &mut app, line, &mut serial);
+                    // Said between commands, where it does not get in the way of their output
+                    if serial.stats() != lost {
+                        lost = serial.stats();
+                        app.errors.count(Error::Serial);
+                        write!(serial, "error: {}\r\n", Error::Serial).ok();
+                    }
+                    editor.prompt(&mut serial);
+                }
             }
-            let data = sensor.mag_data().unwrap();
-            write!(serial, "Magnetometer: x {} y {} z {}\r\n", data.x, data.y, data.z).unwrap();
-        } else {
-            write!(serial, "error: Command {command} not detected\r\n").unwrap();
+            Received::Message(message) => requests::answer(&mut app, message, &mut serial),
+            Received::Nothing => {}
         }
     }
 }
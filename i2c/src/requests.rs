@@ -0,0 +1,113 @@
+//! Answers to the binary requests of `protocol::message`. They reach the same settings as the
+//! shell commands, by the same values.
+use lsm303agr::AccelMode as SensorAccelMode;
+use protocol::frame;
+use protocol::message::{self, AccelMode, Config, Failure, MagMode, Request, Response, Setting};
+use protocol::stream::Source;
+use shell::command::name_of;
+use shell::{Console, Error};
+
+use crate::commands::{sample, App};
+use crate::sensor::{self, ACCEL_ODRS, ACCEL_SCALES, MAG_ODRS};
+
+/// Answers a message the `Link` picked out of the serial input.
+pub fn answer(app: &mut App, message: Result<&[u8], frame::Error>, out: &mut dyn Console) {
+    let response = match message::decode(message) {
+        Ok(request) => run(app, request).unwrap_or_else(Response::Error),
+        Err(error) => {
+            app.errors.count(error.into());
+            Response::Error(error.into())
+        }
+    };
+    let mut buffer = [0; message::MAX_FRAME];
+    // Only a very long error message would not fit
+    if let Ok(frame) = message::encode(&response, &mut buffer) {
+        out.write_bytes(frame);
+    }
+}
+
+fn run(app: &mut App, request: Request) -> Result<Response<'static>, Failure<'static>> {
+    match request {
+        Request::ReadAccel => {
+            let data = app.sensor.accel().map_err(failure)?;
+            Ok(Response::Sample(sample(
+                Source::Accelerometer,
+                data,
+                app.clock.read(),
+            )))
+        }
+        Request::ReadMag => {
+            let data = app.sensor.mag().map_err(failure)?;
+            Ok(Response::Sample(sample(
+                Source::Magnetometer,
+                data,
+                app.clock.read(),
+            )))
+        }
+        Request::GetConfig => Ok(Response::Config(config(app))),
+        Request::Set(setting) => {
+            set(app, setting)?;
+            Ok(Response::Config(config(app)))
+        }
+    }
+}
+
+fn set(app: &mut App, setting: Setting) -> Result<(), Failure<'static>> {
+    let sensor = &mut app.sensor;
+    match setting {
+        Setting::AccelOdr(hz) => sensor.set_accel_odr(find(&ACCEL_ODRS, hz)?),
+        Setting::AccelScale(g) => sensor.set_accel_scale(find(&ACCEL_SCALES, g.into())?),
+        Setting::AccelMode(mode) => sensor.set_accel_mode(match mode {
+            AccelMode::Off => SensorAccelMode::PowerDown,
+            AccelMode::LowPower => SensorAccelMode::LowPower,
+            AccelMode::Normal => SensorAccelMode::Normal,
+            AccelMode::HighResolution => SensorAccelMode::HighResolution,
+        }),
+        Setting::MagOdr(hz) => sensor.set_mag_odr(find(&MAG_ODRS, hz.into())?),
+        Setting::MagMode(mode) => sensor.set_mag_mode(match mode {
+            MagMode::Continuous => sensor::MagMode::Continuous,
+            MagMode::OneShot => sensor::MagMode::OneShot,
+        }),
+    }
+    .map_err(failure)
+}
+
+fn config(app: &App) -> Config {
+    let config = app.sensor.config();
+    Config {
+        accel_odr_hz: number(&ACCEL_ODRS, &config.accel_odr),
+        accel_scale_g: number(&ACCEL_SCALES, &config.accel_scale) as u8,
+        accel_mode: match config.accel_mode {
+            SensorAccelMode::PowerDown => AccelMode::Off,
+            SensorAccelMode::LowPower => AccelMode::LowPower,
+            SensorAccelMode::Normal => AccelMode::Normal,
+            SensorAccelMode::HighResolution => AccelMode::HighResolution,
+        },
+        mag_odr_hz: number(&MAG_ODRS, &config.mag_odr) as u8,
+        mag_mode: match config.mag_mode {
+            sensor::MagMode::Continuous => MagMode::Continuous,
+            sensor::MagMode::OneShot => MagMode::OneShot,
+        },
+    }
+}
+
+// The tables of settings are named by their values in Hz or g, which is what the messages carry
+
+fn find<T: Copy>(table: &[(&str, T)], number: u16) -> Result<T, Failure<'static>> {
+    let (_, value) = table
+        .iter()
+        .find(|(name, _)| name.parse() == Ok(number))
+        .ok_or(Failure::Invalid)?;
+    Ok(*value)
+}
+
+fn number<T: PartialEq>(table: &[(&'static str, T)], value: &T) -> u16 {
+    name_of(table, value).parse().unwrap_or(0)
+}
+
+fn failure(error: Error<'static>) -> Failure<'static> {
+    match error {
+        Error::Failed(reason) => Failure::Failed(reason),
+        _ => Failure::Invalid,
+    }
+}
@@ -0,0 +1,295 @@
+//! The LSM303AGR together with the configuration it runs with.
+//!
+//! The driver keeps the magnetometer mode in the type, so switching it at runtime means holding
+//! whichever of the two drivers is current.
+use board::error::{self, Counters};
+use lsm303agr::{
+    interface::I2cInterface, mode, AccelMode, AccelOutputDataRate, AccelScale, Lsm303agr,
+    MagOutputDataRate, Measurement,
+};
+use nb::block;
+use shell::Error;
+
+type Driver<MODE> = Lsm303agr<I2cInterface<board::SharedI2c<'static>>, MODE>;
+
+enum Device {
+    Continuous(Driver<mode::MagContinuous>),
+    OneShot(Driver<mode::MagOneShot>),
+}
+
+/// Runs `$body` with `$driver` bound to the driver, whatever its magnetometer mode.
+macro_rules! with_driver {
+    ($device:expr, $driver:ident => $body:expr) => {
+        match $device {
+            Device::Continuous($driver) => $body,
+            Device::OneShot($driver) => $body,
+        }
+    };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MagMode {
+    /// Measures at the output data rate.
+    Continuous,
+    /// Idles, and measures once per read.
+    OneShot,
+}
+
+pub const ACCEL_ODRS: [(&str, AccelOutputDataRate); 10] = [
+    ("1", AccelOutputDataRate::Hz1),
+    ("10", AccelOutputDataRate::Hz10),
+    ("25", AccelOutputDataRate::Hz25),
+    ("50", AccelOutputDataRate::Hz50),
+    ("100", AccelOutputDataRate::Hz100),
+    ("200", AccelOutputDataRate::Hz200),
+    ("400", AccelOutputDataRate::Hz400),
+    ("1344", AccelOutputDataRate::Khz1_344),
+    ("1620", AccelOutputDataRate::Khz1_620LowPower),
+    ("5376", AccelOutputDataRate::Khz5_376LowPower),
+];
+
+pub const ACCEL_SCALES: [(&str, AccelScale); 4] = [
+    ("2", AccelScale::G2),
+    ("4", AccelScale::G4),
+    ("8", AccelScale::G8),
+    ("16", AccelScale::G16),
+];
+
+pub const ACCEL_MODES: [(&str, AccelMode); 4] = [
+    ("off", AccelMode::PowerDown),
+    ("low", AccelMode::LowPower),
+    ("normal", AccelMode::Normal),
+    ("high", AccelMode::HighResolution),
+];
+
+pub const MAG_ODRS: [(&str, MagOutputDataRate); 4] = [
+    ("10", MagOutputDataRate::Hz10),
+    ("20", MagOutputDataRate::Hz20),
+    ("50", MagOutputDataRate::Hz50),
+    ("100", MagOutputDataRate::Hz100),
+];
+
+pub const MAG_MODES: [(&str, MagMode); 2] = [
+    ("continuous", MagMode::Continuous),
+    ("oneshot", MagMode::OneShot),
+];
+
+/// What the sensor is currently set to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Config {
+    pub accel_odr: AccelOutputDataRate,
+    pub accel_scale: AccelScale,
+    pub accel_mode: AccelMode,
+    pub mag_odr: MagOutputDataRate,
+    pub mag_mode: MagMode,
+}
+
+pub struct Sensor {
+    /// Only ever `None` while the magnetometer mode changes.
+    device: Option<Device>,
+    config: Config,
+    /// Whether the sensor has `config`, which it might not if setting it up failed at the start.
+    set_up: bool,
+    errors: Counters,
+}
+
+impl Sensor {
+    /// Initialises the sensor with both parts measuring at 50Hz. If that fails, reads and settings
+    /// try again first, see [`Sensor::is_set_up`].
+    pub fn new(i2c: board::SharedI2c<'static>) -> Self {
+        let config = Config {
+            accel_odr: AccelOutputDataRate::Hz50,
+            accel_scale: AccelScale::G2,
+            accel_mode: AccelMode::Normal,
+            mag_odr: MagOutputDataRate::Hz50,
+            mag_mode: MagMode::OneShot,
+        };
+        let mut errors = Counters::default();
+        let mut device = Device::OneShot(Lsm303agr::new_with_i2c(i2c));
+        // Trying again sets it up again anyway
+        let set_up = errors.recover(&mut device, |device| set_up(device, &config), |_| Ok(()));
+
+        let mut sensor = Sensor {
+            device: Some(device),
+            config,
+            set_up: set_up.is_ok(),
+            errors,
+        };
+        // Stays in one shot mode if this fails, which `mag mode` shows
+        sensor.set_mag_mode(MagMode::Continuous).ok();
+        sensor
+    }
+
+    pub fn config(&self) -> Config {
+        self.config
+    }
+
+    pub fn is_set_up(&self) -> bool {
+        self.set_up
+    }
+
+    /// The bus and sensor errors so far.
+    pub fn errors(&self) -> Counters {
+        self.errors
+    }
+
+    /// Runs `op` on the driver, following the policies of [`board::error`] if it fails.
+    fn recover<T>(
+        &mut self,
+        op: impl FnMut(&mut Device) -> Result<T, error::Error>,
+    ) -> Result<T, error::Error> {
+        let config = self.config;
+        // Mode changes always put the device back
+        let device = self.device.as_mut().expect("sensor lost in a mode change");
+        if !self.set_up {
+            self.errors
+                .recover(device, |device| set_up(device, &config), |_| Ok(()))?;
+            self.set_up = true;
+        }
+        self.errors
+            .recover(device, op, |device| set_up(device, &config))
+    }
+
+    /// Waits for the next accelerometer sample, in mg.
+    pub fn accel(&mut self) -> Result<Measurement, Error<'static>> {
+        block!(self.try_accel())
+    }
+
+    /// Waits for the next magnetometer sample, in nT.
+    pub fn mag(&mut self) -> Result<Measurement, Error<'static>> {
+        block!(self.try_mag())
+    }
+
+    /// The next accelerometer sample if there is a new one.
+    pub fn try_accel(&mut self) -> nb::Result<Measurement, Error<'static>> {
+        if self.config.accel_mode == AccelMode::PowerDown {
+            return Err(nb::Error::Other(Error::Failed("accelerometer is off")));
+        }
+        let data = self
+            .recover(|device| {
+                with_driver!(device, driver => {
+                    if !driver.accel_status()?.xyz_new_data {
+                        return Ok(None);
+                    }
+                    Ok(Some(driver.accel_data()?))
+                })
+            })
+            .map_err(|error| nb::Error::Other(error.into()))?;
+        data.ok_or(nb::Error::WouldBlock)
+    }
+
+    /// The next magnetometer sample if there is a new one. In one shot mode this starts a
+    /// measurement unless one is running.
+    pub fn try_mag(&mut self) -> nb::Result<Measurement, Error<'static>> {
+        let data = self
+            .recover(|device| match device {
+                Device::Continuous(driver) => {
+                    if !driver.mag_status()?.xyz_new_data {
+                        return Ok(None);
+                    }
+                    Ok(Some(driver.mag_data()?))
+                }
+                Device::OneShot(driver) => match driver.mag_data() {
+                    Ok(data) => Ok(Some(data)),
+                    Err(nb::Error::WouldBlock) => Ok(None),
+                    Err(nb::Error::Other(error)) => Err(error.into()),
+                },
+            })
+            .map_err(|error| nb::Error::Other(error.into()))?;
+        data.ok_or(nb::Error::WouldBlock)
+    }
+
+    /// Sets the accelerometer data rate. The rates above 1.344kHz only work in low power mode,
+    /// and 1.344kHz not at all in it, so this may switch modes as well.
+    pub fn set_accel_odr(&mut self, odr: AccelOutputDataRate) -> Result<(), Error<'static>> {
+        let mode = self.recover(|device| {
+            with_driver!(device, driver => {
+                driver.set_accel_odr(odr)?;
+                Ok(driver.get_accel_mode())
+            })
+        })?;
+        self.config.accel_odr = odr;
+        self.config.accel_mode = mode;
+        Ok(())
+    }
+
+    pub fn set_accel_scale(&mut self, scale: AccelScale) -> Result<(), Error<'static>> {
+        self.recover(|device| with_driver!(device, driver => Ok(driver.set_accel_scale(scale)?)))?;
+        self.config.accel_scale = scale;
+        Ok(())
+    }
+
+    /// Sets the accelerometer power mode, which has to suit the data rate. Switching on again
+    /// after `PowerDown` goes back to the previous data rate.
+    pub fn set_accel_mode(&mut self, mode: AccelMode) -> Result<(), Error<'static>> {
+        let odr = self.config.accel_odr;
+        let low_power_only = matches!(
+            odr,
+            AccelOutputDataRate::Khz1_620LowPower | AccelOutputDataRate::Khz5_376LowPower
+        );
+        let compatible = match mode {
+            AccelMode::PowerDown => true,
+            AccelMode::LowPower => odr != AccelOutputDataRate::Khz1_344,
+            AccelMode::Normal | AccelMode::HighResolution => !low_power_only,
+        };
+        if !compatible {
+            return Err(Error::Failed("mode does not work at this data rate"));
+        }
+
+        let powered_down = self.config.accel_mode == AccelMode::PowerDown;
+        self.recover(|device| {
+            with_driver!(device, driver => {
+                if powered_down && mode != AccelMode::PowerDown {
+                    driver.set_accel_odr(odr)?;
+                }
+                Ok(driver.set_accel_mode(mode)?)
+            })
+        })?;
+        self.config.accel_mode = mode;
+        Ok(())
+    }
+
+    pub fn set_mag_odr(&mut self, odr: MagOutputDataRate) -> Result<(), Error<'static>> {
+        self.recover(|device| with_driver!(device, driver => Ok(driver.set_mag_odr(odr)?)))?;
+        self.config.mag_odr = odr;
+        Ok(())
+    }
+
+    /// Switches the magnetometer mode. The driver changes type with it, so this is not retried.
+    pub fn set_mag_mode(&mut self, mode: MagMode) -> Result<(), Error<'static>> {
+        let Some(device) = self.device.take() else {
+            return Err(error::Error::Sensor.into());
+        };
+        // A failed change hands the driver back unchanged
+        let (device, result) = match (device, mode) {
+            (Device::Continuous(driver), MagMode::OneShot) => match driver.into_mag_one_shot() {
+                Ok(driver) => (Device::OneShot(driver), Ok(())),
+                Err(error) => (Device::Continuous(error.dev), Err(error.error.into())),
+            },
+            (Device::OneShot(driver), MagMode::Continuous) => match driver.into_mag_continuous() {
+                Ok(driver) => (Device::Continuous(driver), Ok(())),
+                Err(error) => (Device::OneShot(error.dev), Err(error.error.into())),
+            },
+            (device, _) => (device, Ok(())),
+        };
+        self.device = Some(device);
+        if let Err(error) = result {
+            self.errors.count(error);
+            return Err(error.into());
+        }
+        self.config.mag_mode = mode;
+        Ok(())
+    }
+}
+
+/// Puts all of `config` into the sensor, for a sensor or bus that started over.
+fn set_up(device: &mut Device, config: &Config) -> Result<(), error::Error> {
+    with_driver!(device, driver => {
+        driver.init()?;
+        driver.set_accel_odr(config.accel_odr)?;
+        driver.set_accel_mode(config.accel_mode)?;
+        driver.set_accel_scale(config.accel_scale)?;
+        driver.set_mag_odr(config.mag_odr)?;
+        Ok(())
+    })
+}
@@ -1,9 +1,15 @@
-//! This build script copies the `memory.x` file from the crate root into a directory where the
-//! linker can always find it at build time. For many projects this is optional, as the linker
-//! always searches the project root directory (wherever `Cargo.toml` is). However, if you are using
-//! a workspace or have a more complicated build setup, this build script becomes required. Additionally
-//! by requesting that Cargo re-run the build script whenever `memory.x` is changed, a rebuild of the
-//! application with new memory settings is ensured after updating `memory.x`.
+//! This build script copies the `memory.x` file for the board, `memory-v1.x` or `memory-v2.x`,
+//! from the crate root into a directory where the linker can always find it at build time. As the
+//! file to use depends on the features, the linker could not pick it up from the project root
+//! directory by itself. Additionally by requesting that Cargo re-run the build script whenever one
+//! of the files is changed, a rebuild of the application with new memory settings is ensured after
+//! updating it.
+//!
+//! Both reserve the last page of flash for the calibration, 1k on the v1 board and 4k on the v2.
+//!
+//! It also reads the declination settings, `COMPASS_DECLINATION` and `COMPASS_LOCATION`, into
+//! constants in `declination.rs`. A value that is not a number fails the build here, rather than
+//! the firmware on the board.
 
 use std::env;
 use std::fs::File;
@@ -12,15 +18,62 @@ use std::path::PathBuf;
 
 fn main() {
     // Put `memory.x` in our output directory and ensure it's on the linker search path.
+    let memory: &[u8] = if env::var_os("CARGO_FEATURE_V2").is_some() {
+        include_bytes!("memory-v2.x")
+    } else {
+        include_bytes!("memory-v1.x")
+    };
     let out = &PathBuf::from(env::var_os("OUT_DIR").unwrap());
     File::create(out.join("memory.x"))
         .unwrap()
-        .write_all(include_bytes!("memory.x"))
+        .write_all(memory)
         .unwrap();
     println!("cargo:rustc-link-search={}", out.display());
 
     // By default, Cargo will re-run a build script whenever any file in the project changes. By
-    // specifying `memory.x` here, we ensure the build script is only re-run when `memory.x` is
-    // changed.
-    println!("cargo:rerun-if-changed=memory.x");
+    // specifying the memory files here, we ensure the build script is only re-run when one of them
+    // is changed.
+    println!("cargo:rerun-if-changed=memory-v1.x");
+    println!("cargo:rerun-if-changed=memory-v2.x");
+
+    let declination = setting("COMPASS_DECLINATION", |value| number(value, 180.0));
+    let location = setting("COMPASS_LOCATION", |value| {
+        let (latitude, longitude) = value.split_once(',').ok_or("not <latitude>,<longitude>")?;
+        Ok((number(latitude, 90.0)?, number(longitude, 180.0)?))
+    });
+    if location.is_some() && env::var_os("CARGO_FEATURE_TRUE_NORTH").is_none() {
+        println!("cargo:warning=COMPASS_LOCATION is only used with the true-north feature");
+    }
+    File::create(out.join("declination.rs"))
+        .unwrap()
+        .write_all(
+            format!(
+                "/// From `COMPASS_DECLINATION`, in degrees.\n\
+                 const DECLINATION: Option<f32> = {declination:?};\n\
+                 /// From `COMPASS_LOCATION`, latitude and longitude in degrees.\n\
+                 #[cfg_attr(not(feature = \"true-north\"), allow(dead_code))]\n\
+                 const LOCATION: Option<(f32, f32)> = {location:?};\n"
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+}
+
+/// Parses the environment variable `name` if it is set, and fails the build if it does not parse.
+fn setting<T>(name: &str, parse: impl FnOnce(&str) -> Result<T, &'static str>) -> Option<T> {
+    println!("cargo:rerun-if-env-changed={name}");
+    let value = env::var(name).ok()?;
+    match parse(value.trim()) {
+        Ok(value) => Some(value),
+        Err(error) => panic!("{name}={value:?} is {error}"),
+    }
+}
+
+/// A number of degrees no further from 0 than `limit`.
+fn number(value: &str, limit: f32) -> Result<f32, &'static str> {
+    match value.trim().parse::<f32>() {
+        Ok(number) if number.abs() <= limit => Ok(number),
+        Ok(_) => Err("out of range"),
+        Err(_) => Err("not a number"),
+    }
 }
@@ -0,0 +1,12 @@
+MEMORY
+{
+    /* NOTE K = KiBi = 1024 bytes */
+    FLASH : ORIGIN = 0x00000000, LENGTH = 508k
+    /* The last page of the 512k of flash keeps the compass calibration across power cycles, see
+       src/storage.rs */
+    CALIBRATION : ORIGIN = 0x0007F000, LENGTH = 4k
+    RAM : ORIGIN = 0x20000000, LENGTH = 16k
+}
+
+_calibration_start = ORIGIN(CALIBRATION);
+_calibration_end = ORIGIN(CALIBRATION) + LENGTH(CALIBRATION);
@@ -1,6 +0,0 @@
-MEMORY
-{
-    /* NOTE K = KiBi = 1024 bytes */
-    FLASH : ORIGIN = 0x00000000, LENGTH = 256k
-    RAM : ORIGIN = 0x20000000, LENGTH = 16k
-}
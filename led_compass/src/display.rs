@@ -0,0 +1,40 @@
+//! The LED matrix, refreshed from the TIMER1 interrupt so that the main loop never has to wait for
+//! it. That is also what allows showing images with more than one brightness level.
+use core::cell::RefCell;
+use cortex_m::interrupt::{free, Mutex};
+use matrix::Image;
+use microbit::{
+    display::nonblocking::{Display, GreyscaleImage},
+    gpio::DisplayPins,
+    pac::{self, interrupt, TIMER1},
+};
+
+static DISPLAY: Mutex<RefCell<Option<Display<TIMER1>>>> = Mutex::new(RefCell::new(None));
+
+/// Takes over the display pins and starts refreshing the matrix.
+pub fn init(timer: TIMER1, pins: DisplayPins) {
+    let display = Display::new(timer, pins);
+    free(|cs| *DISPLAY.borrow(cs).borrow_mut() = Some(display));
+    #[allow(unsafe_code)]
+    unsafe {
+        pac::NVIC::unmask(pac::Interrupt::TIMER1);
+    }
+}
+
+/// Shows `image` until the next call, brightness levels go from 0 to 9.
+pub fn show(image: Image) {
+    free(|cs| {
+        if let Some(display) = DISPLAY.borrow(cs).borrow_mut().as_mut() {
+            display.show(&GreyscaleImage::new(&image));
+        }
+    });
+}
+
+#[interrupt]
+fn TIMER1() {
+    free(|cs| {
+        if let Some(display) = DISPLAY.borrow(cs).borrow_mut().as_mut() {
+            display.handle_display_event();
+        }
+    });
+}
@@ -8,82 +8,254 @@ use panic_rtt_target as _;
 use rtt_target::{rprintln, rtt_init_print};
 
 mod calibration;
-mod led;
+mod display;
+mod storage;
 
 use crate::calibration::{calc_calibration, calibrated_measurement};
-use microbit::{display::blocking::Display, hal::Timer, Board};
-use led::Direction as LedDirection;
+use microbit::{
+    hal::{prelude::*, Timer},
+    Board,
+};
 
-use core::f32::consts::PI;
-use libm::{atan2f, sqrtf};
+use compass::rose::MAX_BRIGHTNESS;
+use compass::{
+    declination::true_heading,
+    filter::{HeadingFilter, Hysteresis},
+    heading::tilt_compensated_heading,
+    rose,
+};
+use core::fmt::Write;
+use heapless::String;
+use libm::{roundf, sqrtf};
+use matrix::scroll;
 
-#[cfg(feature="v1")]
-use microbit::{hal::twi, pac::twi0::frequency::FREQUENCY_A};
+use board::error::{self, Counters};
+use lsm303agr::{
+    interface::I2cInterface, mode::MagContinuous, AccelOutputDataRate, Lsm303agr,
+    MagOutputDataRate, Measurement,
+};
 
-#[cfg(feature="v2")]
-use microbit::{hal::twim, pac::twim0::frequency::FREQUENCY_A};
+type Sensor = Lsm303agr<I2cInterface<board::I2c>, MagContinuous>;
 
-use lsm303agr::{AccelOutputDataRate, Lsm303agr, MagOutputDataRate};
+/// Weight of the history when smoothing the heading, see `HeadingFilter::new`
+const SMOOTHING: f32 = 0.7;
+/// How far (in radians) the heading has to move past the edge of the sector on display before the
+/// display follows
+const HYSTERESIS: f32 = 0.08;
+
+#[derive(Debug, Clone, Copy)]
+enum Mode {
+    /// One of eight arrows
+    Arrow,
+    /// A spoke for each of the 16 points of the compass rose
+    Rose,
+    /// A needle at the exact angle, dimming the LEDs it passes in between
+    Needle,
+    /// The way the top of the board faces in degrees, scrolling by
+    Degrees,
+}
+
+impl Mode {
+    fn next(self) -> Mode {
+        match self {
+            Mode::Arrow => Mode::Rose,
+            Mode::Rose => Mode::Needle,
+            Mode::Needle => Mode::Degrees,
+            Mode::Degrees => Mode::Arrow,
+        }
+    }
+}
 
 #[entry]
 fn main() -> ! {
     rtt_init_print!();
     let board = Board::take().unwrap();
 
-    #[cfg(feature="v1")]
-    let i2c = { twi::Twi::new(board.TWI0, board.i2c.into(), FREQUENCY_A::K100)};
-
-    #[cfg(feature="v2")]
-    let i2c = {twim::Twim::new(board.TWIM0, board.i2c_internal.into(), FREQUENCY_A::K100)};
+    let mut i2c = board::board_i2c!(board);
+    let mut serial = board::board_serial!(board);
 
     let mut timer = Timer::new(board.TIMER0);
-    let mut display = Display::new(board.display_pins);
+    display::init(board.TIMER1, board.display_pins);
+
+    // A tick or a cross on the display for a second
+    let report = board::selftest::run(&mut i2c);
+    write!(serial, "{}\r\n", report).ok();
+    rprintln!("{}", report);
+    display::show(
+        report
+            .image()
+            .map(|row| row.map(|led| led * MAX_BRIGHTNESS)),
+    );
+    timer.delay_ms(1000_u32);
 
+    // There is no compass without the sensor, so keep trying until it answers
+    let mut errors = Counters::default();
     let mut sensor = Lsm303agr::new_with_i2c(i2c);
-    sensor.init().unwrap();
-    sensor.set_mag_odr(MagOutputDataRate::Hz10).unwrap();
-    sensor.set_accel_odr(AccelOutputDataRate::Hz10).unwrap();
+    while let Err(error) = errors.recover(&mut sensor, set_up, |_| Ok(())) {
+        rprintln!("Error: {} ({})", error, errors);
+        timer.delay_ms(1000_u32);
+    }
+    let mut sensor = loop {
+        match sensor.into_mag_continuous() {
+            Ok(sensor) => break sensor,
+            Err(error) => {
+                let cause = error::Error::from(error.error);
+                errors.count(cause);
+                rprintln!("Error: {} ({})", cause, errors);
+                sensor = error.dev;
+                timer.delay_ms(1000_u32);
+            }
+        }
+    };
 
-    let mut sensor = sensor.into_mag_continuous().ok().unwrap();
+    // Reuse the calibration from the last run unless button A is held down during boot
+    let calibration = {
+        let mut flash = storage::flash();
+        let Ok(button_a_pressed) = board.buttons.button_a.is_low();
+        let loaded = if button_a_pressed {
+            rprintln!("Button A held, recalibrating");
+            None
+        } else {
+            match storage::load(&mut flash) {
+                Ok(calibration) => Some(calibration),
+                Err(e) => {
+                    rprintln!("No usable calibration in flash: {:?}", e);
+                    None
+                }
+            }
+        };
 
-    let calibraion = calc_calibration(&mut sensor, &mut display, &mut timer);
-    rprintln!("Calibration: {:?}", calibraion);
+        match loaded {
+            Some(calibration) => calibration,
+            None => {
+                let calibration = calc_calibration(&mut sensor, &mut errors, &mut timer);
+                if let Err(e) = storage::store(&mut flash, &calibration) {
+                    rprintln!("Failed to store calibration: {:?}", e);
+                }
+                calibration
+            }
+        }
+    };
+    rprintln!("Calibration: {:?}", calibration);
     rprintln!("Calibration done, entering busy loop");
 
+    let declination = declination();
+    rprintln!("Declination: {} degrees", declination);
+
+    // Button B switches between the ways of showing the heading
+    let mut mode = Mode::Arrow;
+    let mut button_b_was_pressed = false;
+
+    let mut filter = HeadingFilter::new(SMOOTHING);
+    let mut arrow = Hysteresis::new(rose::ARROWS.len(), HYSTERESIS);
+    let mut point = Hysteresis::new(rose::POINTS, HYSTERESIS);
+    let mut degrees: String<4> = String::new();
+    let mut frame = 0;
+
     loop {
-        while !sensor.mag_status().unwrap().xyz_new_data {}
-        let mut data = sensor.mag_data().unwrap();
-        data = calibrated_measurement(&data, &calibraion);
-
-        let theta = atan2f(data.y as f32, data.x as f32);
-
-        // Figure out the direction based on theta
-        let dir = if theta < -7. * PI / 8. {
-            LedDirection::West
-        } else if theta < -5. * PI / 8. {
-            LedDirection::SouthWest
-        } else if theta < -3. * PI / 8. {
-            LedDirection::South
-        } else if theta < -PI / 8. {
-            LedDirection::SouthEast
-        } else if theta < PI / 8. {
-            LedDirection::East
-        } else if theta < 3. * PI / 8. {
-            LedDirection::NorthEast
-        } else if theta < 5. * PI / 8. {
-            LedDirection::North
-        } else if theta < 7. * PI / 8. {
-            LedDirection::NorthWest
-        } else {
-            LedDirection::West
+        let Ok(button_b_pressed) = board.buttons.button_b.is_low();
+        if button_b_pressed && !button_b_was_pressed {
+            mode = mode.next();
+            rprintln!("Showing {:?}", mode);
+        }
+        button_b_was_pressed = button_b_pressed;
+
+        // Both sensors run at 10Hz, so the latest accelerometer sample is recent enough
+        let read = recover(&mut sensor, &mut errors, |sensor| {
+            Ok((mag(sensor)?, sensor.accel_data()?))
+        });
+        let Some((mag, accel)) = read else {
+            continue;
         };
+        let data = calibrated_measurement(&mag.into(), &calibration);
+        let accel = accel.into();
 
-        // use the led module to turn the direction into an LED arrow and the led display functions
-        // from chapter 5 to display the arrow
-        display.show(&mut timer, led::direction_to_led(dir), 100);
+        let magnetic = tilt_compensated_heading(&accel, &data);
+        let theta = filter.update(true_heading(magnetic, declination));
 
-        let (x, y, z)  = (data.x as f32, data.y as f32, data.z as f32);
-        let magnitude = sqrtf(x* x + y * y + z * z);
+        display::show(match mode {
+            Mode::Arrow => rose::ARROWS[arrow.update(theta)],
+            Mode::Rose => rose::rose(point.update(theta)),
+            Mode::Needle => rose::needle(theta),
+            Mode::Degrees => {
+                // A column per sample, and a new reading once the last one has gone by
+                if frame >= scroll::frames(&degrees) {
+                    degrees.clear();
+                    write!(degrees, "{}", bearing(theta)).ok();
+                    frame = 0;
+                }
+                frame += 1;
+                scroll::frame(&degrees, frame - 1, MAX_BRIGHTNESS).unwrap_or_default()
+            }
+        });
+
+        let (x, y, z) = (data.x as f32, data.y as f32, data.z as f32);
+        let magnitude = sqrtf(x * x + y * y + z * z);
         rprintln!("{} nT, {} mG", magnitude, magnitude / 100.0);
+        rprintln!(
+            "heading {} true, {} magnetic",
+            theta.to_degrees(),
+            magnetic.to_degrees()
+        );
     }
 }
+
+/// Which way the top of the board faces in whole degrees clockwise from north, for `heading` the
+/// angle of north on the display.
+fn bearing(heading: f32) -> i32 {
+    (roundf(heading.to_degrees()) as i32 - 90).rem_euclid(360)
+}
+
+/// Both sensors at 10Hz, also after the sensor or the bus started over.
+fn set_up<MODE>(
+    sensor: &mut Lsm303agr<I2cInterface<board::I2c>, MODE>,
+) -> Result<(), error::Error> {
+    sensor.init()?;
+    sensor.set_mag_odr(MagOutputDataRate::Hz10)?;
+    sensor.set_accel_odr(AccelOutputDataRate::Hz10)?;
+    Ok(())
+}
+
+/// Runs `op` on the sensor, following the policies of `board::error` if it fails. Failures that
+/// are left are reported over RTT, and the caller goes on without the result.
+pub fn recover<T>(
+    sensor: &mut Sensor,
+    errors: &mut Counters,
+    op: impl FnMut(&mut Sensor) -> Result<T, error::Error>,
+) -> Option<T> {
+    let result = errors.recover(sensor, op, set_up);
+    result
+        .map_err(|error| rprintln!("Error: {} ({})", error, errors))
+        .ok()
+}
+
+/// Waits for the next accelerometer sample.
+pub fn accel(sensor: &mut Sensor) -> Result<Measurement, error::Error> {
+    while !sensor.accel_status()?.xyz_new_data {}
+    Ok(sensor.accel_data()?)
+}
+
+/// Waits for the next magnetometer sample.
+pub fn mag(sensor: &mut Sensor) -> Result<Measurement, error::Error> {
+    while !sensor.mag_status()?.xyz_new_data {}
+    Ok(sensor.mag_data()?)
+}
+
+include!(concat!(env!("OUT_DIR"), "/declination.rs"));
+
+/// Declination in degrees (east positive), fixed at build time. Either set it directly with
+/// `COMPASS_DECLINATION=2.5`, or build with the `true-north` feature and give the location as
+/// `COMPASS_LOCATION=<latitude>,<longitude>` in degrees. Without either the arrow points to
+/// magnetic north. `build.rs` checks the values, so a typo fails the build.
+fn declination() -> f32 {
+    if let Some(declination) = DECLINATION {
+        return declination;
+    }
+
+    #[cfg(feature = "true-north")]
+    if let Some((latitude, longitude)) = LOCATION {
+        return compass::declination::lookup(latitude, longitude);
+    }
+
+    0.0
+}
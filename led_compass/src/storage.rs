@@ -0,0 +1,168 @@
+//! Keeps the [`Calibration`] in the flash page that `memory.x` reserves for it, so we don't have to
+//! play the tilting game after every power cycle. The record format lives in `compass::storage`.
+use crate::calibration::Calibration;
+use compass::storage::{decode, encode, DecodeError, RECORD_LEN};
+use core::ptr::{addr_of, addr_of_mut};
+use embedded_storage::nor_flash::{NorFlash, ReadNorFlash};
+use microbit::pac::{self, NVMC};
+
+#[cfg(feature = "v1")]
+use core::ptr;
+#[cfg(feature = "v2")]
+use core::slice;
+
+#[cfg(feature = "v2")]
+pub use microbit::hal::nvmc::NvmcError as FlashError;
+
+#[cfg(feature = "v2")]
+pub type Flash = microbit::hal::nvmc::Nvmc<NVMC>;
+
+/// The nRF51 HAL has no flash driver, this is just enough of one for a single page.
+#[cfg(feature = "v1")]
+pub struct Flash {
+    nvmc: NVMC,
+    /// The NVMC erases and programs this memory, so it is only ever accessed volatile
+    page: *mut u32,
+    words: usize,
+}
+
+#[cfg(feature = "v1")]
+#[derive(Debug)]
+pub enum FlashError {
+    /// Not on a word or page boundary, or past the end of the page
+    Unaligned,
+}
+
+// The fields are only ever printed
+#[allow(dead_code)]
+#[derive(Debug)]
+pub enum LoadError {
+    Flash(FlashError),
+    Record(DecodeError),
+}
+
+/// Gives access to the reserved calibration page.
+///
+/// Must only be called once, every call hands out the same page.
+#[allow(unsafe_code)]
+pub fn flash() -> Flash {
+    extern "C" {
+        static mut _calibration_start: u32;
+        static mut _calibration_end: u32;
+    }
+
+    // SAFETY: `Board` does not hand out the NVMC, so nobody else is using it
+    let nvmc = unsafe { pac::Peripherals::steal() }.NVMC;
+    let start = addr_of_mut!(_calibration_start);
+    let words = (addr_of!(_calibration_end) as usize - start as usize) / 4;
+
+    // SAFETY: the linker places neither code nor data in the reserved page, we only hand it out
+    // once, and `Nvmc` only reads and programs it with volatile accesses
+    #[cfg(feature = "v2")]
+    return Flash::new(nvmc, unsafe { slice::from_raw_parts_mut(start, words) });
+    // No slice here, the NVMC changes the page behind the compiler's back, so a `&mut` to it
+    // would let the compiler merge or drop accesses. `Flash` only goes through volatile ones.
+    #[cfg(feature = "v1")]
+    return Flash {
+        nvmc,
+        page: start,
+        words,
+    };
+}
+
+pub fn load(flash: &mut Flash) -> Result<Calibration, LoadError> {
+    let mut record = [0; RECORD_LEN];
+    flash.read(0, &mut record).map_err(LoadError::Flash)?;
+    decode(&record).map_err(LoadError::Record)
+}
+
+pub fn store(flash: &mut Flash, calibration: &Calibration) -> Result<(), FlashError> {
+    flash.erase(0, Flash::ERASE_SIZE as u32)?;
+    flash.write(0, &encode(calibration))
+}
+
+#[cfg(feature = "v1")]
+impl Flash {
+    /// Checks that `len` bytes from `offset` are in the page and start on a multiple of `align`.
+    fn check(&self, offset: u32, len: usize, align: usize) -> Result<usize, FlashError> {
+        let offset = offset as usize;
+        if offset.is_multiple_of(align) && offset + len <= self.capacity() {
+            Ok(offset)
+        } else {
+            Err(FlashError::Unaligned)
+        }
+    }
+
+    /// The address of the word at `index`, which [`check`](Self::check) keeps inside the page.
+    fn word(&self, index: usize) -> *mut u32 {
+        self.page.wrapping_add(index)
+    }
+
+    fn wait_ready(&self) {
+        while self.nvmc.ready.read().ready().is_busy() {}
+    }
+}
+
+#[cfg(feature = "v1")]
+impl ReadNorFlash for Flash {
+    type Error = FlashError;
+
+    const READ_SIZE: usize = 1;
+
+    #[allow(unsafe_code)]
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), FlashError> {
+        let offset = self.check(offset, bytes.len(), Self::READ_SIZE)?;
+        self.wait_ready();
+        for (at, byte) in (offset..).zip(bytes) {
+            // SAFETY: `check` keeps the word inside the reserved page
+            let word = unsafe { ptr::read_volatile(self.word(at / 4)) };
+            *byte = word.to_le_bytes()[at % 4];
+        }
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        self.words * 4
+    }
+}
+
+#[cfg(feature = "v1")]
+impl NorFlash for Flash {
+    const WRITE_SIZE: usize = 4;
+
+    const ERASE_SIZE: usize = 1024;
+
+    #[allow(unsafe_code)]
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), FlashError> {
+        let from = self.check(from, 0, Self::ERASE_SIZE)?;
+        let to = self.check(to, 0, Self::ERASE_SIZE)?;
+        self.nvmc.config.write(|w| w.wen().een());
+        for offset in (from..to).step_by(Self::ERASE_SIZE) {
+            let address = self.word(offset / 4) as u32;
+            // SAFETY: the address is in the reserved page, which is what gets erased
+            self.nvmc.erasepage().write(|w| unsafe { w.bits(address) });
+            self.wait_ready();
+        }
+        self.nvmc.config.write(|w| w.wen().ren());
+        Ok(())
+    }
+
+    #[allow(unsafe_code)]
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), FlashError> {
+        let offset = self.check(offset, bytes.len(), Self::WRITE_SIZE)?;
+        if !bytes.len().is_multiple_of(Self::WRITE_SIZE) {
+            return Err(FlashError::Unaligned);
+        }
+        self.nvmc.config.write(|w| w.wen().wen());
+        for (index, word) in (offset / 4..).zip(bytes.chunks_exact(4)) {
+            self.wait_ready();
+            let word = u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+            // SAFETY: `check` keeps the word inside the reserved page
+            unsafe { ptr::write_volatile(self.word(index), word) };
+            cortex_m::asm::dmb();
+        }
+        self.wait_ready();
+        self.nvmc.config.write(|w| w.wen().ren());
+        Ok(())
+    }
+}
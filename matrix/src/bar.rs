@@ -0,0 +1,45 @@
+//! A reading as a bar graph, for values that change too quickly to read as text.
+//!
+//! The LEDs fill up from the bottom row, each row from the left, so every LED is worth a 25th
+//! of the full scale.
+use crate::{Image, SIZE};
+
+/// `value` out of `max`, with the LEDs that are on at `level`. Values outside `0..=max` show
+/// an empty or a full matrix.
+pub fn bar(value: f32, max: f32, level: u8) -> Image {
+    let leds = SIZE * SIZE;
+    // Rounded to the nearest LED, a NaN turns into 0
+    let lit = ((value / max * leds as f32 + 0.5) as usize).min(leds);
+    let mut image = [[0; SIZE]; SIZE];
+    for led in 0..lit {
+        image[SIZE - 1 - led / SIZE][led % SIZE] = level;
+    }
+    image
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lit(image: Image) -> usize {
+        image.iter().flatten().filter(|&&led| led != 0).count()
+    }
+
+    #[test]
+    fn fills_from_the_bottom_left() {
+        let image = bar(7.0, 25.0, 9);
+        assert_eq!(image[4], [9; 5]);
+        assert_eq!(image[3], [9, 9, 0, 0, 0]);
+        assert_eq!(image[2], [0; 5]);
+    }
+
+    #[test]
+    fn rounds_and_clamps() {
+        assert_eq!(lit(bar(0.0, 8.0, 1)), 0);
+        assert_eq!(lit(bar(4.0, 8.0, 1)), 13);
+        assert_eq!(lit(bar(8.0, 8.0, 1)), 25);
+        assert_eq!(lit(bar(20.0, 8.0, 1)), 25);
+        assert_eq!(lit(bar(-1.0, 8.0, 1)), 0);
+        assert_eq!(lit(bar(f32::NAN, 8.0, 1)), 0);
+    }
+}
@@ -0,0 +1,396 @@
+//! A proportional font for the 5x5 LED matrix.
+//!
+//! Every glyph is five rows high and up to five columns wide. Capital letters, digits and some
+//! punctuation have one, so does `g` for the unit. Other lower case letters are shown as capitals,
+//! and anything else as [`UNKNOWN`].
+use crate::SIZE;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Glyph {
+    pub width: usize,
+    /// The top row first, the leftmost column in the highest of `width` bits.
+    pub rows: [u8; SIZE],
+}
+
+impl Glyph {
+    /// Whether the LED in `row` of `column` is on, columns counting from the left.
+    pub fn is_on(&self, row: usize, column: usize) -> bool {
+        column < self.width && self.rows[row] >> (self.width - 1 - column) & 1 == 1
+    }
+}
+
+/// A box, for characters without a glyph.
+pub const UNKNOWN: Glyph = Glyph {
+    width: 4,
+    rows: [0b1111, 0b1001, 0b1001, 0b1001, 0b1111],
+};
+
+pub fn glyph(c: char) -> Glyph {
+    let find = |c| GLYPHS.iter().find(|(glyph, _)| *glyph == c);
+    find(c)
+        .or_else(|| find(c.to_ascii_uppercase()))
+        .map_or(UNKNOWN, |&(_, glyph)| glyph)
+}
+
+const GLYPHS: [(char, Glyph); 47] = [
+    (
+        '0',
+        Glyph {
+            width: 4,
+            rows: [0b0110, 0b1001, 0b1001, 0b1001, 0b0110],
+        },
+    ),
+    (
+        '1',
+        Glyph {
+            width: 3,
+            rows: [0b010, 0b110, 0b010, 0b010, 0b111],
+        },
+    ),
+    (
+        '2',
+        Glyph {
+            width: 4,
+            rows: [0b1110, 0b0001, 0b0110, 0b1000, 0b1111],
+        },
+    ),
+    (
+        '3',
+        Glyph {
+            width: 4,
+            rows: [0b1110, 0b0001, 0b0110, 0b0001, 0b1110],
+        },
+    ),
+    (
+        '4',
+        Glyph {
+            width: 4,
+            rows: [0b0010, 0b0110, 0b1010, 0b1111, 0b0010],
+        },
+    ),
+    (
+        '5',
+        Glyph {
+            width: 4,
+            rows: [0b1111, 0b1000, 0b1110, 0b0001, 0b1110],
+        },
+    ),
+    (
+        '6',
+        Glyph {
+            width: 4,
+            rows: [0b0110, 0b1000, 0b1110, 0b1001, 0b0110],
+        },
+    ),
+    (
+        '7',
+        Glyph {
+            width: 4,
+            rows: [0b1111, 0b0001, 0b0010, 0b0100, 0b0100],
+        },
+    ),
+    (
+        '8',
+        Glyph {
+            width: 4,
+            rows: [0b0110, 0b1001, 0b0110, 0b1001, 0b0110],
+        },
+    ),
+    (
+        '9',
+        Glyph {
+            width: 4,
+            rows: [0b0110, 0b1001, 0b0111, 0b0001, 0b0110],
+        },
+    ),
+    (
+        ' ',
+        Glyph {
+            width: 3,
+            rows: [0b000, 0b000, 0b000, 0b000, 0b000],
+        },
+    ),
+    (
+        '.',
+        Glyph {
+            width: 1,
+            rows: [0b0, 0b0, 0b0, 0b0, 0b1],
+        },
+    ),
+    (
+        ',',
+        Glyph {
+            width: 2,
+            rows: [0b00, 0b00, 0b00, 0b01, 0b10],
+        },
+    ),
+    (
+        ':',
+        Glyph {
+            width: 1,
+            rows: [0b0, 0b1, 0b0, 0b1, 0b0],
+        },
+    ),
+    (
+        '-',
+        Glyph {
+            width: 3,
+            rows: [0b000, 0b000, 0b111, 0b000, 0b000],
+        },
+    ),
+    (
+        '+',
+        Glyph {
+            width: 3,
+            rows: [0b000, 0b010, 0b111, 0b010, 0b000],
+        },
+    ),
+    (
+        '!',
+        Glyph {
+            width: 1,
+            rows: [0b1, 0b1, 0b1, 0b0, 0b1],
+        },
+    ),
+    (
+        '?',
+        Glyph {
+            width: 4,
+            rows: [0b1110, 0b0001, 0b0110, 0b0000, 0b0100],
+        },
+    ),
+    (
+        '/',
+        Glyph {
+            width: 5,
+            rows: [0b00001, 0b00010, 0b00100, 0b01000, 0b10000],
+        },
+    ),
+    (
+        '%',
+        Glyph {
+            width: 5,
+            rows: [0b10001, 0b00010, 0b00100, 0b01000, 0b10001],
+        },
+    ),
+    (
+        'A',
+        Glyph {
+            width: 4,
+            rows: [0b0110, 0b1001, 0b1111, 0b1001, 0b1001],
+        },
+    ),
+    (
+        'B',
+        Glyph {
+            width: 4,
+            rows: [0b1110, 0b1001, 0b1110, 0b1001, 0b1110],
+        },
+    ),
+    (
+        'C',
+        Glyph {
+            width: 4,
+            rows: [0b0111, 0b1000, 0b1000, 0b1000, 0b0111],
+        },
+    ),
+    (
+        'D',
+        Glyph {
+            width: 4,
+            rows: [0b1110, 0b1001, 0b1001, 0b1001, 0b1110],
+        },
+    ),
+    (
+        'E',
+        Glyph {
+            width: 4,
+            rows: [0b1111, 0b1000, 0b1110, 0b1000, 0b1111],
+        },
+    ),
+    (
+        'F',
+        Glyph {
+            width: 4,
+            rows: [0b1111, 0b1000, 0b1110, 0b1000, 0b1000],
+        },
+    ),
+    (
+        'G',
+        Glyph {
+            width: 4,
+            rows: [0b0111, 0b1000, 0b1011, 0b1001, 0b0111],
+        },
+    ),
+    (
+        'H',
+        Glyph {
+            width: 4,
+            rows: [0b1001, 0b1001, 0b1111, 0b1001, 0b1001],
+        },
+    ),
+    (
+        'I',
+        Glyph {
+            width: 3,
+            rows: [0b111, 0b010, 0b010, 0b010, 0b111],
+        },
+    ),
+    (
+        'J',
+        Glyph {
+            width: 4,
+            rows: [0b1111, 0b0001, 0b0001, 0b1001, 0b0110],
+        },
+    ),
+    (
+        'K',
+        Glyph {
+            width: 4,
+            rows: [0b1001, 0b1010, 0b1100, 0b1010, 0b1001],
+        },
+    ),
+    (
+        'L',
+        Glyph {
+            width: 4,
+            rows: [0b1000, 0b1000, 0b1000, 0b1000, 0b1111],
+        },
+    ),
+    (
+        'M',
+        Glyph {
+            width: 5,
+            rows: [0b10001, 0b11011, 0b10101, 0b10001, 0b10001],
+        },
+    ),
+    (
+        'N',
+        Glyph {
+            width: 5,
+            rows: [0b10001, 0b11001, 0b10101, 0b10011, 0b10001],
+        },
+    ),
+    (
+        'O',
+        Glyph {
+            width: 4,
+            rows: [0b0110, 0b1001, 0b1001, 0b1001, 0b0110],
+        },
+    ),
+    (
+        'P',
+        Glyph {
+            width: 4,
+            rows: [0b1110, 0b1001, 0b1110, 0b1000, 0b1000],
+        },
+    ),
+    (
+        'Q',
+        Glyph {
+            width: 4,
+            rows: [0b0110, 0b1001, 0b1001, 0b0110, 0b0001],
+        },
+    ),
+    (
+        'R',
+        Glyph {
+            width: 4,
+            rows: [0b1110, 0b1001, 0b1110, 0b1010, 0b1001],
+        },
+    ),
+    (
+        'S',
+        Glyph {
+            width: 4,
+            rows: [0b0111, 0b1000, 0b0110, 0b0001, 0b1110],
+        },
+    ),
+    (
+        'T',
+        Glyph {
+            width: 5,
+            rows: [0b11111, 0b00100, 0b00100, 0b00100, 0b00100],
+        },
+    ),
+    (
+        'U',
+        Glyph {
+            width: 4,
+            rows: [0b1001, 0b1001, 0b1001, 0b1001, 0b0110],
+        },
+    ),
+    (
+        'V',
+        Glyph {
+            width: 5,
+            rows: [0b10001, 0b10001, 0b10001, 0b01010, 0b00100],
+        },
+    ),
+    (
+        'W',
+        Glyph {
+            width: 5,
+            rows: [0b10001, 0b10001, 0b10101, 0b11011, 0b10001],
+        },
+    ),
+    (
+        'X',
+        Glyph {
+            width: 4,
+            rows: [0b1001, 0b1001, 0b0110, 0b1001, 0b1001],
+        },
+    ),
+    (
+        'Y',
+        Glyph {
+            width: 5,
+            rows: [0b10001, 0b01010, 0b00100, 0b00100, 0b00100],
+        },
+    ),
+    (
+        'Z',
+        Glyph {
+            width: 4,
+            rows: [0b1111, 0b0010, 0b0100, 0b1000, 0b1111],
+        },
+    ),
+    (
+        'g',
+        Glyph {
+            width: 4,
+            rows: [0b0000, 0b0111, 0b1001, 0b0111, 0b0110],
+        },
+    ),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glyphs_fit_their_width() {
+        for (c, glyph) in GLYPHS {
+            assert!((1..=SIZE).contains(&glyph.width), "{:?}", c);
+            for row in glyph.rows {
+                assert!(row >> glyph.width == 0, "{:?}", c);
+            }
+        }
+    }
+
+    #[test]
+    fn lower_case_falls_back_to_capitals() {
+        assert_eq!(glyph('a'), glyph('A'));
+        assert_ne!(glyph('g'), glyph('G'));
+        assert_eq!(glyph('é'), UNKNOWN);
+    }
+
+    #[test]
+    fn columns_count_from_the_left() {
+        let one = glyph('1');
+        assert!(one.is_on(1, 0));
+        assert!(!one.is_on(0, 0));
+        assert!(one.is_on(0, 1));
+        assert!(!one.is_on(0, 3));
+    }
+}
@@ -0,0 +1,23 @@
+//! Text and readings for the 5x5 LED matrix, as images for `microbit::display`.
+//!
+//! The applications show the images with the display driver they already use, for example:
+//!
+//! ```ignore
+//! let mut display = Display::new(board.display_pins);
+//! for image in matrix::scroll::Scroll::new("4.7g", 1) {
+//!     display.show(&mut timer, image, 120);
+//! }
+//! ```
+#![cfg_attr(not(test), no_std)]
+#![deny(unsafe_code)]
+
+pub mod bar;
+pub mod font;
+pub mod scroll;
+
+/// How many rows and columns the matrix has.
+pub const SIZE: usize = 5;
+
+/// Rows of LEDs from the top, each from the left. The blocking display turns on the LEDs that are
+/// not 0, the non-blocking one takes brightness levels from 0 to 9.
+pub type Image = [[u8; SIZE]; SIZE];
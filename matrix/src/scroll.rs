@@ -0,0 +1,133 @@
+//! Text moving across the matrix from right to left, a column per frame.
+//!
+//! The first frame has the first column of the text at the right edge, the last one has the last
+//! column of the text at the left edge. Glyphs are a column apart.
+use crate::font::glyph;
+use crate::{Image, SIZE};
+
+/// How many columns `text` takes, with the gaps between glyphs.
+pub fn width(text: &str) -> usize {
+    let glyphs: usize = text.chars().map(|c| glyph(c).width).sum();
+    glyphs + text.chars().count().saturating_sub(1)
+}
+
+/// How many frames it takes for `text` to go by.
+pub fn frames(text: &str) -> usize {
+    match width(text) {
+        0 => 0,
+        width => width + SIZE - 1,
+    }
+}
+
+/// Frame `index` of `text` going by, with the LEDs that are on at `level`. `None` once the text
+/// has gone by.
+pub fn frame(text: &str, index: usize, level: u8) -> Option<Image> {
+    if index >= frames(text) {
+        return None;
+    }
+    let mut image = [[0; SIZE]; SIZE];
+    for column in 0..SIZE {
+        // The column of the text shown in this column of the matrix
+        let Some(offset) = (index + column).checked_sub(SIZE - 1) else {
+            continue;
+        };
+        for (row, leds) in image.iter_mut().enumerate() {
+            if is_on(text, row, offset) {
+                leds[column] = level;
+            }
+        }
+    }
+    Some(image)
+}
+
+/// Whether the LED in `row` of column `offset` of the text is on.
+fn is_on(text: &str, row: usize, mut offset: usize) -> bool {
+    for c in text.chars() {
+        let glyph = glyph(c);
+        if offset < glyph.width {
+            return glyph.is_on(row, offset);
+        }
+        // The glyph and the gap after it
+        match offset.checked_sub(glyph.width + 1) {
+            Some(next) => offset = next,
+            None => return false,
+        }
+    }
+    false
+}
+
+/// The frames of [`frame`] one after the other.
+#[derive(Debug, Clone)]
+pub struct Scroll<'a> {
+    text: &'a str,
+    level: u8,
+    index: usize,
+}
+
+impl<'a> Scroll<'a> {
+    pub fn new(text: &'a str, level: u8) -> Self {
+        Scroll {
+            text,
+            level,
+            index: 0,
+        }
+    }
+}
+
+impl Iterator for Scroll<'_> {
+    type Item = Image;
+
+    fn next(&mut self) -> Option<Image> {
+        let image = frame(self.text, self.index, self.level)?;
+        self.index += 1;
+        Some(image)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn width_has_gaps_between_glyphs() {
+        assert_eq!(width(""), 0);
+        // 4, 1, 4 and 4 columns wide
+        assert_eq!(width("4.7g"), 13 + 3);
+        assert_eq!(frames("."), 5);
+        assert_eq!(frames(""), 0);
+    }
+
+    #[test]
+    fn text_comes_in_on_the_right_and_leaves_on_the_left() {
+        let images: Vec<_> = Scroll::new("1", 9).collect();
+        assert_eq!(images.len(), 3 + 4);
+        // The first column of the 1
+        assert_eq!(images[0].map(|row| row[4]), [0, 9, 0, 0, 9]);
+        assert_eq!(images[0].map(|row| row[3]), [0; 5]);
+        // All of it, from the left edge
+        assert_eq!(
+            images[4],
+            [
+                [0, 9, 0, 0, 0],
+                [9, 9, 0, 0, 0],
+                [0, 9, 0, 0, 0],
+                [0, 9, 0, 0, 0],
+                [9, 9, 9, 0, 0],
+            ]
+        );
+        // Its last column
+        assert_eq!(images[6].map(|row| row[0]), [0, 0, 0, 0, 9]);
+        assert_eq!(images[6].map(|row| row[1]), [0; 5]);
+        assert_eq!(frame("1", 7, 9), None);
+    }
+
+    #[test]
+    fn gap_separates_glyphs() {
+        // The last column of the first 1, the gap and the first two of the second
+        let image = frame("11", 6, 1).unwrap();
+        assert_eq!(image.map(|row| row[0]), [0, 0, 0, 0, 1]);
+        assert_eq!(image.map(|row| row[1]), [0; 5]);
+        assert_eq!(image.map(|row| row[2]), [0, 1, 0, 0, 1]);
+        assert_eq!(image.map(|row| row[3]), [1, 1, 1, 1, 1]);
+    }
+}
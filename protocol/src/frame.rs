@@ -0,0 +1,199 @@
+//! Framing binary messages.
+//!
+//! A frame is the message followed by its CRC-16 (CCITT, little endian), COBS encoded so that it
+//! contains no zero bytes, and terminated by a zero. A receiver that starts listening halfway
+//! through a stream, or that lost a byte, is back in step at the next zero, and a frame that was
+//! garbled on the way fails the checksum.
+use crc::{Crc, CRC_16_IBM_3740};
+use heapless::Vec;
+
+/// Ends every frame.
+pub const DELIMITER: u8 = 0;
+
+const CRC: Crc<u16> = Crc::<u16>::new(&CRC_16_IBM_3740);
+
+/// The most bytes [`encode`] needs for a message of `len` bytes.
+pub const fn max_frame_len(len: usize) -> usize {
+    let encoded = len + 2;
+    // COBS adds a byte per 254 and one more, then comes the delimiter
+    encoded + encoded / 254 + 2
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The buffer handed to [`encode`] is shorter than [`max_frame_len`].
+    BufferTooSmall,
+    /// The frame did not fit into the buffer of the [`Decoder`].
+    TooLong,
+    /// The frame is not valid COBS, or too short to hold a checksum.
+    Malformed,
+    /// The checksum does not match, the frame was corrupted.
+    Checksum,
+}
+
+/// Frames `message` into `buffer` and returns the part of it to send.
+pub fn encode<'b>(message: &[u8], buffer: &'b mut [u8]) -> Result<&'b [u8], Error> {
+    if buffer.len() < max_frame_len(message.len()) {
+        return Err(Error::BufferTooSmall);
+    }
+    let crc = CRC.checksum(message).to_le_bytes();
+    let mut encoder = cobs::CobsEncoder::new(buffer);
+    encoder.push(message).map_err(|_| Error::BufferTooSmall)?;
+    encoder.push(&crc).map_err(|_| Error::BufferTooSmall)?;
+    let len = encoder.finalize().map_err(|_| Error::BufferTooSmall)?;
+    buffer[len] = DELIMITER;
+    Ok(&buffer[..=len])
+}
+
+/// Picks frames out of a stream of received bytes, for messages of up to `N - 3` bytes.
+#[derive(Debug, Default)]
+pub struct Decoder<const N: usize> {
+    buffer: Vec<u8, N>,
+    overflowed: bool,
+    /// The buffer holds the last frame, to be cleared with the next byte.
+    complete: bool,
+}
+
+impl<const N: usize> Decoder<N> {
+    pub const fn new() -> Self {
+        Decoder {
+            buffer: Vec::new(),
+            overflowed: false,
+            complete: false,
+        }
+    }
+
+    /// Handles one received byte. At the end of a frame returns its message, or why it had to be
+    /// dropped.
+    pub fn feed(&mut self, byte: u8) -> Option<Result<&[u8], Error>> {
+        if self.complete {
+            self.complete = false;
+            self.buffer.clear();
+        }
+        if byte != DELIMITER {
+            if self.buffer.push(byte).is_err() {
+                self.overflowed = true;
+            }
+            return None;
+        }
+
+        self.complete = true;
+        if self.overflowed {
+            self.overflowed = false;
+            return Some(Err(Error::TooLong));
+        }
+        if self.buffer.is_empty() {
+            // Several delimiters in a row, senders may use them to flush the line
+            return None;
+        }
+        Some(self.check())
+    }
+
+    fn check(&mut self) -> Result<&[u8], Error> {
+        let len = cobs::decode_in_place(&mut self.buffer).map_err(|_| Error::Malformed)?;
+        if len < 2 {
+            return Err(Error::Malformed);
+        }
+        let (message, crc) = self.buffer[..len].split_at(len - 2);
+        if CRC.checksum(message).to_le_bytes() == crc {
+            Ok(message)
+        } else {
+            Err(Error::Checksum)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(message: &[u8]) -> std::vec::Vec<u8> {
+        let mut buffer = [0; 64];
+        encode(message, &mut buffer).unwrap().to_vec()
+    }
+
+    fn decode<const N: usize>(
+        decoder: &mut Decoder<N>,
+        bytes: &[u8],
+    ) -> std::vec::Vec<Result<std::vec::Vec<u8>, Error>> {
+        bytes
+            .iter()
+            .filter_map(|byte| decoder.feed(*byte).map(|result| result.map(|m| m.to_vec())))
+            .collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut decoder = Decoder::<32>::new();
+        for message in [
+            &b""[..],
+            b"\x00",
+            b"hello",
+            b"\x00\x00\x01\x00",
+            &[0xff; 20],
+        ] {
+            let frame = frame(message);
+            assert_eq!(frame.iter().filter(|b| **b == 0).count(), 1);
+            assert_eq!(frame.last(), Some(&DELIMITER));
+            assert!(frame.len() <= max_frame_len(message.len()));
+            assert_eq!(decode(&mut decoder, &frame), [Ok(message.to_vec())]);
+        }
+    }
+
+    #[test]
+    fn known_checksum() {
+        // The check value of CRC-16/CCITT-FALSE
+        assert_eq!(CRC.checksum(b"123456789"), 0x29b1);
+    }
+
+    #[test]
+    fn buffer_too_small() {
+        let mut buffer = [0; 8];
+        assert_eq!(encode(b"hello", &mut buffer), Err(Error::BufferTooSmall));
+        assert!(encode(b"hello", &mut [0; 9]).is_ok());
+    }
+
+    #[test]
+    fn resynchronises_after_garbage() {
+        let mut decoder = Decoder::<32>::new();
+        let mut stream = b"noise".to_vec();
+        stream.push(0);
+        stream.extend(frame(b"one"));
+        stream.extend([0, 0]);
+        stream.extend(frame(b"two"));
+        let results = decode(&mut decoder, &stream);
+        assert_eq!(results.len(), 3);
+        assert!(results[0].is_err());
+        assert_eq!(results[1..], [Ok(b"one".to_vec()), Ok(b"two".to_vec())]);
+    }
+
+    #[test]
+    fn corruption_is_detected() {
+        let mut decoder = Decoder::<32>::new();
+        let mut corrupted = frame(b"hello");
+        corrupted[3] ^= 0x10;
+        assert_eq!(decode(&mut decoder, &corrupted), [Err(Error::Checksum)]);
+
+        // A lost byte breaks either the COBS encoding or the checksum
+        let mut short = frame(b"hello");
+        short.remove(2);
+        let results = decode(&mut decoder, &short);
+        assert!(matches!(
+            results[..],
+            [Err(Error::Checksum | Error::Malformed)]
+        ));
+
+        assert_eq!(decode(&mut decoder, &[0x01, 0x00]), [Err(Error::Malformed)]);
+    }
+
+    #[test]
+    fn long_frames_are_dropped() {
+        let mut decoder = Decoder::<8>::new();
+        let mut stream = frame(&[7; 10]);
+        stream.extend(frame(b"ok"));
+        assert_eq!(
+            decode(&mut decoder, &stream),
+            [Err(Error::TooLong), Ok(b"ok".to_vec())]
+        );
+    }
+}
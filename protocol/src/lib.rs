@@ -0,0 +1,7 @@
+//! What the applications and the tools on the host say to each other over the serial line.
+#![cfg_attr(not(test), no_std)]
+#![deny(unsafe_code)]
+
+pub mod frame;
+pub mod message;
+pub mod stream;
@@ -0,0 +1,356 @@
+//! Requests and responses for programs, next to the shell for people.
+//!
+//! A program sends a [`Request`] and the board answers each with a [`Response`], one at a time.
+//! Both go out as a [frame] holding [`VERSION`] and the message in the
+//! [postcard](https://postcard.jamesmunns.com) format, with a [`DELIMITER`] in front as well.
+//! [`Link`] tells a request from typed text by that first delimiter, so both work on the same
+//! serial port. Terminals send a zero byte for Ctrl-@ too, and the text after one goes back to the
+//! shell as soon as it cannot be a frame.
+//!
+//! Adding a variant at the end of an enum keeps old messages readable. Anything else that changes
+//! the encoding needs a new [`VERSION`].
+use crate::frame::{self, Decoder, DELIMITER};
+use crate::stream::Sample;
+use heapless::Vec;
+use serde::{Deserialize, Serialize};
+
+/// The first byte of every message.
+pub const VERSION: u8 = 1;
+
+/// The longest message, version included.
+pub const MAX_MESSAGE: usize = 64;
+
+/// The longest frame, leading delimiter included.
+pub const MAX_FRAME: usize = frame::max_frame_len(MAX_MESSAGE) + 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Request {
+    /// The next accelerometer sample, answered with [`Response::Sample`].
+    ReadAccel,
+    /// The next magnetometer sample, answered with [`Response::Sample`].
+    ReadMag,
+    /// Answered with [`Response::Config`].
+    GetConfig,
+    /// Changes one setting, answered with the [`Response::Config`] that results.
+    Set(Setting),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Response<'a> {
+    /// A sample with its time in microseconds since the board started.
+    Sample(Sample),
+    Config(Config),
+    #[serde(borrow)]
+    Error(Failure<'a>),
+}
+
+/// Why a request was not carried out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Failure<'a> {
+    /// The request was damaged on the way.
+    Damaged,
+    /// The request was made for another version, the board speaks this one.
+    Version(u8),
+    /// The request is not one the board knows, or a setting has a value it does not support.
+    Invalid,
+    /// The sensor could not do it, for the reason given.
+    Failed(&'a str),
+}
+
+/// The sensor settings, with the same values the shell commands take.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Config {
+    pub accel_odr_hz: u16,
+    pub accel_scale_g: u8,
+    pub accel_mode: AccelMode,
+    pub mag_odr_hz: u8,
+    pub mag_mode: MagMode,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Setting {
+    AccelOdr(u16),
+    AccelScale(u8),
+    AccelMode(AccelMode),
+    MagOdr(u8),
+    MagMode(MagMode),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AccelMode {
+    Off,
+    LowPower,
+    Normal,
+    HighResolution,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MagMode {
+    Continuous,
+    OneShot,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The frame was damaged.
+    Frame(frame::Error),
+    /// The message starts with this version instead of [`VERSION`].
+    Version(u8),
+    /// The message does not fit into [`MAX_MESSAGE`].
+    TooLong,
+    /// The message is not of the expected type.
+    Malformed,
+}
+
+impl From<Error> for Failure<'_> {
+    /// What to answer a request that could not be decoded with.
+    fn from(error: Error) -> Self {
+        match error {
+            Error::Frame(_) => Failure::Damaged,
+            Error::Version(_) => Failure::Version(VERSION),
+            Error::TooLong | Error::Malformed => Failure::Invalid,
+        }
+    }
+}
+
+/// Frames `message` into `buffer` and returns the part of it to send.
+pub fn encode<'b, T: Serialize>(
+    message: &T,
+    buffer: &'b mut [u8; MAX_FRAME],
+) -> Result<&'b [u8], Error> {
+    let mut bytes = [0; MAX_MESSAGE];
+    bytes[0] = VERSION;
+    let len = postcard::to_slice(message, &mut bytes[1..])
+        .map_err(|_| Error::TooLong)?
+        .len();
+    buffer[0] = DELIMITER;
+    let frame = frame::encode(&bytes[..=len], &mut buffer[1..]).map_err(Error::Frame)?;
+    let len = frame.len();
+    Ok(&buffer[..=len])
+}
+
+/// Reads a message out of what a [`frame::Decoder`] or a [`Link`] returned.
+pub fn decode<'a, T: Deserialize<'a>>(message: Result<&'a [u8], frame::Error>) -> Result<T, Error> {
+    let message = message.map_err(Error::Frame)?;
+    match message.split_first() {
+        Some((&VERSION, message)) => postcard::from_bytes(message).map_err(|_| Error::Malformed),
+        Some((&version, _)) => Err(Error::Version(version)),
+        None => Err(Error::Malformed),
+    }
+}
+
+/// What a byte received by a [`Link`] turned out to be.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Received<'a> {
+    /// Typed at a terminal, for the shell. Usually the byte just received, but everything since
+    /// the delimiter when that turned out not to start a frame.
+    Text(&'a [u8]),
+    /// The end of a frame, for [`decode`].
+    Message(Result<&'a [u8], frame::Error>),
+    /// Part of a frame.
+    Nothing,
+}
+
+/// Separates the frames of requests from text on a serial port.
+///
+/// After a delimiter every COBS code byte says how far away the next one is. Once one points
+/// further than a frame of [`MAX_FRAME`] can reach, the bytes were not a frame but a zero typed at
+/// a terminal, or a frame that lost its last delimiter on the way. Either way they go back to the
+/// shell as text instead of holding it up until the next request.
+#[derive(Debug, Default)]
+pub struct Link {
+    decoder: Decoder<MAX_FRAME>,
+    framing: bool,
+    /// What was received since the delimiter, to give back as text.
+    received: Vec<u8, MAX_FRAME>,
+    /// Where in `received` the next code byte is due.
+    next_code: usize,
+}
+
+impl Link {
+    pub const fn new() -> Self {
+        Link {
+            decoder: Decoder::new(),
+            framing: false,
+            received: Vec::new(),
+            next_code: 0,
+        }
+    }
+
+    pub fn feed(&mut self, byte: u8) -> Received<'_> {
+        if !self.framing {
+            self.received.clear();
+            if byte != DELIMITER {
+                self.received.push(byte).ok();
+                return Received::Text(&self.received);
+            }
+            self.framing = true;
+            self.next_code = 0;
+            self.decoder = Decoder::new();
+            return Received::Nothing;
+        }
+
+        if byte != DELIMITER {
+            if self.received.len() == self.next_code {
+                self.next_code += byte as usize;
+            }
+            // Always fits, a frame ends before the buffer does
+            self.received.push(byte).ok();
+            // Past the leading delimiter and short of the last one
+            if self.next_code > MAX_FRAME - 2 {
+                self.framing = false;
+                return Received::Text(&self.received);
+            }
+        }
+        match self.decoder.feed(byte) {
+            Some(message) => {
+                self.framing = false;
+                Received::Message(message)
+            }
+            None => Received::Nothing,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::Source;
+    use std::vec::Vec;
+
+    const CONFIG: Config = Config {
+        accel_odr_hz: 1344,
+        accel_scale_g: 16,
+        accel_mode: AccelMode::Normal,
+        mag_odr_hz: 100,
+        mag_mode: MagMode::OneShot,
+    };
+
+    const RESPONSES: [Response; 5] = [
+        Response::Sample(Sample {
+            timestamp: 4_000_000_000,
+            source: Source::Magnetometer,
+            x: -7_372_800,
+            y: 0,
+            z: 300,
+        }),
+        Response::Config(CONFIG),
+        Response::Error(Failure::Failed("mode does not work at this data rate")),
+        Response::Error(Failure::Version(VERSION)),
+        Response::Error(Failure::Damaged),
+    ];
+
+    /// The messages in `bytes` as received by a [`Link`], and the text around them.
+    fn receive(bytes: &[u8]) -> (Vec<Result<Vec<u8>, frame::Error>>, String) {
+        let mut link = Link::new();
+        let mut messages = Vec::new();
+        let mut text = String::new();
+        for &byte in bytes {
+            match link.feed(byte) {
+                Received::Text(bytes) => text.extend(bytes.iter().map(|&byte| byte as char)),
+                Received::Message(message) => messages.push(message.map(<[u8]>::to_vec)),
+                Received::Nothing => {}
+            }
+        }
+        (messages, text)
+    }
+
+    fn frame<T: Serialize>(message: &T) -> Vec<u8> {
+        let mut buffer = [0; MAX_FRAME];
+        encode(message, &mut buffer).unwrap().to_vec()
+    }
+
+    #[test]
+    fn requests_round_trip() {
+        for request in [
+            Request::ReadAccel,
+            Request::ReadMag,
+            Request::GetConfig,
+            Request::Set(Setting::AccelOdr(5376)),
+            Request::Set(Setting::AccelMode(AccelMode::Off)),
+            Request::Set(Setting::MagMode(MagMode::Continuous)),
+        ] {
+            let frame = frame(&request);
+            assert_eq!(frame[0], DELIMITER);
+            assert!(frame.len() <= MAX_FRAME);
+            let (messages, text) = receive(&frame);
+            assert_eq!(text, "");
+            let message = messages[0].as_deref().map_err(|error| *error);
+            assert_eq!(decode(message), Ok(request));
+        }
+    }
+
+    #[test]
+    fn messages_and_text_share_the_line() {
+        let mut bytes = b"accel odr 10\r".to_vec();
+        for response in &RESPONSES {
+            bytes.extend(frame(response));
+        }
+        bytes.extend(b"config\r");
+        let (messages, text) = receive(&bytes);
+        assert_eq!(text, "accel odr 10\rconfig\r");
+        let responses: Vec<Response> = messages
+            .iter()
+            .map(|message| decode(message.as_deref().map_err(|error| *error)).unwrap())
+            .collect();
+        assert_eq!(responses, RESPONSES);
+    }
+
+    #[test]
+    fn bad_messages_are_reported() {
+        let mut damaged = frame(&Request::ReadAccel);
+        damaged[2] ^= 0x10;
+        let mut future = [0; MAX_FRAME];
+        let future = frame::encode(&[VERSION + 1, 0], &mut future).unwrap();
+        let mut not_a_request = [0; MAX_FRAME];
+        let not_a_request = frame::encode(&[VERSION, 200], &mut not_a_request).unwrap();
+
+        for (bytes, error, failure) in [
+            (
+                &damaged[..],
+                Error::Frame(frame::Error::Checksum),
+                Failure::Damaged,
+            ),
+            (
+                future,
+                Error::Version(VERSION + 1),
+                Failure::Version(VERSION),
+            ),
+            (not_a_request, Error::Malformed, Failure::Invalid),
+        ] {
+            let mut link = [DELIMITER].to_vec();
+            link.extend(bytes);
+            let (messages, _) = receive(&link);
+            let message = messages[0].as_deref().map_err(|error| *error);
+            assert_eq!(decode::<Request>(message), Err(error));
+            assert_eq!(Failure::from(error), failure);
+        }
+    }
+
+    #[test]
+    fn unfinished_frames_give_way_to_text() {
+        let mut bytes = frame(&Request::GetConfig);
+        bytes.pop();
+        bytes.extend([b'x'; MAX_FRAME]);
+        let (messages, text) = receive(&bytes);
+        assert!(messages.is_empty());
+        assert!(text.ends_with(&"x".repeat(MAX_FRAME)));
+    }
+
+    #[test]
+    fn typed_zero_gives_way_to_text() {
+        // Ctrl-@, then a command. Its first letter is too large a code byte for a frame.
+        let mut bytes = b"\0accel odr 10\r".to_vec();
+        bytes.extend(frame(&Request::GetConfig));
+        let (messages, text) = receive(&bytes);
+        assert_eq!(text, "accel odr 10\r");
+        let message = messages[0].as_deref().map_err(|error| *error);
+        assert_eq!(decode(message), Ok(Request::GetConfig));
+    }
+
+    #[test]
+    fn long_messages_do_not_fit() {
+        let long = Response::Error(Failure::Failed(core::str::from_utf8(&[b'a'; 80]).unwrap()));
+        assert_eq!(encode(&long, &mut [0; MAX_FRAME]), Err(Error::TooLong));
+    }
+}
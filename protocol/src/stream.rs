@@ -0,0 +1,257 @@
+//! Sensor samples as streamed by the `stream` command of the `i2c` app.
+//!
+//! The samples go out in one of three [`Format`]s:
+//!
+//! - `text`, the same lines the `read` commands print, for watching in a terminal.
+//! - `csv`, a [`CSV_HEADER`] and then a line per sample with its time in microseconds since the
+//!   stream started.
+//! - `binary`, [`Sample::to_bytes`] in a [frame] per sample. That is about half the
+//!   size of a CSV line and survives lost or corrupted bytes.
+//!
+//! The stream ends when the board receives [`STOP`].
+use crate::frame;
+use core::fmt::{self, Write};
+use serde::{Deserialize, Serialize};
+
+/// Stops a stream, Ctrl-C in a terminal.
+pub const STOP: u8 = 0x03;
+
+/// The first line of a CSV stream.
+pub const CSV_HEADER: &str = "time_us,sensor,x,y,z";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    Csv,
+    Binary,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Source {
+    /// Acceleration in mg.
+    Accelerometer,
+    /// Magnetic field in nT.
+    Magnetometer,
+}
+
+impl Source {
+    fn tag(self) -> u8 {
+        match self {
+            Source::Accelerometer => 1,
+            Source::Magnetometer => 2,
+        }
+    }
+
+    fn from_tag(tag: u8) -> Option<Self> {
+        match tag {
+            1 => Some(Source::Accelerometer),
+            2 => Some(Source::Magnetometer),
+            _ => None,
+        }
+    }
+
+    /// The name in CSV lines.
+    pub fn short_name(self) -> &'static str {
+        match self {
+            Source::Accelerometer => "accel",
+            Source::Magnetometer => "mag",
+        }
+    }
+
+    /// The name in text lines.
+    pub fn name(self) -> &'static str {
+        match self {
+            Source::Accelerometer => "Accelerometer",
+            Source::Magnetometer => "Magnetometer",
+        }
+    }
+}
+
+/// One reading of one of the sensors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Sample {
+    /// Microseconds since the stream started, wrapping around after about 71 minutes. Text lines
+    /// do not carry it and read back as 0.
+    pub timestamp: u32,
+    pub source: Source,
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+impl Sample {
+    /// The length of [`Sample::to_bytes`].
+    pub const SIZE: usize = 17;
+
+    /// The longest binary frame.
+    pub const MAX_FRAME: usize = frame::max_frame_len(Self::SIZE);
+
+    /// A tag for the source followed by the timestamp and the axes, all little endian.
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0; Self::SIZE];
+        bytes[0] = self.source.tag();
+        bytes[1..5].copy_from_slice(&self.timestamp.to_le_bytes());
+        bytes[5..9].copy_from_slice(&self.x.to_le_bytes());
+        bytes[9..13].copy_from_slice(&self.y.to_le_bytes());
+        bytes[13..17].copy_from_slice(&self.z.to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let bytes: &[u8; Self::SIZE] = bytes.try_into().ok()?;
+        let word = |at: usize| [bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]];
+        Some(Sample {
+            source: Source::from_tag(bytes[0])?,
+            timestamp: u32::from_le_bytes(word(1)),
+            x: i32::from_le_bytes(word(5)),
+            y: i32::from_le_bytes(word(9)),
+            z: i32::from_le_bytes(word(13)),
+        })
+    }
+
+    /// The binary frame for the sample.
+    pub fn encode<'b>(&self, buffer: &'b mut [u8; Self::MAX_FRAME]) -> &'b [u8] {
+        // The buffer has the right length by its type
+        frame::encode(&self.to_bytes(), buffer).unwrap_or_default()
+    }
+
+    pub fn write_text<W: Write + ?Sized>(&self, out: &mut W) -> fmt::Result {
+        write!(
+            out,
+            "{}: x {} y {} z {}\r\n",
+            self.source.name(),
+            self.x,
+            self.y,
+            self.z
+        )
+    }
+
+    pub fn write_csv<W: Write + ?Sized>(&self, out: &mut W) -> fmt::Result {
+        write!(
+            out,
+            "{},{},{},{},{}\r\n",
+            self.timestamp,
+            self.source.short_name(),
+            self.x,
+            self.y,
+            self.z
+        )
+    }
+
+    /// Reads back a line written by [`Sample::write_text`], without the line ending.
+    pub fn parse_text(line: &str) -> Option<Self> {
+        let (name, axes) = line.trim().split_once(": ")?;
+        let source = [Source::Accelerometer, Source::Magnetometer]
+            .into_iter()
+            .find(|source| source.name() == name)?;
+        let mut words = axes.split(' ');
+        let mut axis = |label: &str| {
+            if words.next()? == label {
+                words.next()?.parse().ok()
+            } else {
+                None
+            }
+        };
+        let (x, y, z) = (axis("x")?, axis("y")?, axis("z")?);
+        words.next().is_none().then_some(Sample {
+            timestamp: 0,
+            source,
+            x,
+            y,
+            z,
+        })
+    }
+
+    /// Reads back a line written by [`Sample::write_csv`], without the line ending.
+    pub fn parse_csv(line: &str) -> Option<Self> {
+        let mut fields = line.trim().split(',');
+        let timestamp = fields.next()?.parse().ok()?;
+        let source = match fields.next()? {
+            "accel" => Source::Accelerometer,
+            "mag" => Source::Magnetometer,
+            _ => return None,
+        };
+        let mut axis = || fields.next()?.parse().ok();
+        let (x, y, z) = (axis()?, axis()?, axis()?);
+        fields.next().is_none().then_some(Sample {
+            timestamp,
+            source,
+            x,
+            y,
+            z,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frame::Decoder;
+
+    const ACCEL: Sample = Sample {
+        timestamp: 123_456,
+        source: Source::Accelerometer,
+        x: -16,
+        y: 980,
+        z: 2,
+    };
+
+    const MAG: Sample = Sample {
+        timestamp: u32::MAX,
+        source: Source::Magnetometer,
+        x: -7_372_800,
+        y: 0,
+        z: 300,
+    };
+
+    #[test]
+    fn bytes_round_trip() {
+        for sample in [ACCEL, MAG] {
+            assert_eq!(Sample::from_bytes(&sample.to_bytes()), Some(sample));
+        }
+        assert_eq!(ACCEL.to_bytes()[..5], [1, 0x40, 0xe2, 0x01, 0x00]);
+        assert_eq!(Sample::from_bytes(&[0; Sample::SIZE]), None);
+        assert_eq!(Sample::from_bytes(&ACCEL.to_bytes()[1..]), None);
+    }
+
+    #[test]
+    fn frames_round_trip() {
+        let mut decoder = Decoder::<{ Sample::MAX_FRAME }>::new();
+        let mut buffer = [0; Sample::MAX_FRAME];
+        for sample in [ACCEL, MAG] {
+            let frame = sample.encode(&mut buffer).to_vec();
+            let (last, rest) = frame.split_last().unwrap();
+            assert!(rest.iter().all(|byte| decoder.feed(*byte).is_none()));
+            let decoded = decoder.feed(*last).unwrap().unwrap();
+            assert_eq!(Sample::from_bytes(decoded), Some(sample));
+        }
+    }
+
+    #[test]
+    fn text_round_trip() {
+        let mut line = String::new();
+        ACCEL.write_text(&mut line).unwrap();
+        assert_eq!(line, "Accelerometer: x -16 y 980 z 2\r\n");
+        assert_eq!(
+            Sample::parse_text(&line),
+            Some(Sample {
+                timestamp: 0,
+                ..ACCEL
+            })
+        );
+        assert_eq!(Sample::parse_text("Magnetometer: x 1 y 2"), None);
+        assert_eq!(Sample::parse_text("Magnetometer: x 1 y 2 z 3 w 4"), None);
+        assert_eq!(Sample::parse_text("Gyro: x 1 y 2 z 3"), None);
+    }
+
+    #[test]
+    fn csv_round_trip() {
+        let mut line = String::new();
+        MAG.write_csv(&mut line).unwrap();
+        assert_eq!(line, "4294967295,mag,-7372800,0,300\r\n");
+        assert_eq!(Sample::parse_csv(&line), Some(MAG));
+        assert_eq!(Sample::parse_csv(CSV_HEADER), None);
+        assert_eq!(Sample::parse_csv("1,mag,1,2"), None);
+        assert_eq!(Sample::parse_csv("1,gyro,1,2,3"), None);
+    }
+}
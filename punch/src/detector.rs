@@ -0,0 +1,250 @@
+//! Finding punches in the acceleration with the gravity taken off.
+//!
+//! A punch starts when the acceleration goes above [`Config::trigger`] and ends when it drops
+//! below [`Config::release`], which is lower so that a punch wobbling around the trigger stays
+//! one punch. Anything shorter than [`Config::min_duration`] was a knock on the board rather than
+//! a punch. Pulling the arm back decelerates it about as hard as the punch, so for
+//! [`Config::refractory`] after a punch nothing starts a new one.
+use crate::Vector;
+use core::fmt;
+
+/// Times are in ms, accelerations in g.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Config {
+    pub trigger: f32,
+    pub release: f32,
+    pub min_duration: u32,
+    pub refractory: u32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            trigger: 1.5,
+            release: 0.5,
+            min_duration: 40,
+            refractory: 150,
+        }
+    }
+}
+
+/// The strongest sample of a punch.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Peak {
+    pub acceleration: Vector,
+    pub magnitude: f32,
+}
+
+impl Peak {
+    pub fn new(acceleration: Vector) -> Self {
+        Peak {
+            acceleration,
+            magnitude: acceleration.magnitude(),
+        }
+    }
+
+    /// Keeps `acceleration` if it is stronger than the peak so far.
+    pub fn update(&mut self, acceleration: Vector) {
+        let sample = Peak::new(acceleration);
+        if sample.magnitude > self.magnitude {
+            *self = sample;
+        }
+    }
+}
+
+impl fmt::Display for Peak {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:.2}g towards {} ({} g)",
+            self.magnitude,
+            self.acceleration.direction(),
+            self.acceleration
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Punch {
+    /// When the acceleration went above the trigger.
+    pub start: u32,
+    /// From the start until the acceleration dropped below the release.
+    pub duration: u32,
+    pub peak: Peak,
+}
+
+impl fmt::Display for Punch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} in {}ms", self.peak, self.duration)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    Idle,
+    Punching {
+        start: u32,
+        peak: Peak,
+    },
+    /// Since the end of the last punch.
+    Refractory {
+        end: u32,
+    },
+}
+
+pub struct Detector {
+    config: Config,
+    state: State,
+}
+
+impl Detector {
+    pub fn new(config: Config) -> Self {
+        Detector {
+            config,
+            state: State::Idle,
+        }
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Whether a punch is going on, which may still turn out to be a knock.
+    pub fn is_punching(&self) -> bool {
+        matches!(self.state, State::Punching { .. })
+    }
+
+    /// Adds the sample taken at `time`, and gives the punch it ended if there was one.
+    pub fn update(&mut self, time: u32, acceleration: Vector) -> Option<Punch> {
+        let magnitude = acceleration.magnitude();
+        match self.state {
+            State::Refractory { end } if time.wrapping_sub(end) < self.config.refractory => None,
+            State::Idle | State::Refractory { .. } => {
+                self.state = if magnitude > self.config.trigger {
+                    State::Punching {
+                        start: time,
+                        peak: Peak::new(acceleration),
+                    }
+                } else {
+                    State::Idle
+                };
+                None
+            }
+            State::Punching { start, mut peak } => {
+                if magnitude >= self.config.release {
+                    peak.update(acceleration);
+                    self.state = State::Punching { start, peak };
+                    return None;
+                }
+                let duration = time.wrapping_sub(start);
+                if duration < self.config.min_duration {
+                    self.state = State::Idle;
+                    return None;
+                }
+                self.state = State::Refractory { end: time };
+                Some(Punch {
+                    start,
+                    duration,
+                    peak,
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gravity::Rest;
+    use crate::synthetic;
+
+    /// The punches in a synthetic trace, after the gravity from its first still second.
+    fn punches(trace: &str, config: Config) -> Vec<Punch> {
+        // The synthetic traces are at 400Hz
+        let mut rest = Rest::new(400);
+        let mut gravity = None;
+        let mut detector = Detector::new(config);
+        let mut punches = Vec::new();
+        for (time, sample) in synthetic::samples(trace) {
+            let Some(gravity) = gravity else {
+                gravity = rest.add(sample);
+                continue;
+            };
+            punches.extend(detector.update(time, sample.minus(gravity)));
+        }
+        punches
+    }
+
+    #[test]
+    fn synthetic_jab_is_one_punch_without_the_pull_back() {
+        let punches = punches(synthetic::JAB, Config::default());
+        assert_eq!(punches.len(), 1);
+        let punch = punches[0];
+        assert_eq!(punch.peak.acceleration.direction(), "+x");
+        assert!((punch.peak.magnitude - 4.0).abs() < 0.3, "{}", punch);
+        assert!((60..=120).contains(&punch.duration), "{}", punch);
+    }
+
+    #[test]
+    fn synthetic_combo_is_every_punch_in_it() {
+        let punches = punches(synthetic::COMBO, Config::default());
+        let directions: Vec<_> = punches
+            .iter()
+            .map(|punch| punch.peak.acceleration.direction())
+            .collect();
+        assert_eq!(directions, ["+x", "+y", "+x"]);
+    }
+
+    #[test]
+    fn synthetic_knocks_are_not_punches() {
+        assert_eq!(punches(synthetic::KNOCKS, Config::default()), []);
+    }
+
+    #[test]
+    fn short_minimum_lets_synthetic_knocks_through() {
+        let config = Config {
+            min_duration: 0,
+            ..Config::default()
+        };
+        assert!(!punches(synthetic::KNOCKS, config).is_empty());
+    }
+
+    #[test]
+    fn no_refractory_period_counts_the_synthetic_pull_back() {
+        let config = Config {
+            refractory: 0,
+            ..Config::default()
+        };
+        let punches = punches(synthetic::JAB, config);
+        assert_eq!(punches.len(), 2);
+        assert_eq!(punches[1].peak.acceleration.direction(), "-x");
+    }
+
+    #[test]
+    fn gravity_is_taken_off_whichever_way_the_board_is_held() {
+        let punches = punches(synthetic::ROTATED, Config::default());
+        assert_eq!(punches.len(), 1);
+        assert_eq!(punches[0].peak.acceleration.direction(), "+z");
+        assert!(
+            (punches[0].peak.magnitude - 3.0).abs() < 0.3,
+            "{}",
+            punches[0]
+        );
+    }
+
+    #[test]
+    fn dipping_between_release_and_trigger_stays_one_punch() {
+        let mut detector = Detector::new(Config::default());
+        let g = |x| Vector { x, y: 0.0, z: 0.0 };
+        let samples = [0.0, 2.0, 1.0, 2.5, 1.0, 3.0, 0.0];
+        let punches: Vec<_> = samples
+            .iter()
+            .enumerate()
+            .filter_map(|(i, &x)| detector.update(i as u32 * 20, g(x)))
+            .collect();
+        assert_eq!(punches.len(), 1);
+        assert_eq!(punches[0].start, 20);
+        assert_eq!(punches[0].duration, 100);
+        assert_eq!(punches[0].peak.magnitude, 3.0);
+    }
+}
@@ -0,0 +1,87 @@
+//! The gravity to take off the samples.
+//!
+//! The accelerometer measures gravity as well as the punch. Averaging it while the board lies
+//! still gives a [`Vector`] to take off every later sample, which leaves only what the hand did,
+//! however the board is held.
+use crate::Vector;
+
+/// How far from 1g a sample may be and still count as the board lying still.
+pub const REST_TOLERANCE: f32 = 0.1;
+
+/// Averages samples into the gravity, starting over whenever the board moves.
+#[derive(Debug, Clone, Copy)]
+pub struct Rest {
+    samples: u32,
+    sum: Vector,
+    count: u32,
+}
+
+impl Rest {
+    /// Waits for `samples` still ones in a row, a second's worth is plenty.
+    pub fn new(samples: u32) -> Self {
+        Rest {
+            samples,
+            sum: Vector::default(),
+            count: 0,
+        }
+    }
+
+    /// Adds a sample, and gives the gravity once there were enough still ones in a row.
+    pub fn add(&mut self, sample: Vector) -> Option<Vector> {
+        if (sample.magnitude() - 1.0).abs() > REST_TOLERANCE {
+            *self = Rest::new(self.samples);
+            return None;
+        }
+        self.sum = self.sum.plus(sample);
+        self.count += 1;
+        if self.count < self.samples {
+            return None;
+        }
+        let n = self.count as f32;
+        Some(Vector {
+            x: self.sum.x / n,
+            y: self.sum.y / n,
+            z: self.sum.z / n,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::synthetic;
+
+    /// A second of the synthetic traces.
+    const SAMPLES: u32 = 400;
+
+    fn gravity(trace: &str) -> Vector {
+        let mut rest = Rest::new(SAMPLES);
+        synthetic::samples(trace)
+            .into_iter()
+            .find_map(|(_, sample)| rest.add(sample))
+            .unwrap()
+    }
+
+    #[test]
+    fn gravity_points_down_the_board() {
+        let gravity = gravity(synthetic::JAB);
+        assert_eq!(gravity.direction(), "+z");
+        assert!((gravity.magnitude() - 1.0).abs() < 0.02);
+
+        assert_eq!(self::gravity(synthetic::ROTATED).direction(), "-y");
+    }
+
+    #[test]
+    fn moving_starts_over() {
+        let still = Vector::from_mg(0, 0, 1000);
+        let mut rest = Rest::new(SAMPLES);
+        for _ in 1..SAMPLES {
+            assert_eq!(rest.add(still), None);
+        }
+        assert_eq!(rest.add(Vector::from_mg(1500, 0, 1000)), None);
+        for _ in 1..SAMPLES {
+            assert_eq!(rest.add(still), None);
+        }
+        assert_eq!(rest.add(still), Some(still));
+    }
+}
@@ -0,0 +1,129 @@
+//! Punch detection for the punchometer.
+//!
+//! The firmware feeds the samples to a [`gravity::Rest`] first, then to a [`detector::Detector`]
+//! with the gravity taken off, and adds the punches it finds to a [`session::Session`].
+#![cfg_attr(not(test), no_std)]
+#![deny(unsafe_code)]
+
+pub mod detector;
+pub mod gravity;
+pub mod session;
+
+use core::fmt;
+use libm::sqrtf;
+
+/// An acceleration in g.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Vector {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Vector {
+    /// From the sensor, which measures in mg.
+    pub fn from_mg(x: i32, y: i32, z: i32) -> Self {
+        Vector {
+            x: x as f32 / 1000.0,
+            y: y as f32 / 1000.0,
+            z: z as f32 / 1000.0,
+        }
+    }
+
+    pub fn magnitude(self) -> f32 {
+        sqrtf(self.x * self.x + self.y * self.y + self.z * self.z)
+    }
+
+    pub fn plus(self, other: Vector) -> Vector {
+        Vector {
+            x: self.x + other.x,
+            y: self.y + other.y,
+            z: self.z + other.z,
+        }
+    }
+
+    pub fn minus(self, other: Vector) -> Vector {
+        Vector {
+            x: self.x - other.x,
+            y: self.y - other.y,
+            z: self.z - other.z,
+        }
+    }
+
+    /// The axis that has the largest part of the vector, with its sign, like `-y`.
+    pub fn direction(self) -> &'static str {
+        let (x, y, z) = (self.x.abs(), self.y.abs(), self.z.abs());
+        if x >= y && x >= z {
+            if self.x < 0. {
+                "-x"
+            } else {
+                "+x"
+            }
+        } else if y >= z {
+            if self.y < 0. {
+                "-y"
+            } else {
+                "+y"
+            }
+        } else if self.z < 0. {
+            "-z"
+        } else {
+            "+z"
+        }
+    }
+}
+
+impl fmt::Display for Vector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "x {:.2} y {:.2} z {:.2}", self.x, self.y, self.z)
+    }
+}
+
+/// Reading the synthetic traces in `synthetic/`, for the tests of all modules.
+#[cfg(test)]
+mod synthetic {
+    use crate::Vector;
+
+    /// The samples of a trace: the time in ms and the acceleration. The lines are as the `csv`
+    /// format of the `stream` command writes them, `time_us,accel,x,y,z` with the axes in mg.
+    /// Other lines, and lines starting with `#`, are skipped.
+    pub fn samples(trace: &str) -> Vec<(u32, Vector)> {
+        trace
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.starts_with('#'))
+            .filter_map(|line| {
+                let fields: Vec<&str> = line.split(',').collect();
+                let [time, "accel", x, y, z] = fields[..] else {
+                    return None;
+                };
+                let axis = |field: &str| field.parse().unwrap();
+                let time: u32 = time.parse().unwrap();
+                Some((time / 1000, Vector::from_mg(axis(x), axis(y), axis(z))))
+            })
+            .collect()
+    }
+
+    pub const JAB: &str = include_str!("../synthetic/jab.csv");
+    pub const COMBO: &str = include_str!("../synthetic/combo.csv");
+    pub const KNOCKS: &str = include_str!("../synthetic/knocks.csv");
+    pub const ROTATED: &str = include_str!("../synthetic/rotated.csv");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn direction_is_the_largest_axis_with_its_sign() {
+        assert_eq!(Vector::from_mg(2000, -1000, 500).direction(), "+x");
+        assert_eq!(Vector::from_mg(200, -1000, 500).direction(), "-y");
+        assert_eq!(Vector::from_mg(200, 100, -3000).direction(), "-z");
+    }
+
+    #[test]
+    fn magnitude_counts_every_axis() {
+        let vector = Vector::from_mg(3000, 0, -4000);
+        assert!((vector.magnitude() - 5.0).abs() < 1e-4);
+    }
+}
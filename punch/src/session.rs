@@ -0,0 +1,121 @@
+//! Statistics over a run of punches.
+//!
+//! A session starts with the first punch and is over after [`Session::is_over`] finds no punch
+//! for a while, so that a pause between rounds starts a new one.
+use crate::detector::{Peak, Punch};
+use core::fmt;
+
+/// How long without a punch ends a session, in ms.
+pub const GAP: u32 = 5_000;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Session {
+    pub count: u32,
+    /// The strongest punch.
+    pub best: Option<Peak>,
+    /// The peaks of all punches, added up.
+    total: f32,
+    /// The start of the first punch and the end of the last one.
+    first: u32,
+    last: u32,
+}
+
+impl Session {
+    pub fn add(&mut self, punch: &Punch) {
+        if self.count == 0 {
+            self.first = punch.start;
+        }
+        self.count += 1;
+        self.total += punch.peak.magnitude;
+        self.last = punch.start.wrapping_add(punch.duration);
+        if self
+            .best
+            .is_none_or(|best| punch.peak.magnitude > best.magnitude)
+        {
+            self.best = Some(punch.peak);
+        }
+    }
+
+    /// The average peak, in g.
+    pub fn average(&self) -> Option<f32> {
+        (self.count > 0).then(|| self.total / self.count as f32)
+    }
+
+    /// How many punches there were per minute from the first to the end of the last.
+    pub fn per_minute(&self) -> Option<f32> {
+        let ms = self.last.wrapping_sub(self.first);
+        (self.count > 1 && ms > 0).then(|| self.count as f32 * 60_000.0 / ms as f32)
+    }
+
+    /// Whether there was no punch for [`GAP`] until `time`.
+    pub fn is_over(&self, time: u32) -> bool {
+        self.count > 0 && time.wrapping_sub(self.last) >= GAP
+    }
+}
+
+impl fmt::Display for Session {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let noun = if self.count == 1 { "punch" } else { "punches" };
+        write!(f, "{} {}", self.count, noun)?;
+        if let (Some(best), Some(average)) = (self.best, self.average()) {
+            write!(f, ", best {:.2}g, average {:.2}g", best.magnitude, average)?;
+        }
+        if let Some(per_minute) = self.per_minute() {
+            write!(f, ", {:.0} per minute", per_minute)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Vector;
+
+    fn punch(start: u32, g: f32) -> Punch {
+        Punch {
+            start,
+            duration: 100,
+            peak: Peak::new(Vector {
+                x: g,
+                y: 0.0,
+                z: 0.0,
+            }),
+        }
+    }
+
+    #[test]
+    fn statistics_cover_every_punch() {
+        let mut session = Session::default();
+        for (start, g) in [(0, 3.0), (400, 5.0), (900, 4.0), (1400, 2.0)] {
+            session.add(&punch(start, g));
+        }
+        assert_eq!(session.count, 4);
+        assert_eq!(session.best.unwrap().magnitude, 5.0);
+        assert_eq!(session.average(), Some(3.5));
+        // Four punches in 1.5s
+        assert_eq!(session.per_minute(), Some(160.0));
+        assert_eq!(
+            session.to_string(),
+            "4 punches, best 5.00g, average 3.50g, 160 per minute"
+        );
+    }
+
+    #[test]
+    fn one_punch_has_no_rate() {
+        let mut session = Session::default();
+        assert_eq!(session.to_string(), "0 punches");
+        session.add(&punch(1000, 3.0));
+        assert_eq!(session.per_minute(), None);
+        assert_eq!(session.to_string(), "1 punch, best 3.00g, average 3.00g");
+    }
+
+    #[test]
+    fn pause_ends_the_session() {
+        let mut session = Session::default();
+        assert!(!session.is_over(100_000));
+        session.add(&punch(1000, 3.0));
+        assert!(!session.is_over(1100 + GAP - 1));
+        assert!(session.is_over(1100 + GAP));
+    }
+}
@@ -3,94 +3,222 @@
 #![no_std]
 
 use cortex_m_rt::entry;
-use rtt_target::{rtt_init_print, rprintln};
 use panic_rtt_target as _;
+use rtt_target::{rprintln, rtt_init_print};
 
-#[cfg(feature="v1")]
-use microbit::{
-    hal::twi,
-    pac::twi0::frequency::FREQUENCY_A,
-};
-
-#[cfg(feature="v2")]
-use microbit::{
-    hal::twim,
-    pac::twim0::frequency::FREQUENCY_A,
-};
-
+use board::error::{self, Counters};
+use board::fifo::{self, Burst};
+use board::wakeup;
 use lsm303agr::{
-    AccelScale, AccelOutputDataRate, Lsm303agr
+    interface::I2cInterface, mode, AccelMode, AccelOutputDataRate, AccelScale, Lsm303agr,
 };
 
+use core::cell::RefCell;
+use core::fmt::Write;
+use heapless::String;
+use matrix::bar::bar;
+use matrix::scroll;
+use microbit::display::blocking::Display;
+use microbit::hal::clocks::Clocks;
+use microbit::hal::rtc::Rtc;
 use microbit::hal::timer::Timer;
-use microbit::hal::prelude::*;
-use nb::Error;
+use microbit::pac::RTC0;
+use punch::detector::{Config, Detector};
+use punch::gravity::Rest;
+use punch::session::Session;
+use punch::Vector;
+
+// Impacts only last a few ms, so the sensor measures as often as the bus can keep up with
+const ODR: AccelOutputDataRate = AccelOutputDataRate::Hz400;
+const ODR_HZ: u32 = 400;
+const MODE: AccelMode = AccelMode::HighResolution;
+// Allow the sensor to measure up to 16 G since human punches can actually be quite fast
+const SCALE: AccelScale = AccelScale::G16;
+
+/// How long to leave the FIFO filling, half of what it holds at 400Hz.
+const BURST_MS: u32 = 40;
+
+/// How many bursts each frame of scrolling text stays up for.
+const SCROLL_BURSTS: usize = 3;
+/// The acceleration that fills the bar graph, in g.
+const BAR_FULL: f32 = 8.0;
+
+/// The RTC counts at 32768Hz divided by one more than this, about every ms. Its 24 bits wrap after
+/// 4.7 hours.
+const PRESCALER: u32 = 32;
+
+type Driver = Lsm303agr<I2cInterface<board::SharedI2c<'static>>, mode::MagOneShot>;
+
+/// The driver for the settings, and the bus for the FIFO which the driver leaves out.
+struct Sensor {
+    driver: Driver,
+    bus: board::SharedI2c<'static>,
+}
+
+/// Streams samples into the FIFO and raises INT1 when a punch starts, also after the sensor or
+/// the bus started over.
+fn set_up(sensor: &mut Sensor) -> Result<(), error::Error> {
+    let driver = &mut sensor.driver;
+    driver.init()?;
+    driver.set_accel_mode(MODE)?;
+    driver.set_accel_odr(ODR)?;
+    driver.set_accel_scale(SCALE)?;
+    fifo::stream(&mut sensor.bus)?;
+    let trigger = Config::default().trigger * 1000.0;
+    wakeup::arm(&mut sensor.bus, trigger as u32, SCALE)
+}
+
+fn drain(sensor: &mut Sensor) -> Result<Burst, error::Error> {
+    fifo::drain(&mut sensor.bus)
+}
+
+/// Microseconds since it started, from the RTC which keeps counting while the CPU sleeps.
+struct Clock {
+    rtc: Rtc<RTC0>,
+    last: u32,
+    ticks: u64,
+}
+
+impl Clock {
+    fn new(rtc: RTC0) -> Self {
+        let Ok(rtc) = Rtc::new(rtc, PRESCALER) else {
+            unreachable!("the prescaler fits in 12 bits")
+        };
+        rtc.enable_counter();
+        Clock {
+            last: rtc.get_counter(),
+            rtc,
+            ticks: 0,
+        }
+    }
+
+    fn us(&mut self) -> u64 {
+        let now = self.rtc.get_counter();
+        self.ticks += (now.wrapping_sub(self.last) & 0xff_ffff) as u64;
+        self.last = now;
+        self.ticks * (PRESCALER as u64 + 1) * 1_000_000 / 32_768
+    }
+}
 
 #[entry]
 fn main() -> ! {
-    const THRESHOLD: f32 = 0.5;
-
     rtt_init_print!();
     let board = microbit::Board::take().unwrap();
 
-    #[cfg(feature = "v1")]
-    let i2c = {twi::Twi::new(board.TWI0, board.i2c.into(), FREQUENCY_A::K100)};
+    let i2c = board::board_i2c!(board);
+    let mut serial = board::board_serial!(board);
 
-    #[cfg(feature = "v2")]
-    let i2c = {twim::Twim::new(board.TWIM0, board.i2c_internal.into(), FREQUENCY_A::K100)};
-
-    let mut countdown = Timer::new(board.TIMER0);
+    // The RTC runs from the low frequency clock
+    Clocks::new(board.CLOCK).start_lfclk();
+    let mut clock = Clock::new(board.RTC0);
+    let mut wakeup = board::board_wakeup!(board);
+    let mut scb = board.SCB;
     let mut delay = Timer::new(board.TIMER1);
-    let mut sensor = Lsm303agr::new_with_i2c(i2c);
-    sensor.init().unwrap();
-    sensor.set_accel_odr(AccelOutputDataRate::Hz50).unwrap();
-    // Allow the sensor to measure up to 16 G since human punches can actually be quite fast
-    sensor.set_accel_scale(AccelScale::G16).unwrap();
 
-    let mut max_g = 0.;
-    let mut measuring = false;
+    // The driver sets the sensor up, the FIFO is read next to it
+    let bus = cortex_m::singleton!(: RefCell<board::I2c> = RefCell::new(i2c)).unwrap();
+    let mut bus = board::SharedI2c::new(bus);
+
+    // A tick or a cross on the display for a second
+    let selftest = board::selftest::run(&mut bus);
+    write!(serial, "{}\r\n", selftest).ok();
+    rprintln!("{}", selftest);
+    let mut display = Display::new(board.display_pins);
+    display.show(&mut delay, selftest.image(), 1000);
+
+    let mut sensor = Sensor {
+        driver: Lsm303agr::new_with_i2c(bus),
+        bus,
+    };
+    let mut errors = Counters::default();
+    let mut ready = false;
+
+    // Until the board lay still for a second there is no gravity to take off
+    let mut rest = Rest::new(ODR_HZ);
+    let mut gravity = None;
+    rprintln!("Hold still");
+
+    let mut detector = Detector::new(Config::default());
+    let mut session = Session::default();
+    let mut slept = false;
+
+    // The last punch going by, then the acceleration as a bar graph
+    let mut text: String<16> = String::new();
+    let mut bursts = 0;
+    let mut live = 0.0;
 
     loop {
-        while !sensor.accel_status().unwrap().xyz_new_data {}
-        // x acceleration in g
-        let g_x = sensor.accel_data().unwrap().x as f32 / 1000.0;
-
-        if measuring {
-            // Check the status of our countdown
-            match countdown.wait() {
-                // countdown isn't done yet
-                Err(Error::WouldBlock) => {
-                    if g_x > max_g {
-                        max_g = g_x;
-                    }
-                },
-                // Countdown is done
-                Ok(_) => {
-                    // Report max value
-                    rprintln!("Max acceleration: {}g", max_g);
-
-                    // Reset
-                    max_g = 0.;
-                    measuring = false;
-                },
-                // Since the nrf52 and nrf51 HAL have Void as an error type this path cannot occur,
-                // as Void is an empty type
-                Err(Error::Other(_)) => {
-                    unreachable!()
+        // Without its settings the sensor would never have a sample, so those come first
+        if !ready {
+            let result = errors.recover(&mut sensor, set_up, |_| Ok(()));
+            ready = report(result, &errors).is_some();
+        }
+        // Showing the display is what leaves the FIFO time to fill
+        let image = scroll::frame(&text, bursts / SCROLL_BURSTS, 1)
+            .unwrap_or_else(|| bar(live, BAR_FULL, 1));
+        display.show(&mut delay, image, BURST_MS);
+        bursts += 1;
+        if !ready {
+            continue;
+        }
+        let result = errors.recover(&mut sensor, drain, set_up);
+        let Some(burst) = report(result, &errors) else {
+            continue;
+        };
+        // While the CPU slept the FIFO was meant to run over
+        if burst.overrun && !slept {
+            rprintln!("The FIFO filled up, samples may have been lost");
+        }
+        slept = false;
+
+        // The last sample is the one just measured, the others are a sample period apart
+        let now = clock.us();
+        let samples = burst.samples();
+        live = 0.0;
+        for (i, &raw) in samples.iter().enumerate() {
+            let age = (samples.len() - 1 - i) as u64 * 1_000_000 / ODR_HZ as u64;
+            let time = now.saturating_sub(age) / 1000;
+            let [x, y, z] = fifo::mg(raw, MODE, SCALE);
+            let sample = Vector::from_mg(x, y, z);
+            let Some(gravity) = gravity else {
+                gravity = rest.add(sample);
+                if let Some(gravity) = gravity {
+                    rprintln!("Gravity: {} g, ready", gravity);
                 }
-            }
-        } else {
-            // If acceleration goes above a threshold, we start measuring
-            if g_x > THRESHOLD {
-                rprintln!("START");
-
-                measuring = true;
-                max_g = g_x;
-                // The documentation notes that the timer works at a frequency of 1Mhz, so in order
-                // to wait for 1 second we have to set it to 1_000_000 ticks.
-                countdown.start(1_000_000_u32);
+                continue;
+            };
+            let acceleration = sample.minus(gravity);
+            live = acceleration.magnitude().max(live);
+
+            if let Some(punch) = detector.update(time as u32, acceleration) {
+                session.add(&punch);
+                rprintln!("Punch {}: {}", session.count, punch);
+                text.clear();
+                write!(text, "{:.1}g", punch.peak.magnitude).ok();
+                bursts = 0;
             }
         }
-        delay.delay_ms(20_u8);
+        if session.is_over((now / 1000) as u32) {
+            rprintln!("Session: {}", session);
+            session = Session::default();
+        }
+
+        // Between sessions nothing happens until the next punch, which raises INT1 as it starts
+        let scrolling = bursts / SCROLL_BURSTS < scroll::frames(&text);
+        if gravity.is_some() && session.count == 0 && !detector.is_punching() && !scrolling {
+            let result = errors.recover(
+                &mut sensor,
+                |sensor| wakeup.sleep(&mut sensor.bus, &mut scb),
+                set_up,
+            );
+            slept = report(result, &errors).is_some();
+        }
     }
 }
+
+/// Reports a failure over RTT, with all of them so far.
+fn report<T>(result: Result<T, error::Error>, errors: &Counters) -> Option<T> {
+    result
+        .map_err(|error| rprintln!("Error: {} ({})", error, errors))
+        .ok()
+}
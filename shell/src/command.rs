@@ -0,0 +1,636 @@
+//! Commands described by tables.
+//!
+//! An application lists its commands in a `const` table of [`Command`]s, each with a name, a
+//! line of help, the arguments it takes and the function that runs it. [`execute`] looks up the
+//! command a line names, checks and converts the arguments and reports mistakes, so the commands
+//! themselves only ever see well formed input:
+//!
+//! ```
+//! use core::fmt::Write;
+//! use shell::command::{execute, Arg, Args, Command, Error};
+//! use shell::Console;
+//! # struct Out(String);
+//! # impl Write for Out {
+//! #     fn write_str(&mut self, s: &str) -> core::fmt::Result { self.0.write_str(s) }
+//! # }
+//! # impl Console for Out {
+//! #     fn write_bytes(&mut self, _: &[u8]) {}
+//! #     fn read_byte(&mut self) -> Option<u8> { None }
+//! # }
+//!
+//! fn add(total: &mut i32, args: &Args, out: &mut dyn Console) -> Result<(), Error<'static>> {
+//!     *total += args.int(0);
+//!     write!(out, "{}\r\n", total).ok();
+//!     Ok(())
+//! }
+//!
+//! const COMMANDS: &[Command<i32>] = &[Command {
+//!     name: "add",
+//!     help: "Adds to the total",
+//!     args: &[Arg::int("amount", -100, 100)],
+//!     run: add,
+//! }];
+//!
+//! let mut total = 0;
+//! let mut out = Out(String::new());
+//! execute(COMMANDS, &mut total, "add 12", &mut out);
+//! execute(COMMANDS, &mut total, "add 1000", &mut out);
+//! assert_eq!(out.0, "12\r\nerror: amount must be between -100 and 100\r\nusage: add <amount>\r\n");
+//! ```
+//!
+//! Names may consist of several words, like `accel odr`, which gives commands that belong together
+//! a common prefix. The line `help` lists all commands, `help <name>` shows a single one.
+use crate::Console;
+use core::fmt::{self, Display, Write};
+use heapless::Vec;
+
+/// The most arguments a command can take.
+pub const MAX_ARGS: usize = 8;
+
+/// A command in the table handed to [`execute`]. `C` is whatever the commands work on, usually
+/// the peripherals they talk to.
+pub struct Command<C> {
+    /// The words that select the command.
+    pub name: &'static str,
+    /// One line for the `help` listing.
+    pub help: &'static str,
+    /// The arguments following the name, at most [`MAX_ARGS`]. Optional ones have to come last.
+    pub args: &'static [Arg],
+    /// Runs the command once the arguments were checked against `args`.
+    pub run: fn(&mut C, &Args, &mut dyn Console) -> Result<(), Error<'static>>,
+}
+
+// Lets the line editor complete the names straight from the table
+impl<C> AsRef<str> for Command<C> {
+    fn as_ref(&self) -> &str {
+        self.name
+    }
+}
+
+/// The description of an argument.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Arg {
+    /// Shown in the usage and in error messages.
+    pub name: &'static str,
+    pub kind: Kind,
+    pub optional: bool,
+}
+
+/// What an argument may be.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// A whole number within `min..=max`, in decimal or with a `0x` or `0b` prefix.
+    Int { min: i32, max: i32 },
+    /// One of the given words, for settings like data rates that only take a few values.
+    Choice(&'static [&'static str]),
+}
+
+impl Arg {
+    /// A required number within `min..=max`.
+    pub const fn int(name: &'static str, min: i32, max: i32) -> Self {
+        Arg {
+            name,
+            kind: Kind::Int { min, max },
+            optional: false,
+        }
+    }
+
+    /// A required choice between `choices`.
+    pub const fn choice(name: &'static str, choices: &'static [&'static str]) -> Self {
+        Arg {
+            name,
+            kind: Kind::Choice(choices),
+            optional: false,
+        }
+    }
+
+    /// Makes the argument optional.
+    pub const fn optional(self) -> Self {
+        Arg {
+            optional: true,
+            ..self
+        }
+    }
+
+    fn parse<'a>(&self, word: &'a str) -> Result<Value, Error<'a>> {
+        match self.kind {
+            Kind::Int { min, max } => {
+                let value = parse_int(word).ok_or(Error::InvalidNumber {
+                    arg: self.name,
+                    value: word,
+                })?;
+                if (min..=max).contains(&value) {
+                    Ok(Value::Int(value))
+                } else {
+                    Err(Error::OutOfRange {
+                        arg: self.name,
+                        min,
+                        max,
+                    })
+                }
+            }
+            Kind::Choice(choices) => choices
+                .iter()
+                .position(|choice| choice.eq_ignore_ascii_case(word))
+                .map(Value::Choice)
+                .ok_or(Error::InvalidChoice {
+                    arg: self.name,
+                    choices,
+                }),
+        }
+    }
+}
+
+impl Display for Arg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (open, close) = if self.optional {
+            ('[', ']')
+        } else {
+            ('<', '>')
+        };
+        match self.kind {
+            Kind::Int { .. } => write!(f, "{}{}{}", open, self.name, close),
+            Kind::Choice(choices) => {
+                f.write_char(open)?;
+                for (i, choice) in choices.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char('|')?;
+                    }
+                    f.write_str(choice)?;
+                }
+                f.write_char(close)
+            }
+        }
+    }
+}
+
+/// The names of a table pairing each choice with the value it stands for, for use in
+/// [`Arg::choice`]:
+///
+/// ```
+/// # use shell::command::{names, Arg};
+/// const RATES: [(&str, u32); 3] = [("10", 10), ("50", 50), ("100", 100)];
+/// const RATE: Arg = Arg::choice("rate", &names(&RATES));
+/// ```
+///
+/// The command then finds the value with `RATES[args.choice(0)].1`.
+pub const fn names<T, const N: usize>(table: &[(&'static str, T); N]) -> [&'static str; N] {
+    let mut names = [""; N];
+    let mut i = 0;
+    while i < N {
+        names[i] = table[i].0;
+        i += 1;
+    }
+    names
+}
+
+/// The name `value` has in `table`, the other way around from [`names`].
+pub fn name_of<T: PartialEq>(table: &[(&'static str, T)], value: &T) -> &'static str {
+    table
+        .iter()
+        .find(|(_, candidate)| candidate == value)
+        .map_or("?", |(name, _)| name)
+}
+
+/// A checked argument.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Value {
+    Int(i32),
+    /// The index into the choices of the argument.
+    Choice(usize),
+}
+
+/// The arguments a command was given, in the order of its [`Command::args`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Args {
+    values: Vec<Value, MAX_ARGS>,
+}
+
+impl Args {
+    /// The argument at `index`, `None` for an optional argument that was left out.
+    pub fn get(&self, index: usize) -> Option<Value> {
+        self.values.get(index).copied()
+    }
+
+    /// The number at `index`.
+    ///
+    /// # Panics
+    ///
+    /// If the argument is not a [`Kind::Int`] or an optional one that was left out, both
+    /// mistakes in the command table.
+    pub fn int(&self, index: usize) -> i32 {
+        match self.get(index) {
+            Some(Value::Int(value)) => value,
+            other => panic!("argument {} is not a number: {:?}", index, other),
+        }
+    }
+
+    /// The index of the choice at `index`, to look up in a table of the values the choices stand
+    /// for.
+    ///
+    /// # Panics
+    ///
+    /// If the argument is not a [`Kind::Choice`] or an optional one that was left out.
+    pub fn choice(&self, index: usize) -> usize {
+        match self.get(index) {
+            Some(Value::Choice(choice)) => choice,
+            other => panic!("argument {} is not a choice: {:?}", index, other),
+        }
+    }
+
+    /// The number at `index` if it was given, `default` otherwise.
+    pub fn int_or(&self, index: usize, default: i32) -> i32 {
+        match self.get(index) {
+            Some(Value::Int(value)) => value,
+            _ => default,
+        }
+    }
+}
+
+/// Why a line could not be run. `'a` is the lifetime of the line, for the words it quotes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error<'a> {
+    UnknownCommand(&'a str),
+    MissingArgument(&'static str),
+    UnexpectedArgument(&'a str),
+    InvalidNumber {
+        arg: &'static str,
+        value: &'a str,
+    },
+    OutOfRange {
+        arg: &'static str,
+        min: i32,
+        max: i32,
+    },
+    InvalidChoice {
+        arg: &'static str,
+        choices: &'static [&'static str],
+    },
+    /// The command itself failed, for example because a sensor did not respond.
+    Failed(&'static str),
+}
+
+impl Display for Error<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::UnknownCommand(name) => {
+                write!(f, "unknown command \"{}\", try \"help\"", name)
+            }
+            Error::MissingArgument(arg) => write!(f, "missing {}", arg),
+            Error::UnexpectedArgument(word) => write!(f, "unexpected \"{}\"", word),
+            Error::InvalidNumber { arg, value } => {
+                write!(f, "{} must be a number, not \"{}\"", arg, value)
+            }
+            Error::OutOfRange { arg, min, max } => {
+                write!(f, "{} must be between {} and {}", arg, min, max)
+            }
+            Error::InvalidChoice { arg, choices } => {
+                write!(f, "{} must be one of ", arg)?;
+                for (i, choice) in choices.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    f.write_str(choice)?;
+                }
+                Ok(())
+            }
+            Error::Failed(reason) => f.write_str(reason),
+        }
+    }
+}
+
+/// Runs the command `line` names, or the built in `help`, writing the output and any error
+/// message to `out`. Empty lines are ignored.
+pub fn execute<C>(commands: &[Command<C>], context: &mut C, line: &str, out: &mut impl Console) {
+    let words = line.split_whitespace();
+    match words.clone().next() {
+        None => {}
+        Some("help") => help(commands, words.skip(1), out),
+        Some(first) => match find(commands, words) {
+            Some((command, args)) => {
+                let result = parse_args(command, args)
+                    .map_err(|error| (error, true))
+                    .and_then(|args| {
+                        (command.run)(context, &args, out).map_err(|error| (error, false))
+                    });
+                if let Err((error, show_usage)) = result {
+                    write!(out, "error: {}\r\n", error).ok();
+                    if show_usage {
+                        out.write_str("usage: ").ok();
+                        usage(command, out);
+                        out.write_str("\r\n").ok();
+                    }
+                }
+            }
+            None => {
+                write!(out, "error: {}\r\n", Error::UnknownCommand(first)).ok();
+            }
+        },
+    }
+}
+
+/// The command with the longest name matching the leading words, and the words after its name.
+fn find<'a, 'c, C>(
+    commands: &'c [Command<C>],
+    words: impl Iterator<Item = &'a str> + Clone,
+) -> Option<(&'c Command<C>, impl Iterator<Item = &'a str> + Clone)> {
+    commands
+        .iter()
+        .filter_map(|command| {
+            let mut rest = words.clone();
+            let matches = command
+                .name
+                .split_whitespace()
+                .all(|word| rest.next() == Some(word));
+            matches.then_some((command, rest))
+        })
+        .max_by_key(|(command, _)| command.name.len())
+}
+
+fn parse_args<'a, C>(
+    command: &Command<C>,
+    mut words: impl Iterator<Item = &'a str>,
+) -> Result<Args, Error<'a>> {
+    let mut args = Args::default();
+    for arg in command.args {
+        match words.next() {
+            Some(word) => {
+                // A table entry with more than MAX_ARGS arguments can never be satisfied
+                args.values
+                    .push(arg.parse(word)?)
+                    .map_err(|_| Error::UnexpectedArgument(word))?;
+            }
+            None if arg.optional => break,
+            None => return Err(Error::MissingArgument(arg.name)),
+        }
+    }
+    match words.next() {
+        Some(word) => Err(Error::UnexpectedArgument(word)),
+        None => Ok(args),
+    }
+}
+
+/// Lists all commands, or the ones starting with the words given.
+fn help<'a, C>(
+    commands: &[Command<C>],
+    words: impl Iterator<Item = &'a str> + Clone,
+    out: &mut impl Write,
+) {
+    let selected = |command: &&Command<C>| {
+        let mut name = command.name.split_whitespace();
+        words.clone().all(|word| name.next() == Some(word))
+    };
+    if !commands.iter().any(|command| selected(&command)) {
+        let name = words.clone().next().unwrap_or_default();
+        write!(out, "error: {}\r\n", Error::UnknownCommand(name)).ok();
+        return;
+    }
+
+    let width = commands
+        .iter()
+        .filter(selected)
+        .map(|command| usage_length(command))
+        .max()
+        .unwrap_or(0);
+    for command in commands.iter().filter(selected) {
+        usage(command, out);
+        for _ in usage_length(command)..width + 2 {
+            out.write_char(' ').ok();
+        }
+        write!(out, "{}\r\n", command.help).ok();
+    }
+}
+
+/// Writes the name of the command followed by its arguments.
+fn usage<C>(command: &Command<C>, out: &mut impl Write) {
+    out.write_str(command.name).ok();
+    for arg in command.args {
+        write!(out, " {}", arg).ok();
+    }
+}
+
+fn usage_length<C>(command: &Command<C>) -> usize {
+    struct Counter(usize);
+
+    impl Write for Counter {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            self.0 += s.len();
+            Ok(())
+        }
+    }
+
+    let mut counter = Counter(0);
+    usage(command, &mut counter);
+    counter.0
+}
+
+fn parse_int(word: &str) -> Option<i32> {
+    let (negative, digits) = match word.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, word),
+    };
+    let (radix, digits) = if let Some(hex) = digits.strip_prefix("0x") {
+        (16, hex)
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        (2, binary)
+    } else {
+        (10, digits)
+    };
+    // from_str_radix would accept a second sign
+    if digits.starts_with(['+', '-']) {
+        return None;
+    }
+    let value = i32::from_str_radix(digits, radix).ok()?;
+    Some(if negative { -value } else { value })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::console::Terminal;
+
+    #[derive(Default)]
+    struct Log(std::vec::Vec<String>);
+
+    fn record(log: &mut Log, args: &Args, _: &mut dyn Console) -> Result<(), Error<'static>> {
+        log.0.push(format!("{:?}", args.values));
+        Ok(())
+    }
+
+    fn fail(_: &mut Log, _: &Args, _: &mut dyn Console) -> Result<(), Error<'static>> {
+        Err(Error::Failed("sensor did not respond"))
+    }
+
+    const RATES: &[&str] = &["10", "50", "100"];
+
+    const COMMANDS: &[Command<Log>] = &[
+        Command {
+            name: "accel",
+            help: "Reads the accelerometer",
+            args: &[],
+            run: record,
+        },
+        Command {
+            name: "accel odr",
+            help: "Sets the data rate",
+            args: &[Arg::choice("rate", RATES)],
+            run: record,
+        },
+        Command {
+            name: "mag read",
+            help: "Reads the magnetometer",
+            args: &[Arg::int("count", 1, 100).optional()],
+            run: record,
+        },
+        Command {
+            name: "poke",
+            help: "Writes a register",
+            args: &[Arg::int("register", 0, 0xff), Arg::int("value", -128, 255)],
+            run: record,
+        },
+        Command {
+            name: "fail",
+            help: "Always fails",
+            args: &[],
+            run: fail,
+        },
+    ];
+
+    fn run(line: &str) -> (std::vec::Vec<String>, String) {
+        let mut log = Log::default();
+        let mut out = Terminal::default();
+        execute(COMMANDS, &mut log, line, &mut out);
+        (log.0, out.output)
+    }
+
+    #[test]
+    fn longest_name_wins() {
+        assert_eq!(run("accel"), (vec!["[]".to_string()], String::new()));
+        assert_eq!(run("  accel   odr  50 ").0, ["[Choice(1)]"]);
+        assert_eq!(run("mag read 10").0, ["[Int(10)]"]);
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(run("poke 0x20 0b101").0, ["[Int(32), Int(5)]"]);
+        assert_eq!(run("poke 255 -128").0, ["[Int(255), Int(-128)]"]);
+        assert_eq!(
+            run("poke 0x20 --1").1,
+            "error: value must be a number, not \"--1\"\r\nusage: poke <register> <value>\r\n"
+        );
+        assert_eq!(parse_int("-+5"), None);
+        assert_eq!(parse_int("-0x10"), Some(-16));
+        assert_eq!(parse_int("0x"), None);
+        assert_eq!(parse_int("12a"), None);
+    }
+
+    #[test]
+    fn optional_arguments() {
+        assert_eq!(run("mag read").0, ["[]"]);
+        let args = Args::default();
+        assert_eq!(args.int_or(0, 7), 7);
+        assert_eq!(args.get(0), None);
+    }
+
+    #[test]
+    fn choices_ignore_case() {
+        let (log, out) = run("accel odr 100");
+        assert_eq!((log, out), (vec!["[Choice(2)]".to_string()], String::new()));
+        let arg = Arg::choice("mode", &["low", "high"]);
+        assert_eq!(arg.parse("HIGH"), Ok(Value::Choice(1)));
+    }
+
+    #[test]
+    fn choice_tables() {
+        const MODES: [(&str, Option<bool>); 3] =
+            [("off", None), ("low", Some(false)), ("high", Some(true))];
+        const MODE: Arg = Arg::choice("mode", &names(&MODES));
+        assert_eq!(MODE.kind, Kind::Choice(&["off", "low", "high"]));
+        assert_eq!(name_of(&MODES, &Some(true)), "high");
+        assert_eq!(name_of(&MODES[1..], &None), "?");
+    }
+
+    #[test]
+    fn argument_errors_show_the_usage() {
+        assert_eq!(
+            run("accel odr 60"),
+            (
+                vec![],
+                "error: rate must be one of 10, 50, 100\r\nusage: accel odr <10|50|100>\r\n"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            run("poke 1").1,
+            "error: missing value\r\nusage: poke <register> <value>\r\n"
+        );
+        assert_eq!(
+            run("poke 1 2 3").1,
+            "error: unexpected \"3\"\r\nusage: poke <register> <value>\r\n"
+        );
+        assert_eq!(
+            run("mag read 0").1,
+            "error: count must be between 1 and 100\r\nusage: mag read [count]\r\n"
+        );
+        assert_eq!(
+            run("poke x 1").1,
+            "error: register must be a number, not \"x\"\r\nusage: poke <register> <value>\r\n"
+        );
+    }
+
+    #[test]
+    fn unknown_commands() {
+        assert_eq!(
+            run("magnet"),
+            (
+                vec![],
+                "error: unknown command \"magnet\", try \"help\"\r\n".to_string()
+            )
+        );
+        // Only a prefix of "mag read"
+        assert_eq!(
+            run("mag").1,
+            "error: unknown command \"mag\", try \"help\"\r\n"
+        );
+        assert_eq!(run("   "), (vec![], String::new()));
+    }
+
+    #[test]
+    fn failures_are_reported_without_usage() {
+        assert_eq!(run("fail").1, "error: sensor did not respond\r\n");
+    }
+
+    #[test]
+    fn help_lists_everything() {
+        let expected: String = [
+            ("accel", "Reads the accelerometer"),
+            ("accel odr <10|50|100>", "Sets the data rate"),
+            ("mag read [count]", "Reads the magnetometer"),
+            ("poke <register> <value>", "Writes a register"),
+            ("fail", "Always fails"),
+        ]
+        .iter()
+        .map(|(usage, help)| format!("{:25}{}\r\n", usage, help))
+        .collect();
+        assert_eq!(run("help").1, expected);
+    }
+
+    #[test]
+    fn help_for_some_commands() {
+        assert_eq!(
+            run("help accel").1,
+            format!(
+                "{:23}Reads the accelerometer\r\n{:23}Sets the data rate\r\n",
+                "accel", "accel odr <10|50|100>"
+            )
+        );
+        assert_eq!(
+            run("help mag read").1,
+            "mag read [count]  Reads the magnetometer\r\n"
+        );
+        assert_eq!(
+            run("help nothing").1,
+            "error: unknown command \"nothing\", try \"help\"\r\n"
+        );
+    }
+}
@@ -0,0 +1,42 @@
+//! What commands talk to.
+use core::fmt::Write;
+
+/// The other end of the serial line as seen by a command. Most commands only print text, but
+/// streaming ones also send binary data and keep going until they receive a byte telling them to
+/// stop.
+pub trait Console: Write {
+    /// Sends `bytes` as they are, for data that is not text.
+    fn write_bytes(&mut self, bytes: &[u8]);
+
+    /// A byte received since the command started, if there is one. Never waits.
+    fn read_byte(&mut self) -> Option<u8>;
+}
+
+/// Records the output and plays back scripted input, for the tests.
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub(crate) struct Terminal {
+    pub output: std::string::String,
+    pub bytes: std::vec::Vec<u8>,
+    pub input: std::collections::VecDeque<u8>,
+}
+
+#[cfg(test)]
+impl Write for Terminal {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.output.push_str(s);
+        self.bytes.extend_from_slice(s.as_bytes());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl Console for Terminal {
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    fn read_byte(&mut self) -> Option<u8> {
+        self.input.pop_front()
+    }
+}
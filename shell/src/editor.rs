@@ -0,0 +1,613 @@
+//! Line editing on a serial terminal.
+//!
+//! [`LineEditor`] takes the bytes a terminal sends one at a time and keeps the line the user is
+//! typing, echoing what they should see. It understands the keys terminal emulators like
+//! `minicom`, `screen` or PuTTY send with their default settings:
+//!
+//! | Key                       | Bytes                                 | Action                       |
+//! |---------------------------|---------------------------------------|------------------------------|
+//! | Backspace                 | `0x08` or `0x7f`                      | Delete before the cursor     |
+//! | Delete                    | `ESC [ 3 ~`                           | Delete under the cursor      |
+//! | Left, Right               | `ESC [ D`, `ESC [ C`                  | Move the cursor              |
+//! | Home, End                 | `ESC [ H`, `ESC [ F`, Ctrl-A, Ctrl-E  | Jump to the start or end     |
+//! | Up, Down                  | `ESC [ A`, `ESC [ B`                  | Browse the history           |
+//! | Tab                       | `0x09`                                | Complete the command name    |
+//! | Ctrl-C                    | `0x03`                                | Discard the line             |
+//! | Enter                     | `0x0d`, `0x0a` or both                | Finish the line              |
+//!
+//! Cursor movement is echoed with ANSI escape sequences, so the terminal has to understand those,
+//! which every emulator in use today does. Only printable ASCII ends up in the line.
+use core::fmt::Write;
+use core::str;
+use heapless::{Deque, Vec};
+
+const CTRL_A: u8 = 0x01;
+const CTRL_C: u8 = 0x03;
+const CTRL_E: u8 = 0x05;
+const BELL: u8 = 0x07;
+const BACKSPACE: u8 = 0x08;
+const TAB: u8 = 0x09;
+const LF: u8 = 0x0a;
+const CR: u8 = 0x0d;
+const ESC: u8 = 0x1b;
+const DEL: u8 = 0x7f;
+
+/// Where in an escape sequence the last byte left the parser.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Escape {
+    None,
+    /// After `ESC`.
+    Start,
+    /// After `ESC [` and the numeric parameter read so far.
+    Csi(u8),
+    /// After the `;` that separates the parameter from the modifier keys held down, which are
+    /// ignored.
+    Modifiers(u8),
+    /// After `ESC O`, which some terminals send for the cursor keys instead of `ESC [`.
+    Ss3,
+}
+
+/// The keys that arrive as escape sequences.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Key {
+    Up,
+    Down,
+    Right,
+    Left,
+    Home,
+    End,
+    Delete,
+}
+
+impl Key {
+    /// Decodes the final byte of a sequence and its numeric parameter.
+    fn decode(byte: u8, parameter: u8) -> Option<Key> {
+        match (byte, parameter) {
+            (b'A', _) => Some(Key::Up),
+            (b'B', _) => Some(Key::Down),
+            (b'C', _) => Some(Key::Right),
+            (b'D', _) => Some(Key::Left),
+            (b'H', _) | (b'~', 1) | (b'~', 7) => Some(Key::Home),
+            (b'F', _) | (b'~', 4) | (b'~', 8) => Some(Key::End),
+            (b'~', 3) => Some(Key::Delete),
+            _ => None,
+        }
+    }
+}
+
+/// Edits a line of up to `N` characters and remembers the last `H` lines entered.
+///
+/// Feed it every received byte with [`feed`](Self::feed) and it returns the line once the user
+/// presses enter. The editor does not print the prompt by itself after a line, so that the
+/// response to a command comes before the next prompt:
+///
+/// ```
+/// # let mut out = String::new();
+/// let mut editor: shell::LineEditor<32, 4> = shell::LineEditor::new("> ", &["help"]);
+/// editor.prompt(&mut out);
+/// for byte in b"he\thi\r" {
+///     if let Some(line) = editor.feed(*byte, &mut out) {
+///         assert_eq!(line, "help hi");
+///         editor.prompt(&mut out);
+///     }
+/// }
+/// ```
+///
+/// Errors from `out` are ignored, there is nobody to tell about them anyway.
+pub struct LineEditor<'a, const N: usize = 64, const H: usize = 8, T: 'a = &'a str> {
+    prompt: &'a str,
+    commands: &'a [T],
+    line: Vec<u8, N>,
+    cursor: usize,
+    /// Newest entry first.
+    history: Deque<Vec<u8, N>, H>,
+    /// The history entry on display, `None` while editing a new line.
+    browsing: Option<usize>,
+    /// The line that was being typed before browsing the history.
+    draft: Vec<u8, N>,
+    escape: Escape,
+    /// The line was returned, the next byte starts a new one.
+    finished: bool,
+    /// The last byte was CR, so that the LF of a CR LF does not finish another (empty) line.
+    after_cr: bool,
+}
+
+impl<'a, const N: usize, const H: usize, T: AsRef<str>> LineEditor<'a, N, H, T> {
+    /// Creates an editor showing `prompt` and completing the names in `commands`, which can also
+    /// be a table of [`Command`](crate::Command)s.
+    pub fn new(prompt: &'a str, commands: &'a [T]) -> Self {
+        LineEditor {
+            prompt,
+            commands,
+            line: Vec::new(),
+            cursor: 0,
+            history: Deque::new(),
+            browsing: None,
+            draft: Vec::new(),
+            escape: Escape::None,
+            finished: false,
+            after_cr: false,
+        }
+    }
+
+    /// Prints the prompt, at startup and after handling each line.
+    pub fn prompt(&self, out: &mut impl Write) {
+        out.write_str(self.prompt).ok();
+    }
+
+    /// The line as typed so far.
+    pub fn line(&self) -> &str {
+        // Only printable ASCII is ever inserted
+        str::from_utf8(&self.line).unwrap_or("")
+    }
+
+    /// The lines entered so far, newest first.
+    pub fn history(&self) -> impl Iterator<Item = &str> {
+        self.history
+            .iter()
+            .map(|line| str::from_utf8(line).unwrap_or(""))
+    }
+
+    /// Handles one received byte, writing the echo to `out`. Returns the line when the byte
+    /// finished it.
+    pub fn feed(&mut self, byte: u8, out: &mut impl Write) -> Option<&str> {
+        if self.finished {
+            self.finished = false;
+            self.line.clear();
+            self.cursor = 0;
+        }
+        let after_cr = core::mem::replace(&mut self.after_cr, byte == CR);
+
+        match self.escape {
+            Escape::None => {}
+            Escape::Start => {
+                self.escape = match byte {
+                    b'[' => Escape::Csi(0),
+                    b'O' => Escape::Ss3,
+                    _ => Escape::None,
+                };
+                return None;
+            }
+            Escape::Csi(parameter) => {
+                match byte {
+                    b'0'..=b'9' => {
+                        let digit = byte - b'0';
+                        self.escape =
+                            Escape::Csi(parameter.saturating_mul(10).saturating_add(digit));
+                    }
+                    b';' => self.escape = Escape::Modifiers(parameter),
+                    _ => self.end_sequence(byte, parameter, out),
+                }
+                return None;
+            }
+            Escape::Modifiers(parameter) => {
+                if !byte.is_ascii_digit() {
+                    self.end_sequence(byte, parameter, out);
+                }
+                return None;
+            }
+            Escape::Ss3 => {
+                self.escape = Escape::None;
+                if let Some(key) = Key::decode(byte, 0) {
+                    self.key(key, out);
+                }
+                return None;
+            }
+        }
+
+        match byte {
+            CR => return self.finish(out),
+            LF if !after_cr => return self.finish(out),
+            LF => {}
+            BACKSPACE | DEL if self.cursor > 0 => {
+                self.cursor_left(1, out);
+                self.line.remove(self.cursor);
+                self.redraw_tail(out);
+            }
+            TAB => self.complete(out),
+            ESC => self.escape = Escape::Start,
+            CTRL_A => self.key(Key::Home, out),
+            CTRL_E => self.key(Key::End, out),
+            CTRL_C => {
+                out.write_str("^C\r\n").ok();
+                self.line.clear();
+                self.cursor = 0;
+                self.browsing = None;
+                self.prompt(out);
+            }
+            b' '..=b'~' => self.insert(&[byte], out),
+            _ => {}
+        }
+        None
+    }
+
+    /// Handles the final byte of a control sequence, anything but a final byte aborts it.
+    fn end_sequence(&mut self, byte: u8, parameter: u8, out: &mut impl Write) {
+        self.escape = Escape::None;
+        if (0x40..=0x7e).contains(&byte) {
+            if let Some(key) = Key::decode(byte, parameter) {
+                self.key(key, out);
+            }
+        }
+    }
+
+    fn key(&mut self, key: Key, out: &mut impl Write) {
+        match key {
+            Key::Left if self.cursor > 0 => self.cursor_left(1, out),
+            Key::Right if self.cursor < self.line.len() => self.cursor_right(1, out),
+            Key::Home => self.cursor_left(self.cursor, out),
+            Key::End => self.cursor_right(self.line.len() - self.cursor, out),
+            Key::Delete if self.cursor < self.line.len() => {
+                self.line.remove(self.cursor);
+                self.redraw_tail(out);
+            }
+            Key::Up => {
+                let next = self.browsing.map_or(0, |index| index + 1);
+                if next < self.history.len() {
+                    if self.browsing.is_none() {
+                        self.draft = self.line.clone();
+                    }
+                    self.browsing = Some(next);
+                    self.replace_line(next, out);
+                } else {
+                    bell(out);
+                }
+            }
+            Key::Down => match self.browsing {
+                Some(0) => {
+                    self.browsing = None;
+                    self.line = core::mem::take(&mut self.draft);
+                    self.redraw_line(out);
+                }
+                Some(index) => {
+                    self.browsing = Some(index - 1);
+                    self.replace_line(index - 1, out);
+                }
+                None => bell(out),
+            },
+            _ => bell(out),
+        }
+    }
+
+    fn finish(&mut self, out: &mut impl Write) -> Option<&str> {
+        out.write_str("\r\n").ok();
+        self.finished = true;
+        self.browsing = None;
+        if !self.line.is_empty() && self.history.front() != Some(&self.line) {
+            if self.history.is_full() {
+                self.history.pop_back();
+            }
+            self.history.push_front(self.line.clone()).ok();
+        }
+        Some(self.line())
+    }
+
+    /// Inserts `bytes` at the cursor, as much of them as fits.
+    fn insert(&mut self, bytes: &[u8], out: &mut impl Write) {
+        let start = self.cursor;
+        for byte in bytes {
+            if self.line.insert(self.cursor, *byte).is_err() {
+                bell(out);
+                break;
+            }
+            self.cursor += 1;
+        }
+        write_bytes(&self.line[start..self.cursor], out);
+        if self.cursor < self.line.len() {
+            self.redraw_tail(out);
+        }
+    }
+
+    /// Completes the command name, which can be several words but not the arguments after it.
+    fn complete(&mut self, out: &mut impl Write) {
+        let commands: &'a [T] = self.commands;
+        let typed = self.line.len();
+        if self.cursor != typed {
+            bell(out);
+            return;
+        }
+        let mut matches = commands
+            .iter()
+            .map(|command| command.as_ref().as_bytes())
+            .filter(|command| command.starts_with(&self.line));
+        let Some(first) = matches.next() else {
+            bell(out);
+            return;
+        };
+
+        let mut common = first.len();
+        let mut unique = true;
+        for other in matches {
+            unique = false;
+            common = first
+                .iter()
+                .zip(other)
+                .take(common)
+                .take_while(|(a, b)| a == b)
+                .count();
+        }
+
+        if unique {
+            self.insert(&first[typed..], out);
+            self.insert(b" ", out);
+        } else if common > typed {
+            self.insert(&first[typed..common], out);
+        } else {
+            // Nothing to add, show the candidates instead
+            out.write_str("\r\n").ok();
+            let candidates = commands
+                .iter()
+                .map(|command| command.as_ref())
+                .filter(|command| command.as_bytes().starts_with(&self.line));
+            for (i, command) in candidates.enumerate() {
+                if i > 0 {
+                    out.write_str("  ").ok();
+                }
+                out.write_str(command).ok();
+            }
+            out.write_str("\r\n").ok();
+            self.prompt(out);
+            write_bytes(&self.line, out);
+        }
+    }
+
+    /// Shows history entry `index` in place of the current line.
+    fn replace_line(&mut self, index: usize, out: &mut impl Write) {
+        if let Some(entry) = self.history.iter().nth(index) {
+            self.line = entry.clone();
+            self.redraw_line(out);
+        }
+    }
+
+    /// Redraws the whole line after it was replaced, leaving the cursor at its end.
+    fn redraw_line(&mut self, out: &mut impl Write) {
+        self.cursor_left(self.cursor, out);
+        write_bytes(&self.line, out);
+        out.write_str("\x1b[K").ok();
+        self.cursor = self.line.len();
+    }
+
+    /// Redraws the line from the cursor on, after characters were inserted or removed there.
+    fn redraw_tail(&self, out: &mut impl Write) {
+        let tail = &self.line[self.cursor..];
+        write_bytes(tail, out);
+        out.write_str("\x1b[K").ok();
+        if !tail.is_empty() {
+            write!(out, "\x1b[{}D", tail.len()).ok();
+        }
+    }
+
+    fn cursor_left(&mut self, count: usize, out: &mut impl Write) {
+        if count > 0 {
+            write!(out, "\x1b[{}D", count).ok();
+            self.cursor -= count;
+        }
+    }
+
+    fn cursor_right(&mut self, count: usize, out: &mut impl Write) {
+        if count > 0 {
+            write!(out, "\x1b[{}C", count).ok();
+            self.cursor += count;
+        }
+    }
+}
+
+fn bell(out: &mut impl Write) {
+    out.write_char(BELL as char).ok();
+}
+
+fn write_bytes(bytes: &[u8], out: &mut impl Write) {
+    out.write_str(str::from_utf8(bytes).unwrap_or("")).ok();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COMMANDS: &[&str] = &["accelerometer", "accel", "magnetometer", "help"];
+
+    type Editor = LineEditor<'static, 16, 3>;
+
+    fn editor() -> Editor {
+        LineEditor::new("> ", COMMANDS)
+    }
+
+    /// Feeds `input` and returns the lines it finished and everything echoed.
+    fn run(editor: &mut Editor, input: &[u8]) -> (std::vec::Vec<String>, String) {
+        let mut out = String::new();
+        let mut lines = std::vec::Vec::new();
+        for byte in input {
+            if let Some(line) = editor.feed(*byte, &mut out) {
+                lines.push(line.to_string());
+            }
+        }
+        (lines, out)
+    }
+
+    fn lines(input: &[u8]) -> std::vec::Vec<String> {
+        run(&mut editor(), input).0
+    }
+
+    #[test]
+    fn plain_typing_is_echoed() {
+        let mut editor = editor();
+        let (lines, out) = run(&mut editor, b"help\r");
+        assert_eq!(lines, ["help"]);
+        assert_eq!(out, "help\r\n");
+    }
+
+    #[test]
+    fn line_endings() {
+        assert_eq!(lines(b"a\rb\nc\r\nd\n\r"), ["a", "b", "c", "d", ""]);
+        assert_eq!(lines(b"\r\r"), ["", ""]);
+    }
+
+    #[test]
+    fn backspace() {
+        assert_eq!(lines(b"helo\x7f\x7flp\r"), ["help"]);
+        assert_eq!(lines(b"ab\x08c\r"), ["ac"]);
+        // Nothing to delete at the start of the line
+        assert_eq!(lines(b"\x7fa\x1b[D\x7f\r"), ["a"]);
+    }
+
+    #[test]
+    fn backspace_in_the_middle_redraws_the_tail() {
+        let mut editor = editor();
+        let (_, out) = run(&mut editor, b"abc\x1b[D\x7f");
+        assert_eq!(editor.line(), "ac");
+        assert!(out.ends_with("\x1b[1Dc\x1b[K\x1b[1D"), "{:?}", out);
+    }
+
+    #[test]
+    fn cursor_movement_and_insertion() {
+        assert_eq!(lines(b"hlp\x1b[D\x1b[De\r"), ["help"]);
+        assert_eq!(lines(b"elp\x1b[Hh\x1b[F!\r"), ["help!"]);
+        assert_eq!(lines(b"elp\x01h\x05!\r"), ["help!"]);
+        assert_eq!(lines(b"elp\x1b[1~h\x1b[4~!\r"), ["help!"]);
+        // SS3 cursor keys
+        assert_eq!(lines(b"ac\x1bODb\r"), ["abc"]);
+    }
+
+    #[test]
+    fn cursor_stops_at_the_ends() {
+        let mut editor = editor();
+        let (_, out) = run(&mut editor, b"ab\x1b[C");
+        assert!(out.ends_with('\x07'));
+        let (_, out) = run(&mut editor, b"\x1b[D\x1b[D\x1b[D");
+        assert!(out.ends_with('\x07'));
+        let (lines, _) = run(&mut editor, b"x\r");
+        assert_eq!(lines, ["xab"]);
+    }
+
+    #[test]
+    fn delete_key() {
+        assert_eq!(lines(b"abc\x1b[H\x1b[3~\r"), ["bc"]);
+        assert_eq!(lines(b"abc\x1b[3~\r"), ["abc"]);
+    }
+
+    #[test]
+    fn unknown_sequences_and_control_characters_are_ignored() {
+        assert_eq!(lines(b"a\x1b[1;5Ab\x1b[Zc\x1bxd\x02\x1b[15~e\r"), ["abcde"]);
+        // Ctrl-Left is still left
+        assert_eq!(lines(b"bc\x1b[1;5Da\r"), ["bac"]);
+    }
+
+    #[test]
+    fn full_line_rings_the_bell() {
+        let mut editor = editor();
+        let (_, out) = run(&mut editor, b"0123456789abcdefg");
+        assert_eq!(editor.line(), "0123456789abcdef");
+        assert!(out.contains('\x07'));
+    }
+
+    #[test]
+    fn ctrl_c_discards_the_line() {
+        let mut editor = editor();
+        let (lines, out) = run(&mut editor, b"junk\x03ok\r");
+        assert_eq!(lines, ["ok"]);
+        assert!(out.contains("^C\r\n> "));
+    }
+
+    #[test]
+    fn history_browsing() {
+        let mut editor = editor();
+        run(&mut editor, b"one\rtwo\rthree\r");
+        assert_eq!(
+            editor.history().collect::<std::vec::Vec<_>>(),
+            ["three", "two", "one"]
+        );
+
+        let (lines, _) = run(&mut editor, b"\x1b[A\x1b[A\r");
+        assert_eq!(lines, ["two"]);
+        // Most recent first again, and the recalled line can be edited
+        let (lines, _) = run(&mut editor, b"\x1b[A!\r");
+        assert_eq!(lines, ["two!"]);
+        // Going back down restores what was being typed
+        let (lines, _) = run(&mut editor, b"dr\x1b[A\x1b[A\x1b[B\x1b[Baft\r");
+        assert_eq!(lines, ["draft"]);
+    }
+
+    #[test]
+    fn history_limits() {
+        let mut editor = editor();
+        run(&mut editor, b"one\rtwo\rtwo\r\rthree\rfour\r");
+        // Repeats and empty lines are not recorded, the oldest entry makes room
+        assert_eq!(
+            editor.history().collect::<std::vec::Vec<_>>(),
+            ["four", "three", "two"]
+        );
+        let (lines, out) = run(&mut editor, b"\x1b[A\x1b[A\x1b[A\x1b[A\r");
+        assert_eq!(lines, ["two"]);
+        assert!(out.contains('\x07'));
+        let (_, out) = run(&mut editor, b"\x1b[B");
+        assert!(out.ends_with('\x07'));
+    }
+
+    #[test]
+    fn recalled_line_overwrites_a_longer_one() {
+        let mut editor = editor();
+        run(&mut editor, b"ab\r");
+        let (_, out) = run(&mut editor, b"abcdef\x1b[D\x1b[A");
+        assert_eq!(editor.line(), "ab");
+        assert!(out.ends_with("\x1b[5Dab\x1b[K"), "{:?}", out);
+    }
+
+    #[test]
+    fn unique_completion_adds_a_space() {
+        assert_eq!(lines(b"ma\t10\r"), ["magnetometer 10"]);
+        assert_eq!(lines(b"h\t\r"), ["help "]);
+    }
+
+    #[test]
+    fn completion_extends_to_the_common_prefix() {
+        let mut editor = editor();
+        run(&mut editor, b"a\t");
+        assert_eq!(editor.line(), "accel");
+        let (_, out) = run(&mut editor, b"\t");
+        assert_eq!(out, "\r\naccelerometer  accel\r\n> accel");
+        let (lines, _) = run(&mut editor, b"e\t\r");
+        assert_eq!(lines, ["accelerometer "]);
+    }
+
+    #[test]
+    fn names_with_several_words() {
+        let commands = ["accel", "accel odr", "accel scale", "mag read"];
+        let mut editor: LineEditor<'_, 16, 1, &str> = LineEditor::new("", &commands);
+        let mut out = String::new();
+        for byte in b"ac\t" {
+            editor.feed(*byte, &mut out);
+        }
+        assert_eq!(editor.line(), "accel");
+        for byte in b" s\t" {
+            editor.feed(*byte, &mut out);
+        }
+        assert_eq!(editor.line(), "accel scale ");
+        for byte in b"\x03m\t" {
+            editor.feed(*byte, &mut out);
+        }
+        assert_eq!(editor.line(), "mag read ");
+    }
+
+    #[test]
+    fn completion_without_candidates() {
+        let mut editor = editor();
+        let (_, out) = run(&mut editor, b"x\t");
+        assert_eq!(editor.line(), "x");
+        assert!(out.ends_with('\x07'));
+        // Arguments are not completed
+        let (_, out) = run(&mut editor, b"\x7fhelp a\t");
+        assert_eq!(editor.line(), "help a");
+        assert!(out.ends_with('\x07'));
+    }
+
+    #[test]
+    fn completion_does_not_overflow() {
+        let mut editor: LineEditor<'static, 8, 1> = LineEditor::new("", COMMANDS);
+        let mut out = String::new();
+        for byte in b"mag\t" {
+            editor.feed(*byte, &mut out);
+        }
+        assert_eq!(editor.line(), "magnetom");
+        assert!(out.contains('\x07'));
+    }
+}
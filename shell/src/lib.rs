@@ -0,0 +1,11 @@
+//! The serial console shared by the applications in this workspace.
+#![cfg_attr(not(test), no_std)]
+#![deny(unsafe_code)]
+
+pub mod command;
+pub mod console;
+pub mod editor;
+
+pub use command::{execute, Arg, Args, Command, Error, Value};
+pub use console::Console;
+pub use editor::LineEditor;